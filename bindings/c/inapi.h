@@ -37,10 +37,19 @@ extern char *geterr();
  */
 typedef void Host;
 
+/**
+ * @brief Create a new Host connected to localhost.
+ * @param path [Optional] Path to the data file for this host.
+ * @return A new Host struct, or null on error.
+ */
+extern Host *host_local(const char *path);
+
 /**
  * @brief Create a new Host connected to the endpoint specified in the
- *        data file. This function expects to find the following keys
- *        in the root namespace: "hostname", "api_port", "file_port".
+ *        data file. The optional "transport" key selects "agent"
+ *        (default), "ssh" or "local". For the agent transport, this
+ *        function expects to find the following keys in the root
+ *        namespace: "hostname", "api_port", "file_port".
  * @param path Path to the data file for this host.
  * @return A new Host struct, or null on error.
  *
@@ -85,6 +94,14 @@ extern Host *host_connect_payload(const char *api_endpoint, const char *file_end
  */
 extern void *host_data(Host *host);
 
+/**
+ * @brief Check whether the host supports a message type.
+ * @param host The host connection you wish to check.
+ * @param capability The message type, e.g. "command::exec_stream".
+ * @return Boolean on success (0 or 1) and -1 on error.
+ */
+extern int8_t host_supports(Host *host, const char *capability);
+
 /**
  * @brief Close the connection to your managed host.
  * @param host The host connection you wish to close.
@@ -171,15 +188,44 @@ extern int8_t get_value_type(void *value, const char *pointer);
  */
 typedef void Command;
 
+/**
+ * @brief Array of raw bytes
+ */
+typedef struct _ByteArray {
+    uint8_t *ptr; /**< Bytes */
+    size_t length; /**< Size of array */
+    size_t capacity; /**< Capacity of array */
+} ByteArray;
+
 /**
  * @brief Result attributes returned from the managed host.
  */
 typedef struct _CommandResult {
-    int32_t exit_code; /**< Exit code for the shell command's process */
-    char *stdout; /**< Process's standard output */
-    char *stderr; /**< Process's standard error */
+    int32_t exit_code; /**< Exit code for the shell command's process, or -1 if it was killed by a signal */
+    char *stdout; /**< Process's standard output, trimmed and with invalid UTF-8 replaced */
+    char *stderr; /**< Process's standard error, trimmed and with invalid UTF-8 replaced */
+    int32_t signal; /**< Signal that terminated the process, or 0 */
+    ByteArray stdout_raw; /**< Process's untrimmed standard output */
+    ByteArray stderr_raw; /**< Process's untrimmed standard error */
 } CommandResult;
 
+/**
+ * @brief Output stream that a chunk of streamed output was read from.
+ */
+enum CommandStream {
+    Stdout, /**< Process's standard output */
+    Stderr, /**< Process's standard error */
+};
+
+/**
+ * @brief Callback that receives chunks of streamed command output.
+ * @param stream The stream that the chunk was read from.
+ * @param chunk The raw output. This is not null terminated.
+ * @param len The length of the chunk in bytes.
+ * @param user_data The user data pointer passed to `command_exec_stream`.
+ */
+typedef void (*CommandStreamHandler)(enum CommandStream stream, const uint8_t *chunk, size_t len, void *user_data);
+
 /**
  * @brief Create a new Command to represent your shell command.
  * @param cmd_str The shell command.
@@ -207,6 +253,38 @@ typedef struct _CommandResult {
  */
 extern Command *command_new(const char *cmd_str);
 
+/**
+ * @brief Set the maximum number of seconds the command may run for.
+ * @param cmd The command object.
+ * @param timeout Seconds before the command's process group is killed, or 0 to wait indefinitely.
+ * @return Return code - zero on success, non-zero on error.
+ */
+extern uint8_t command_set_timeout(Command *cmd, uint64_t timeout);
+
+/**
+ * @brief Skip the command in `command_exec_guarded` if a path exists.
+ * @param cmd The command object.
+ * @param path The path to check for, or null to remove the guard.
+ * @return Return code - zero on success, non-zero on error.
+ */
+extern uint8_t command_set_creates(Command *cmd, const char *path);
+
+/**
+ * @brief Run the command in `command_exec_guarded` only if a check command succeeds.
+ * @param cmd The command object.
+ * @param check The check command, or null to remove the guard.
+ * @return Return code - zero on success, non-zero on error.
+ */
+extern uint8_t command_set_onlyif(Command *cmd, const char *check);
+
+/**
+ * @brief Run the command in `command_exec_guarded` only if a check command fails.
+ * @param cmd The command object.
+ * @param check The check command, or null to remove the guard.
+ * @return Return code - zero on success, non-zero on error.
+ */
+extern uint8_t command_set_unless(Command *cmd, const char *check);
+
 /**
  * @brief Send request to the Agent to run your shell command.
  * @param cmd The command object.
@@ -215,6 +293,24 @@ extern Command *command_new(const char *cmd_str);
  */
 extern CommandResult *command_exec(Command *cmd, Host *host);
 
+/**
+ * @brief Run your shell command, passing its output to a callback as it is produced.
+ * @param cmd The command object.
+ * @param host The host object you wish to run the command on.
+ * @param handler The callback that receives each chunk of output.
+ * @param user_data [Optional] A pointer that is passed through to the callback.
+ * @return A struct containing the exit code. The stdout and stderr members are empty.
+ */
+extern CommandResult *command_exec_stream(Command *cmd, Host *host, CommandStreamHandler handler, void *user_data);
+
+/**
+ * @brief Run your shell command if its guards allow it.
+ * @param cmd The command object.
+ * @param host The host object you wish to run the command on.
+ * @return A struct containing the execution results, or null if the command was not required to run.
+ */
+extern CommandResult *command_exec_guarded(Command *cmd, Host *host);
+
 /**
  * @brief Free a Command pointer's memory.
  * @param cmd The Command pointer.
@@ -65,6 +65,7 @@ PHP_METHOD(Command, exec) {
 
     array_init(return_value);
     add_assoc_long(return_value, "exit_code", result->exit_code);
+    add_assoc_long(return_value, "signal", result->signal);
     add_assoc_string(return_value, "stdout", result->stdout);
     add_assoc_string(return_value, "stderr", result->stderr);
 
@@ -0,0 +1,531 @@
+// Copyright 2015-2017 Intecture Developers. See the COPYRIGHT file at the
+// top-level directory of this distribution and at
+// https://intecture.io/COPYRIGHT.
+//
+// Licensed under the Mozilla Public License 2.0 <LICENSE or
+// https://www.tldrlegal.com/l/mpl-2.0>. This file may not be copied,
+// modified, or distributed except according to those terms.
+
+//! In-process Agent that manages the local machine.
+//!
+//! `LocalAgent` speaks the same protocol as the Intecture Agent, but
+//! runs on a thread inside your program and performs each request
+//! using the local target. This lets code that is written for remote
+//! hosts (e.g. payloads) be tested without installing an Agent, and
+//! serves as a reference implementation of the Agent's API.
+//!
+//! File uploads are served on a second endpoint by a `zfilexfer`
+//! server, as they are by the Intecture Agent.
+//!
+//! The API is served without CURVE authentication, so the agent
+//! should only be bound to the loopback interface or an `inproc://`
+//! endpoint.
+
+use command::{CommandResult, CommandStream, CommandTarget};
+use czmq::{ZMsg, ZPoller, ZSock, SocketType};
+use directory::DirectoryTarget;
+use error::{Error, Result};
+use file::{FileOwner, FileTarget};
+use host::Host;
+use host::protocol::{CAPABILITIES, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION};
+use host::telemetry::Telemetry;
+use package::PackageTarget;
+use serde_json;
+use service::ServiceTarget;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+use target::Target;
+use zdaemon::Endpoint;
+use zfilexfer;
+
+/// Time in milliseconds between checks for agent shutdown.
+const POLL_INTERVAL: i32 = 100;
+/// Number of file chunks that can be in flight at once.
+const UPLOAD_SLOTS: u32 = 10;
+
+/// Agent that serves requests against the local machine.
+///
+/// The agent runs until it is dropped.
+///
+///# Examples
+///
+/// ```no_run
+/// # use inapi::{Command, LocalAgent};
+///let agent = LocalAgent::new().unwrap();
+///let mut host = agent.connect().unwrap();
+///
+///let result = Command::new("whoami").exec(&mut host).unwrap();
+///println!("{}", result.stdout);
+/// ```
+pub struct LocalAgent {
+    /// API endpoint that the agent is bound to
+    endpoint: String,
+    /// File transfer endpoint that the agent is bound to
+    file_endpoint: String,
+    /// Cleared to signal the agent's thread to stop
+    running: Arc<AtomicBool>,
+    /// Handle to the agent's thread
+    handle: Option<JoinHandle<()>>,
+}
+
+impl LocalAgent {
+    /// Start a new agent on random loopback ports.
+    pub fn new() -> Result<LocalAgent> {
+        let sock = ZSock::new(SocketType::ROUTER);
+        let port = try!(sock.bind("tcp://127.0.0.1:*"));
+        let file_sock = ZSock::new(SocketType::ROUTER);
+        let file_port = try!(file_sock.bind("tcp://127.0.0.1:*"));
+        Self::start(sock, file_sock, format!("tcp://127.0.0.1:{}", port), format!("tcp://127.0.0.1:{}", file_port))
+    }
+
+    /// Start a new agent bound to `endpoint`, serving file transfers
+    /// on `file_endpoint`.
+    pub fn bind(endpoint: &str, file_endpoint: &str) -> Result<LocalAgent> {
+        let sock = ZSock::new(SocketType::ROUTER);
+        try!(sock.bind(endpoint));
+        let file_sock = ZSock::new(SocketType::ROUTER);
+        try!(file_sock.bind(file_endpoint));
+        Self::start(sock, file_sock, endpoint.into(), file_endpoint.into())
+    }
+
+    /// Get the API endpoint that the agent is bound to.
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    /// Get the file transfer endpoint that the agent is bound to.
+    pub fn file_endpoint(&self) -> &str {
+        &self.file_endpoint
+    }
+
+    /// Create a new Host connected to this agent. Note that this
+    /// function does not load any user data.
+    pub fn connect(&self) -> Result<Host> {
+        Host::connect_insecure(&self.endpoint, Some(&self.file_endpoint))
+    }
+
+    fn start(mut sock: ZSock, mut file_sock: ZSock, endpoint: String, file_endpoint: String) -> Result<LocalAgent> {
+        let running = Arc::new(AtomicBool::new(true));
+        let thread_running = running.clone();
+        let (tx, rx) = mpsc::channel();
+
+        let handle = thread::spawn(move || {
+            // Host is not Send, so it has to be created on the
+            // agent's thread.
+            let path: Option<String> = None;
+            let setup = Host::local(path).and_then(|host| {
+                let mut poller = try!(ZPoller::new());
+                try!(poller.add(&mut sock));
+                try!(poller.add(&mut file_sock));
+                let file_server = try!(zfilexfer::Server::new(file_sock, UPLOAD_SLOTS));
+                Ok((host, poller, file_server))
+            });
+
+            let (mut host, mut poller, mut file_server) = match setup {
+                Ok(s) => {
+                    let _ = tx.send(Ok(()));
+                    s
+                },
+                Err(e) => {
+                    let _ = tx.send(Err(e));
+                    return;
+                },
+            };
+
+            while thread_running.load(Ordering::SeqCst) {
+                // No socket means that the poll interval elapsed
+                // without a request.
+                let ready: Option<ZSock> = poller.wait(Some(POLL_INTERVAL));
+                if let Some(mut s) = ready {
+                    if s == sock {
+                        if let Ok(msg) = ZMsg::recv(&mut s) {
+                            serve(&mut host, &mut s, &msg);
+                        }
+                    } else {
+                        // Failed transfers are reported to the client
+                        // by the server.
+                        let _ = file_server.recv(&mut s);
+                    }
+                }
+            }
+        });
+
+        try!(try!(rx.recv().or(Err(Error::Generic("Agent thread exited before starting".into())))));
+
+        Ok(LocalAgent {
+            endpoint: endpoint,
+            file_endpoint: file_endpoint,
+            running: running,
+            handle: Some(handle),
+        })
+    }
+}
+
+impl Drop for LocalAgent {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Handle a single request from a ROUTER socket, replying with
+/// either "Ok" and the results, or "Err" and an error message.
+fn serve(host: &mut Host, sock: &mut ZSock, msg: &ZMsg) {
+    // Requests arrive as [identity, "", endpoint, args...]
+    let identity = match msg.popstr() {
+        Some(Ok(s)) => s.into_bytes(),
+        Some(Err(b)) => b,
+        None => return,
+    };
+
+    match msg.popstr() {
+        Some(Ok(ref d)) if d == "" => (),
+        _ => return,
+    }
+
+    let _ = match handle(host, sock, &identity, msg) {
+        Ok(frames) => reply(sock, &identity, "Ok", &frames),
+        Err(e) => reply(sock, &identity, "Err", &[e.to_string().into_bytes()]),
+    };
+}
+
+fn handle(host: &mut Host, sock: &mut ZSock, identity: &[u8], msg: &ZMsg) -> Result<Vec<Vec<u8>>> {
+    let endpoint = try!(pop_arg(msg));
+
+    match endpoint.as_ref() {
+        "hello" => {
+            let version: u32 = try!(try!(pop_arg(msg)).parse());
+            if version < MIN_PROTOCOL_VERSION {
+                return Err(Error::Generic(format!("Unsupported protocol version {}", version)));
+            }
+
+            let mut frames = vec![version.min(PROTOCOL_VERSION).to_string().into_bytes()];
+            frames.extend(CAPABILITIES.iter().map(|c| c.as_bytes().to_vec()));
+            Ok(frames)
+        },
+        "ping" => Ok(Vec::new()),
+        "batch" => {
+            let stop_on_error = try!(pop_arg(msg)) == "1";
+            let count: usize = try!(try!(pop_arg(msg)).parse());
+            let mut frames = Vec::new();
+
+            for _ in 0..count {
+                let len: usize = try!(try!(pop_arg(msg)).parse());
+                let op = ZMsg::new();
+                for _ in 0..len {
+                    match msg.popstr() {
+                        Some(Ok(s)) => try!(op.addstr(&s)),
+                        Some(Err(b)) => try!(op.addbytes(&b)),
+                        None => return Err(Error::Generic("Batch operation is missing frames".into())),
+                    }
+                }
+
+                // Operations that need their own conversation with
+                // the client can't be batched.
+                let result = match op.popstr() {
+                    Some(Ok(ref e)) if e == "batch" || e == "hello" || e == "command::exec_stream" => {
+                        Err(Error::Generic(format!("Endpoint `{}` cannot be batched", e)))
+                    },
+                    Some(Ok(e)) => {
+                        try!(op.pushstr(&e));
+                        handle(host, sock, identity, &op)
+                    },
+                    _ => Err(Error::Generic("Missing or invalid argument in request".into())),
+                };
+
+                match result {
+                    Ok(f) => {
+                        frames.push(b"Ok".to_vec());
+                        frames.push(f.len().to_string().into_bytes());
+                        frames.extend(f);
+                    },
+                    Err(e) => {
+                        frames.push(b"Err".to_vec());
+                        frames.push(b"1".to_vec());
+                        frames.push(e.to_string().into_bytes());
+
+                        if stop_on_error {
+                            break;
+                        }
+                    },
+                }
+            }
+
+            Ok(frames)
+        },
+        "command::exec" => {
+            let cmd = try!(pop_arg(msg));
+            let timeout = try!(pop_timeout(msg));
+            Ok(command_frames(try!(Target::exec(host, &cmd, timeout))))
+        },
+        "command::exec_stream" => {
+            let cmd = try!(pop_arg(msg));
+            let timeout = try!(pop_timeout(msg));
+
+            let result = try!(Target::exec_stream(host, &cmd, timeout, &mut |stream, chunk| {
+                let stream = match stream {
+                    CommandStream::Stdout => "stdout",
+                    CommandStream::Stderr => "stderr",
+                };
+
+                // If the client has gone away there's nobody to
+                // tell, so let the process run to completion.
+                let _ = reply(sock, identity, "Stream", &[stream.as_bytes().to_vec(), chunk.to_vec()]);
+            }));
+
+            Ok(vec![
+                result.exit_code.to_string().into_bytes(),
+                result.signal.unwrap_or(0).to_string().into_bytes(),
+            ])
+        },
+        "directory::is_directory" => bool_frames(Target::directory_is_directory(host, try!(pop_arg(msg)))),
+        "directory::exists" => bool_frames(Target::directory_exists(host, try!(pop_arg(msg)))),
+        "directory::create" => {
+            let path = try!(pop_arg(msg));
+            let recursive = try!(pop_arg(msg)) == "1";
+            try!(Target::directory_create(host, path, recursive));
+            Ok(Vec::new())
+        },
+        "directory::delete" => {
+            let path = try!(pop_arg(msg));
+            let recursive = try!(pop_arg(msg)) == "1";
+            try!(Target::directory_delete(host, path, recursive));
+            Ok(Vec::new())
+        },
+        "directory::mv" => {
+            let path = try!(pop_arg(msg));
+            try!(Target::directory_mv(host, path, try!(pop_arg(msg))));
+            Ok(Vec::new())
+        },
+        "directory::get_owner" => Ok(owner_frames(try!(Target::directory_get_owner(host, try!(pop_arg(msg)))))),
+        "directory::set_owner" => {
+            let path = try!(pop_arg(msg));
+            let user = try!(pop_arg(msg));
+            try!(Target::directory_set_owner(host, path, &user, &try!(pop_arg(msg))));
+            Ok(Vec::new())
+        },
+        "directory::get_mode" => Ok(vec![try!(Target::directory_get_mode(host, try!(pop_arg(msg)))).to_string().into_bytes()]),
+        "directory::set_mode" => {
+            let path = try!(pop_arg(msg));
+            try!(Target::directory_set_mode(host, path, try!(try!(pop_arg(msg)).parse())));
+            Ok(Vec::new())
+        },
+        "file::is_file" => bool_frames(Target::file_is_file(host, try!(pop_arg(msg)))),
+        "file::exists" => bool_frames(Target::file_exists(host, try!(pop_arg(msg)))),
+        "file::delete" => {
+            try!(Target::file_delete(host, try!(pop_arg(msg))));
+            Ok(Vec::new())
+        },
+        "file::mv" => {
+            let path = try!(pop_arg(msg));
+            try!(Target::file_mv(host, path, try!(pop_arg(msg))));
+            Ok(Vec::new())
+        },
+        "file::copy" => {
+            let path = try!(pop_arg(msg));
+            try!(Target::file_copy(host, path, try!(pop_arg(msg))));
+            Ok(Vec::new())
+        },
+        "file::get_owner" => Ok(owner_frames(try!(Target::file_get_owner(host, try!(pop_arg(msg)))))),
+        "file::set_owner" => {
+            let path = try!(pop_arg(msg));
+            let user = try!(pop_arg(msg));
+            try!(Target::file_set_owner(host, path, &user, &try!(pop_arg(msg))));
+            Ok(Vec::new())
+        },
+        "file::get_mode" => Ok(vec![try!(Target::file_get_mode(host, try!(pop_arg(msg)))).to_string().into_bytes()]),
+        "file::set_mode" => {
+            let path = try!(pop_arg(msg));
+            try!(Target::file_set_mode(host, path, try!(try!(pop_arg(msg)).parse())));
+            Ok(Vec::new())
+        },
+        "package::default_provider" => Ok(vec![try!(Target::default_provider(host)).to_string().into_bytes()]),
+        "service::action" => {
+            let name = try!(pop_arg(msg));
+            match try!(Target::service_action(host, &name, &try!(pop_arg(msg)))) {
+                Some(result) => Ok(command_frames(result)),
+                None => Ok(Vec::new()),
+            }
+        },
+        "telemetry" => {
+            let telemetry = try!(Telemetry::init(host));
+            Ok(vec![try!(serde_json::to_string(&telemetry)).into_bytes()])
+        },
+        _ => Err(Error::Generic(format!("Unknown endpoint `{}`", endpoint))),
+    }
+}
+
+fn reply(sock: &mut ZSock, identity: &[u8], status: &str, frames: &[Vec<u8>]) -> Result<()> {
+    let msg = ZMsg::new();
+    try!(msg.addbytes(identity));
+    try!(msg.addstr(""));
+    try!(msg.addstr(status));
+    for frame in frames {
+        try!(msg.addbytes(frame));
+    }
+    try!(msg.send(sock));
+    Ok(())
+}
+
+fn pop_arg(msg: &ZMsg) -> Result<String> {
+    match msg.popstr() {
+        Some(Ok(s)) => Ok(s),
+        _ => Err(Error::Generic("Missing or invalid argument in request".into())),
+    }
+}
+
+fn pop_timeout(msg: &ZMsg) -> Result<Option<u64>> {
+    if msg.size() == 0 {
+        Ok(None)
+    } else {
+        Ok(Some(try!(try!(pop_arg(msg)).parse())))
+    }
+}
+
+fn bool_frames(result: Result<bool>) -> Result<Vec<Vec<u8>>> {
+    Ok(vec![if try!(result) { b"1".to_vec() } else { b"0".to_vec() }])
+}
+
+fn owner_frames(owner: FileOwner) -> Vec<Vec<u8>> {
+    vec![
+        owner.user_name.into_bytes(),
+        owner.user_uid.to_string().into_bytes(),
+        owner.group_name.into_bytes(),
+        owner.group_gid.to_string().into_bytes(),
+    ]
+}
+
+fn command_frames(result: CommandResult) -> Vec<Vec<u8>> {
+    vec![
+        result.exit_code.to_string().into_bytes(),
+        result.stdout_raw,
+        result.stderr_raw,
+        result.signal.unwrap_or(0).to_string().into_bytes(),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use command::{Command, CommandStream};
+    use czmq::ZSys;
+    use directory::{Directory, DirectoryOpts};
+    use file::File;
+    use host::Transport;
+    use error::Error;
+    use std::fs;
+    use std::io::{Read, Write};
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_command() {
+        ZSys::init();
+
+        let agent = LocalAgent::new().unwrap();
+        let mut host = agent.connect().unwrap();
+        assert_eq!(host.transport(), Transport::Agent);
+        assert_eq!(host.protocol_version(), PROTOCOL_VERSION);
+        assert!(host.supports("command::exec_stream"));
+        assert!(host.data().pointer("/_telemetry/os/platform").is_some());
+
+        let result = Command::new("echo moo; echo cow >&2; exit 2").exec(&mut host).unwrap();
+        assert_eq!(result.exit_code, 2);
+        assert_eq!(result.stdout, "moo");
+        assert_eq!(result.stderr, "cow");
+
+        let mut output = Vec::new();
+        let result = Command::new("echo moo; echo cow >&2").exec_stream(&mut host, |stream, chunk| {
+            output.push((stream, chunk.to_vec()));
+        }).unwrap();
+        assert_eq!(result.exit_code, 0);
+        assert!(output.contains(&(CommandStream::Stdout, b"moo\n".to_vec())));
+        assert!(output.contains(&(CommandStream::Stderr, b"cow\n".to_vec())));
+    }
+
+    #[test]
+    fn test_command_timeout() {
+        ZSys::init();
+
+        let agent = LocalAgent::new().unwrap();
+        let mut host = agent.connect().unwrap();
+
+        // Keep the agent busy so that the next request times out
+        let endpoint = agent.endpoint().to_string();
+        let (tx, rx) = mpsc::channel();
+        let busy = thread::spawn(move || {
+            let mut busy_host = Host::connect_insecure(&endpoint, None).unwrap();
+            tx.send(()).unwrap();
+            Command::new("sleep 5").exec(&mut busy_host).unwrap();
+        });
+        rx.recv().unwrap();
+        thread::sleep(Duration::from_millis(200));
+
+        let mut cmd = Command::new("true");
+        cmd.set_timeout(Some(1));
+        match cmd.exec(&mut host) {
+            Err(Error::Timeout(_)) => (),
+            _ => panic!("Expected command to time out"),
+        }
+
+        // The API socket must be usable again after a timeout
+        let result = Command::new("echo moo").exec(&mut host).unwrap();
+        assert_eq!(result.stdout, "moo");
+
+        busy.join().unwrap();
+    }
+
+    #[test]
+    fn test_filesystem() {
+        ZSys::init();
+
+        let agent = LocalAgent::new().unwrap();
+        let mut host = agent.connect().unwrap();
+        let tempdir = TempDir::new("local_agent_fs").unwrap();
+
+        let dir_path = tempdir.path().join("moo/cow");
+        let dir = Directory::new(&mut host, &dir_path).unwrap();
+        assert!(!dir.exists(&mut host).unwrap());
+        assert!(dir.create(&mut host, None).is_err());
+        dir.create(&mut host, Some(&[DirectoryOpts::DoRecursive])).unwrap();
+        assert!(dir_path.is_dir());
+
+        let file_path = dir_path.join("file");
+        fs::File::create(&file_path).unwrap();
+        let mut file = File::new(&mut host, &file_path).unwrap();
+        file.set_mode(&mut host, 600).unwrap();
+        assert_eq!(file.get_mode(&mut host).unwrap(), 600);
+
+        file.mv(&mut host, dir_path.join("moved")).unwrap();
+        assert!(dir_path.join("moved").is_file());
+        assert!(File::new(&mut host, &dir_path).is_err());
+    }
+
+    #[test]
+    fn test_upload() {
+        ZSys::init();
+
+        let agent = LocalAgent::new().unwrap();
+        let mut host = agent.connect().unwrap();
+        let tempdir = TempDir::new("local_agent_upload").unwrap();
+
+        let local_path = tempdir.path().join("local");
+        fs::File::create(&local_path).unwrap().write_all(b"moo cow").unwrap();
+
+        let remote_path = tempdir.path().join("remote");
+        fs::File::create(&remote_path).unwrap();
+        let file = File::new(&mut host, &remote_path).unwrap();
+        file.upload(&mut host, &local_path, None).unwrap();
+
+        let mut content = String::new();
+        fs::File::open(&remote_path).unwrap().read_to_string(&mut content).unwrap();
+        assert_eq!(content, "moo cow");
+    }
+}
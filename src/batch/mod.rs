@@ -0,0 +1,555 @@
+// Copyright 2015-2017 Intecture Developers. See the COPYRIGHT file at the
+// top-level directory of this distribution and at
+// https://intecture.io/COPYRIGHT.
+//
+// Licensed under the Mozilla Public License 2.0 <LICENSE or
+// https://www.tldrlegal.com/l/mpl-2.0>. This file may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Batch primitive.
+//!
+//! A batch is sent to the Agent as a single message:
+//!
+//! `["batch", "<stop on error>", "<count>", ("<frames>", frame...)...]`
+//!
+//! where each operation is the same frames that would otherwise be
+//! sent as its own request. The Agent runs each operation in order
+//! and replies with its status and frames:
+//!
+//! `["Ok", ("Ok"|"Err", "<frames>", frame...)...]`
+//!
+//! If the batch stops on an error, the reply ends with the result of
+//! the failed operation.
+
+use command::{CommandResult, CommandTarget};
+#[cfg(feature = "remote-run")]
+use czmq::ZMsg;
+use directory::DirectoryTarget;
+use error::{Error, Result};
+use file::{FileOwner, FileTarget};
+#[cfg(feature = "remote-run")]
+use host::{HostSendRecv, Transport};
+use host::Host;
+use service::ServiceTarget;
+use std::path::{Path, PathBuf};
+#[cfg(feature = "remote-run")]
+use target::reply;
+use target::Target;
+
+/// Queue of operations that are run on a host in a single round
+/// trip.
+///
+/// Hosts that are managed by an Agent with batch support receive the
+/// whole batch in one message. For other hosts, each operation is
+/// run in turn, so a batch can be used with any transport.
+///
+///# Examples
+///
+/// ```no_run
+/// # use inapi::{Batch, Host};
+#[cfg_attr(not(feature = "remote-run"), doc = "# let path: Option<String> = None;")]
+#[cfg_attr(not(feature = "remote-run"), doc = "# let mut host = Host::local(path).unwrap();")]
+#[cfg_attr(feature = "remote-run", doc = "# let mut host = Host::connect(\"hosts/myhost.json\").unwrap();")]
+///let mut batch = Batch::new();
+///batch.set_stop_on_error(true);
+///batch.directory_create("/etc/myapp", true);
+///batch.file_copy("/tmp/myapp.conf", "/etc/myapp/myapp.conf");
+///batch.file_set_mode("/etc/myapp/myapp.conf", 640);
+///batch.exec("systemctl reload myapp");
+///
+///for result in batch.run(&mut host).unwrap() {
+///    if let Err(e) = result {
+///        println!("Operation failed: {}", e);
+///    }
+///}
+/// ```
+pub struct Batch {
+    /// Operations in the order they will be run
+    ops: Vec<Op>,
+    /// Skip the remaining operations once one fails
+    stop_on_error: bool,
+}
+
+/// Output of a successful batch operation.
+#[derive(Debug)]
+pub enum BatchOutput {
+    /// The operation has no output
+    Done,
+    /// Result of a check, e.g. `file_exists`
+    Bool(bool),
+    /// Owner of a file or directory
+    Owner(FileOwner),
+    /// Mode of a file or directory
+    Mode(u16),
+    /// Result of `exec`
+    Command(CommandResult),
+    /// Result of `service_action`, or `None` if the service was
+    /// already in the requested state
+    Service(Option<CommandResult>),
+}
+
+enum Op {
+    Exec(String, Option<u64>),
+    DirectoryExists(PathBuf),
+    DirectoryCreate(PathBuf, bool),
+    DirectoryDelete(PathBuf, bool),
+    DirectoryMv(PathBuf, PathBuf),
+    DirectoryGetOwner(PathBuf),
+    DirectorySetOwner(PathBuf, String, String),
+    DirectoryGetMode(PathBuf),
+    DirectorySetMode(PathBuf, u16),
+    FileExists(PathBuf),
+    FileDelete(PathBuf),
+    FileMv(PathBuf, PathBuf),
+    FileCopy(PathBuf, PathBuf),
+    FileGetOwner(PathBuf),
+    FileSetOwner(PathBuf, String, String),
+    FileGetMode(PathBuf),
+    FileSetMode(PathBuf, u16),
+    ServiceAction(String, String),
+}
+
+impl Batch {
+    /// Create a new, empty Batch.
+    pub fn new() -> Batch {
+        Batch {
+            ops: Vec::new(),
+            stop_on_error: false,
+        }
+    }
+
+    /// Skip the remaining operations once one fails. Skipped
+    /// operations return an error.
+    pub fn set_stop_on_error(&mut self, stop: bool) {
+        self.stop_on_error = stop;
+    }
+
+    /// Get the number of queued operations.
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    /// Check whether any operations are queued.
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// Queue a shell command.
+    pub fn exec(&mut self, cmd: &str) {
+        self.ops.push(Op::Exec(cmd.into(), None));
+    }
+
+    /// Queue a shell command that is killed if it runs for longer
+    /// than `timeout` seconds.
+    pub fn exec_timeout(&mut self, cmd: &str, timeout: u64) {
+        self.ops.push(Op::Exec(cmd.into(), Some(timeout)));
+    }
+
+    /// Queue a check for whether a directory exists.
+    pub fn directory_exists<P: AsRef<Path>>(&mut self, path: P) {
+        self.ops.push(Op::DirectoryExists(path.as_ref().into()));
+    }
+
+    /// Queue the creation of a directory.
+    pub fn directory_create<P: AsRef<Path>>(&mut self, path: P, recursive: bool) {
+        self.ops.push(Op::DirectoryCreate(path.as_ref().into(), recursive));
+    }
+
+    /// Queue the deletion of a directory.
+    pub fn directory_delete<P: AsRef<Path>>(&mut self, path: P, recursive: bool) {
+        self.ops.push(Op::DirectoryDelete(path.as_ref().into(), recursive));
+    }
+
+    /// Queue the move of a directory.
+    pub fn directory_mv<P: AsRef<Path>>(&mut self, path: P, new_path: P) {
+        self.ops.push(Op::DirectoryMv(path.as_ref().into(), new_path.as_ref().into()));
+    }
+
+    /// Queue a lookup of a directory's owner.
+    pub fn directory_get_owner<P: AsRef<Path>>(&mut self, path: P) {
+        self.ops.push(Op::DirectoryGetOwner(path.as_ref().into()));
+    }
+
+    /// Queue a change of a directory's owner.
+    pub fn directory_set_owner<P: AsRef<Path>>(&mut self, path: P, user: &str, group: &str) {
+        self.ops.push(Op::DirectorySetOwner(path.as_ref().into(), user.into(), group.into()));
+    }
+
+    /// Queue a lookup of a directory's mode.
+    pub fn directory_get_mode<P: AsRef<Path>>(&mut self, path: P) {
+        self.ops.push(Op::DirectoryGetMode(path.as_ref().into()));
+    }
+
+    /// Queue a change of a directory's mode.
+    pub fn directory_set_mode<P: AsRef<Path>>(&mut self, path: P, mode: u16) {
+        self.ops.push(Op::DirectorySetMode(path.as_ref().into(), mode));
+    }
+
+    /// Queue a check for whether a file exists.
+    pub fn file_exists<P: AsRef<Path>>(&mut self, path: P) {
+        self.ops.push(Op::FileExists(path.as_ref().into()));
+    }
+
+    /// Queue the deletion of a file.
+    pub fn file_delete<P: AsRef<Path>>(&mut self, path: P) {
+        self.ops.push(Op::FileDelete(path.as_ref().into()));
+    }
+
+    /// Queue the move of a file.
+    pub fn file_mv<P: AsRef<Path>>(&mut self, path: P, new_path: P) {
+        self.ops.push(Op::FileMv(path.as_ref().into(), new_path.as_ref().into()));
+    }
+
+    /// Queue a copy of a file.
+    pub fn file_copy<P: AsRef<Path>>(&mut self, path: P, new_path: P) {
+        self.ops.push(Op::FileCopy(path.as_ref().into(), new_path.as_ref().into()));
+    }
+
+    /// Queue a lookup of a file's owner.
+    pub fn file_get_owner<P: AsRef<Path>>(&mut self, path: P) {
+        self.ops.push(Op::FileGetOwner(path.as_ref().into()));
+    }
+
+    /// Queue a change of a file's owner.
+    pub fn file_set_owner<P: AsRef<Path>>(&mut self, path: P, user: &str, group: &str) {
+        self.ops.push(Op::FileSetOwner(path.as_ref().into(), user.into(), group.into()));
+    }
+
+    /// Queue a lookup of a file's mode.
+    pub fn file_get_mode<P: AsRef<Path>>(&mut self, path: P) {
+        self.ops.push(Op::FileGetMode(path.as_ref().into()));
+    }
+
+    /// Queue a change of a file's mode.
+    pub fn file_set_mode<P: AsRef<Path>>(&mut self, path: P, mode: u16) {
+        self.ops.push(Op::FileSetMode(path.as_ref().into(), mode));
+    }
+
+    /// Queue a service action, e.g. "start".
+    pub fn service_action(&mut self, name: &str, action: &str) {
+        self.ops.push(Op::ServiceAction(name.into(), action.into()));
+    }
+
+    /// Run the queued operations on the host, in order.
+    ///
+    /// The outer `Result` is an error if the batch couldn't be run
+    /// at all. Otherwise there is one result per operation, in the
+    /// order they were queued.
+    pub fn run(&self, host: &mut Host) -> Result<Vec<Result<BatchOutput>>> {
+        if let Some(results) = try!(self.run_batched(host)) {
+            return Ok(results);
+        }
+
+        let mut results = Vec::new();
+        for op in &self.ops {
+            if self.stop_on_error && results.iter().any(|r: &Result<BatchOutput>| r.is_err()) {
+                results.push(Err(skipped()));
+            } else {
+                results.push(op.run(host));
+            }
+        }
+
+        Ok(results)
+    }
+
+    #[cfg(not(feature = "remote-run"))]
+    fn run_batched(&self, _: &mut Host) -> Result<Option<Vec<Result<BatchOutput>>>> {
+        Ok(None)
+    }
+
+    /// Send the batch as a single request, if the host's Agent
+    /// supports it.
+    #[cfg(feature = "remote-run")]
+    fn run_batched(&self, host: &mut Host) -> Result<Option<Vec<Result<BatchOutput>>>> {
+        if host.transport() != Transport::Agent || !host.supports("batch") {
+            return Ok(None);
+        }
+
+        let msg = ZMsg::new();
+        try!(msg.addstr("batch"));
+        try!(msg.addstr(if self.stop_on_error { "1" } else { "0" }));
+        try!(msg.addstr(&self.ops.len().to_string()));
+        for op in &self.ops {
+            let frames = try!(op.frames());
+            try!(msg.addstr(&frames.len().to_string()));
+            for frame in frames {
+                try!(msg.addstr(&frame));
+            }
+        }
+        try!(host.send(msg));
+
+        let timeout = self.timeout(host.agent_options().timeout);
+        let reply = try!(host.recv_timeout(0, None, timeout));
+        let mut results = Vec::new();
+
+        for op in &self.ops {
+            let status = match reply.popstr() {
+                Some(Ok(s)) => s,
+                Some(Err(_)) => return Err(Error::HostResponse),
+                // The Agent stopped after an error
+                None => {
+                    results.push(Err(skipped()));
+                    continue;
+                },
+            };
+
+            let count: usize = try!(try!(try!(reply.popstr().ok_or(Error::HostResponse)).or(Err(Error::HostResponse))).parse().or(Err(Error::HostResponse)));
+            let frames = ZMsg::new();
+            for _ in 0..count {
+                match try!(reply.popstr().ok_or(Error::HostResponse)) {
+                    Ok(s) => try!(frames.addstr(&s)),
+                    Err(b) => try!(frames.addbytes(&b)),
+                }
+            }
+
+            results.push(match status.as_ref() {
+                "Ok" => op.decode(&frames),
+                "Err" => Err(Error::Agent(try!(try!(frames.popstr().ok_or(Error::HostResponse)).or(Err(Error::HostResponse))))),
+                _ => return Err(Error::HostResponse),
+            });
+        }
+
+        Ok(Some(results))
+    }
+
+    /// Seconds to wait for the Agent to run the whole batch. Each
+    /// operation gets as long as it would if it were run on its own:
+    /// the command's timeout, or else the default timeout.
+    #[cfg(feature = "remote-run")]
+    fn timeout(&self, default: u64) -> u64 {
+        if self.ops.is_empty() {
+            return default;
+        }
+
+        self.ops.iter().fold(0, |total: u64, op| match *op {
+            Op::Exec(_, Some(t)) => total.saturating_add(t),
+            _ => total.saturating_add(default),
+        })
+    }
+}
+
+fn skipped() -> Error {
+    Error::Generic("Skipped because an earlier operation in the batch failed".into())
+}
+
+impl Op {
+    /// Run the operation as its own request.
+    fn run(&self, host: &mut Host) -> Result<BatchOutput> {
+        match *self {
+            Op::Exec(ref cmd, timeout) => Ok(BatchOutput::Command(try!(Target::exec(host, cmd, timeout)))),
+            Op::DirectoryExists(ref p) => Ok(BatchOutput::Bool(try!(Target::directory_exists(host, p)))),
+            Op::DirectoryCreate(ref p, r) => Target::directory_create(host, p, r).map(|_| BatchOutput::Done),
+            Op::DirectoryDelete(ref p, r) => Target::directory_delete(host, p, r).map(|_| BatchOutput::Done),
+            Op::DirectoryMv(ref p, ref n) => Target::directory_mv(host, p, n).map(|_| BatchOutput::Done),
+            Op::DirectoryGetOwner(ref p) => Ok(BatchOutput::Owner(try!(Target::directory_get_owner(host, p)))),
+            Op::DirectorySetOwner(ref p, ref u, ref g) => Target::directory_set_owner(host, p, u, g).map(|_| BatchOutput::Done),
+            Op::DirectoryGetMode(ref p) => Ok(BatchOutput::Mode(try!(Target::directory_get_mode(host, p)))),
+            Op::DirectorySetMode(ref p, m) => Target::directory_set_mode(host, p, m).map(|_| BatchOutput::Done),
+            Op::FileExists(ref p) => Ok(BatchOutput::Bool(try!(Target::file_exists(host, p)))),
+            Op::FileDelete(ref p) => Target::file_delete(host, p).map(|_| BatchOutput::Done),
+            Op::FileMv(ref p, ref n) => Target::file_mv(host, p, n).map(|_| BatchOutput::Done),
+            Op::FileCopy(ref p, ref n) => Target::file_copy(host, p, n).map(|_| BatchOutput::Done),
+            Op::FileGetOwner(ref p) => Ok(BatchOutput::Owner(try!(Target::file_get_owner(host, p)))),
+            Op::FileSetOwner(ref p, ref u, ref g) => Target::file_set_owner(host, p, u, g).map(|_| BatchOutput::Done),
+            Op::FileGetMode(ref p) => Ok(BatchOutput::Mode(try!(Target::file_get_mode(host, p)))),
+            Op::FileSetMode(ref p, m) => Target::file_set_mode(host, p, m).map(|_| BatchOutput::Done),
+            Op::ServiceAction(ref n, ref a) => Ok(BatchOutput::Service(try!(Target::service_action(host, n, a)))),
+        }
+    }
+
+    /// Get the request frames for the operation.
+    #[cfg(feature = "remote-run")]
+    fn frames(&self) -> Result<Vec<String>> {
+        let frames = match *self {
+            Op::Exec(ref cmd, timeout) => {
+                let mut f = vec!["command::exec".into(), cmd.clone()];
+                if let Some(t) = timeout {
+                    f.push(t.to_string());
+                }
+                f
+            },
+            Op::DirectoryExists(ref p) => vec!["directory::exists".into(), try!(path_str(p))],
+            Op::DirectoryCreate(ref p, r) => vec!["directory::create".into(), try!(path_str(p)), bool_str(r)],
+            Op::DirectoryDelete(ref p, r) => vec!["directory::delete".into(), try!(path_str(p)), bool_str(r)],
+            Op::DirectoryMv(ref p, ref n) => vec!["directory::mv".into(), try!(path_str(p)), try!(path_str(n))],
+            Op::DirectoryGetOwner(ref p) => vec!["directory::get_owner".into(), try!(path_str(p))],
+            Op::DirectorySetOwner(ref p, ref u, ref g) => vec!["directory::set_owner".into(), try!(path_str(p)), u.clone(), g.clone()],
+            Op::DirectoryGetMode(ref p) => vec!["directory::get_mode".into(), try!(path_str(p))],
+            Op::DirectorySetMode(ref p, m) => vec!["directory::set_mode".into(), try!(path_str(p)), m.to_string()],
+            Op::FileExists(ref p) => vec!["file::exists".into(), try!(path_str(p))],
+            Op::FileDelete(ref p) => vec!["file::delete".into(), try!(path_str(p))],
+            Op::FileMv(ref p, ref n) => vec!["file::mv".into(), try!(path_str(p)), try!(path_str(n))],
+            Op::FileCopy(ref p, ref n) => vec!["file::copy".into(), try!(path_str(p)), try!(path_str(n))],
+            Op::FileGetOwner(ref p) => vec!["file::get_owner".into(), try!(path_str(p))],
+            Op::FileSetOwner(ref p, ref u, ref g) => vec!["file::set_owner".into(), try!(path_str(p)), u.clone(), g.clone()],
+            Op::FileGetMode(ref p) => vec!["file::get_mode".into(), try!(path_str(p))],
+            Op::FileSetMode(ref p, m) => vec!["file::set_mode".into(), try!(path_str(p)), m.to_string()],
+            Op::ServiceAction(ref n, ref a) => vec!["service::action".into(), n.clone(), a.clone()],
+        };
+
+        Ok(frames)
+    }
+
+    /// Decode the reply frames for the operation.
+    #[cfg(feature = "remote-run")]
+    fn decode(&self, frames: &ZMsg) -> Result<BatchOutput> {
+        match *self {
+            Op::Exec(..) => Ok(BatchOutput::Command(try!(reply::decode(frames, "command::exec")))),
+            Op::DirectoryExists(_) => Ok(BatchOutput::Bool(try!(reply::decode(frames, "directory::exists")))),
+            Op::FileExists(_) => Ok(BatchOutput::Bool(try!(reply::decode(frames, "file::exists")))),
+            Op::DirectoryGetOwner(_) => Ok(BatchOutput::Owner(try!(reply::decode(frames, "directory::get_owner")))),
+            Op::FileGetOwner(_) => Ok(BatchOutput::Owner(try!(reply::decode(frames, "file::get_owner")))),
+            Op::DirectoryGetMode(_) => Ok(BatchOutput::Mode(try!(reply::decode(frames, "directory::get_mode")))),
+            Op::FileGetMode(_) => Ok(BatchOutput::Mode(try!(reply::decode(frames, "file::get_mode")))),
+            Op::ServiceAction(..) => Ok(BatchOutput::Service(try!(reply::decode(frames, "service::action")))),
+            _ => Ok(BatchOutput::Done),
+        }
+    }
+}
+
+#[cfg(feature = "remote-run")]
+fn path_str(path: &Path) -> Result<String> {
+    match path.to_str() {
+        Some(p) => Ok(p.into()),
+        None => Err(Error::Generic(format!("Path is not valid UTF-8: {}", path.display()))),
+    }
+}
+
+#[cfg(feature = "remote-run")]
+fn bool_str(b: bool) -> String {
+    if b { "1".into() } else { "0".into() }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "remote-run")]
+    use agent::LocalAgent;
+    #[cfg(feature = "remote-run")]
+    use czmq::{ZMsg, ZSys};
+    use host::Host;
+    #[cfg(feature = "remote-run")]
+    use host::AgentOptions;
+    use std::fs;
+    #[cfg(feature = "remote-run")]
+    use std::thread;
+    use super::*;
+    use tempdir::TempDir;
+
+    fn check(host: &mut Host) {
+        let tempdir = TempDir::new("batch_test").unwrap();
+        let dir = tempdir.path().join("moo");
+        let file = dir.join("cow");
+
+        let mut batch = Batch::new();
+        batch.directory_create(&dir, false);
+        batch.exec(&format!("touch {}", file.display()));
+        batch.file_set_mode(&file, 600);
+        batch.file_get_mode(&file);
+        batch.file_exists(dir.join("missing"));
+        batch.directory_create(tempdir.path().join("a/b"), false);
+        batch.file_delete(&file);
+
+        let results = batch.run(host).unwrap();
+        assert_eq!(results.len(), 7);
+        assert!(dir.is_dir());
+        assert_eq!(results[1].as_ref().map(|r| match *r { BatchOutput::Command(ref c) => c.exit_code, _ => -1 }).unwrap(), 0);
+        assert_eq!(results[3].as_ref().map(|r| match *r { BatchOutput::Mode(m) => m, _ => 0 }).unwrap(), 600);
+        assert!(results[4].as_ref().map(|r| match *r { BatchOutput::Bool(b) => !b, _ => false }).unwrap());
+        assert!(results[5].is_err());
+        assert!(results[6].is_ok());
+        assert!(!file.exists());
+
+        fs::File::create(&file).unwrap();
+        batch.set_stop_on_error(true);
+        let results = batch.run(host).unwrap();
+        assert_eq!(results.len(), 7);
+        assert!(results[0].is_err());
+        assert!(results[1..].iter().all(|r| r.is_err()));
+        assert!(file.exists());
+    }
+
+    #[test]
+    fn test_run_local() {
+        let path: Option<String> = None;
+        let mut host = Host::local(path).unwrap();
+        check(&mut host);
+    }
+
+    #[cfg(feature = "remote-run")]
+    #[test]
+    fn test_run_agent() {
+        ZSys::init();
+
+        let agent = LocalAgent::new().unwrap();
+        let mut host = agent.connect().unwrap();
+        assert!(host.supports("batch"));
+        check(&mut host);
+    }
+
+    #[cfg(feature = "remote-run")]
+    #[test]
+    fn test_timeout() {
+        let mut batch = Batch::new();
+        assert_eq!(batch.timeout(60), 60);
+
+        batch.exec_timeout("sleep 10", 30);
+        batch.exec("true");
+        batch.file_exists("/tmp");
+        batch.exec_timeout("sleep 20", 40);
+        assert_eq!(batch.timeout(60), 190);
+    }
+
+    #[cfg(feature = "remote-run")]
+    #[test]
+    fn test_run_untimed_commands() {
+        ZSys::init();
+
+        let agent = LocalAgent::new().unwrap();
+        let mut host = agent.connect().unwrap();
+        host.set_agent_options(AgentOptions {
+            timeout: 1,
+            ..AgentOptions::default()
+        });
+
+        // Each command finishes within the default timeout, but the
+        // batch as a whole doesn't.
+        let mut batch = Batch::new();
+        for _ in 0..5 {
+            batch.exec("sleep 0.8");
+        }
+
+        let results = batch.run(&mut host).unwrap();
+        assert_eq!(results.len(), 5);
+        assert!(results.iter().all(|r| r.is_ok()));
+    }
+
+    #[cfg(feature = "remote-run")]
+    #[test]
+    fn test_bad_frame_count() {
+        ZSys::init();
+
+        let (client, mut server) = ZSys::create_pipe().unwrap();
+
+        let agent_mock = thread::spawn(move || {
+            let req = ZMsg::recv(&mut server).unwrap();
+            assert_eq!("batch", req.popstr().unwrap().unwrap());
+
+            let reply = ZMsg::new();
+            reply.addstr("Ok").unwrap();
+            reply.addstr("Ok").unwrap();
+            reply.addstr("one").unwrap();
+            reply.send(&mut server).unwrap();
+        });
+
+        let mut host = Host::test_new(None, Some(client), None, None);
+
+        let mut batch = Batch::new();
+        batch.file_exists("/tmp");
+        match batch.run(&mut host) {
+            Err(Error::HostResponse) => (),
+            _ => panic!("Expected an invalid response"),
+        }
+
+        agent_mock.join().unwrap();
+    }
+}
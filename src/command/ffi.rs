@@ -8,37 +8,101 @@
 
 //! FFI interface for Command
 
-use ffi_helpers::Leaky;
+use ffi_helpers::{Ffi__Array, Leaky};
 use host::Host;
-use libc::{c_char, uint8_t};
+use error::Error;
+use libc::{c_char, size_t, uint8_t, uint64_t};
 use std::convert;
 use std::ffi::CString;
+use std::os::raw::c_void;
 use std::panic::catch_unwind;
-use super::{Command, CommandResult};
+use std::ptr;
+use super::{Command, CommandResult, CommandStream};
 
 #[repr(C)]
 pub struct Ffi__CommandResult {
     pub exit_code: i32,
     pub stdout: *mut c_char,
     pub stderr: *mut c_char,
+    pub signal: i32,
+    pub stdout_raw: Ffi__Array<uint8_t>,
+    pub stderr_raw: Ffi__Array<uint8_t>,
 }
 
 impl convert::From<CommandResult> for Ffi__CommandResult {
     fn from(result: CommandResult) -> Ffi__CommandResult {
         Ffi__CommandResult {
             exit_code: result.exit_code,
-            stdout: CString::new(result.stdout).unwrap().into_raw(),
-            stderr: CString::new(result.stderr).unwrap().into_raw(),
+            stdout: c_string(result.stdout).into_raw(),
+            stderr: c_string(result.stderr).into_raw(),
+            signal: result.signal.unwrap_or(0),
+            stdout_raw: Ffi__Array::from(result.stdout_raw),
+            stderr_raw: Ffi__Array::from(result.stderr_raw),
         }
     }
 }
 
+// Output may contain NUL bytes, which C strings can't represent.
+// Callers that need them should use the raw byte arrays instead.
+fn c_string(s: String) -> CString {
+    CString::new(s.replace('\0', "\u{FFFD}")).unwrap()
+}
+
+#[repr(C)]
+#[derive(Debug, PartialEq)]
+pub enum Ffi__CommandStream {
+    Stdout,
+    Stderr,
+}
+
+impl convert::From<CommandStream> for Ffi__CommandStream {
+    fn from(stream: CommandStream) -> Ffi__CommandStream {
+        match stream {
+            CommandStream::Stdout => Ffi__CommandStream::Stdout,
+            CommandStream::Stderr => Ffi__CommandStream::Stderr,
+        }
+    }
+}
+
+pub type Ffi__CommandStreamHandler = extern "C" fn(Ffi__CommandStream, *const uint8_t, size_t, *mut c_void);
+
 #[no_mangle]
 pub extern "C" fn command_new(cmd_ptr: *const c_char) -> *mut Command {
     let cmd_string = trynull!(ptrtostr!(cmd_ptr, "command string"));
     Box::into_raw(Box::new(Command::new(cmd_string)))
 }
 
+#[no_mangle]
+pub extern "C" fn command_set_timeout(cmd_ptr: *mut Command, timeout: uint64_t) -> uint8_t {
+    let mut cmd = Leaky::new(tryrc!(boxptr!(cmd_ptr, "Command pointer")));
+    cmd.set_timeout(if timeout == 0 { None } else { Some(timeout as u64) });
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn command_set_creates(cmd_ptr: *mut Command, path_ptr: *const c_char) -> uint8_t {
+    let mut cmd = Leaky::new(tryrc!(boxptr!(cmd_ptr, "Command pointer")));
+    let path = if path_ptr.is_null() { None } else { Some(tryrc!(ptrtostr!(path_ptr, "creates path string"))) };
+    cmd.set_creates(path);
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn command_set_onlyif(cmd_ptr: *mut Command, check_ptr: *const c_char) -> uint8_t {
+    let mut cmd = Leaky::new(tryrc!(boxptr!(cmd_ptr, "Command pointer")));
+    let check = if check_ptr.is_null() { None } else { Some(tryrc!(ptrtostr!(check_ptr, "onlyif command string"))) };
+    cmd.set_onlyif(check);
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn command_set_unless(cmd_ptr: *mut Command, check_ptr: *const c_char) -> uint8_t {
+    let mut cmd = Leaky::new(tryrc!(boxptr!(cmd_ptr, "Command pointer")));
+    let check = if check_ptr.is_null() { None } else { Some(tryrc!(ptrtostr!(check_ptr, "unless command string"))) };
+    cmd.set_unless(check);
+    0
+}
+
 #[no_mangle]
 pub extern "C" fn command_exec(cmd_ptr: *mut Command, host_ptr: *mut Host) -> *mut Ffi__CommandResult {
     let cmd = Leaky::new(trynull!(readptr!(cmd_ptr, "Command pointer")));
@@ -50,6 +114,37 @@ pub extern "C" fn command_exec(cmd_ptr: *mut Command, host_ptr: *mut Host) -> *m
     Box::into_raw(Box::new(ffi_result))
 }
 
+#[no_mangle]
+pub extern "C" fn command_exec_stream(cmd_ptr: *mut Command,
+                                      host_ptr: *mut Host,
+                                      handler: Option<Ffi__CommandStreamHandler>,
+                                      user_data: *mut c_void) -> *mut Ffi__CommandResult {
+    let cmd = Leaky::new(trynull!(readptr!(cmd_ptr, "Command pointer")));
+    let mut host = Leaky::new(trynull!(readptr!(host_ptr, "Host pointer")));
+    let handler = trynull!(handler.ok_or(Error::NullPtr("stream handler")));
+
+    let result = trynull!(cmd.exec_stream(&mut host, |stream, chunk| {
+        handler(stream.into(), chunk.as_ptr(), chunk.len() as size_t, user_data);
+    }));
+    let ffi_result: Ffi__CommandResult = trynull!(catch_unwind(|| result.into()));
+
+    Box::into_raw(Box::new(ffi_result))
+}
+
+#[no_mangle]
+pub extern "C" fn command_exec_guarded(cmd_ptr: *mut Command, host_ptr: *mut Host) -> *mut Ffi__CommandResult {
+    let cmd = Leaky::new(trynull!(readptr!(cmd_ptr, "Command pointer")));
+    let mut host = Leaky::new(trynull!(readptr!(host_ptr, "Host pointer")));
+
+    match trynull!(cmd.exec_guarded(&mut host)) {
+        Some(result) => {
+            let ffi_result: Ffi__CommandResult = trynull!(catch_unwind(|| result.into()));
+            Box::into_raw(Box::new(ffi_result))
+        },
+        None => ptr::null_mut(),
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn command_free(cmd_ptr: *mut Command) -> uint8_t {
     tryrc!(boxptr!(cmd_ptr, "Command pointer"));
@@ -65,6 +160,12 @@ pub extern "C" fn command_result_free(result_ptr: *mut Ffi__CommandResult) -> ui
     if !result.stderr.is_null() {
         unsafe { CString::from_raw(result.stderr) };
     }
+    if !result.stdout_raw.ptr.is_null() {
+        let _: Vec<uint8_t> = result.stdout_raw.into();
+    }
+    if !result.stderr_raw.ptr.is_null() {
+        let _: Vec<uint8_t> = result.stderr_raw.into();
+    }
     0
 }
 
@@ -86,12 +187,13 @@ mod tests {
 
     #[test]
     fn test_convert_command_result() {
-        let result = CommandResult {
-            exit_code: 0,
-            stdout: "moo".to_string(),
-            stderr: "cow".to_string(),
-        };
-        Ffi__CommandResult::from(result);
+        let result = CommandResult::from_raw(None, Some(9), b"moo\0\xff\n".to_vec(), b"cow".to_vec());
+        let ffi = Ffi__CommandResult::from(result);
+        assert_eq!(ffi.exit_code, -1);
+        assert_eq!(ffi.signal, 9);
+        assert_eq!(unsafe { CStr::from_ptr(ffi.stdout).to_str().unwrap() }, "moo\u{FFFD}\u{FFFD}");
+        let stdout_raw: Vec<u8> = ffi.stdout_raw.into();
+        assert_eq!(stdout_raw, b"moo\0\xff\n");
     }
 
     #[test]
@@ -103,9 +205,52 @@ mod tests {
         assert_eq!(unsafe { CStr::from_ptr(ERRMSG).to_str().unwrap() }, "Received null when we expected a command string pointer");
     }
 
-    #[cfg(feature = "local-run")]
     #[test]
-    fn test_exec() {
+    fn test_set_timeout() {
+        let cmd = command_new(CString::new("moo").unwrap().into_raw());
+        assert_eq!(command_set_timeout(cmd, 10), 0);
+        assert_eq!(Leaky::new(readptr!(cmd, "Command pointer").unwrap()).timeout, Some(10));
+        assert_eq!(command_set_timeout(cmd, 0), 0);
+        assert_eq!(Leaky::new(readptr!(cmd, "Command pointer").unwrap()).timeout, None);
+        assert_eq!(command_free(cmd), 0);
+    }
+
+    #[test]
+    fn test_set_guards() {
+        let cmd = command_new(CString::new("moo").unwrap().into_raw());
+        assert_eq!(command_set_creates(cmd, CString::new("/opt/app").unwrap().into_raw()), 0);
+        assert_eq!(command_set_onlyif(cmd, CString::new("true").unwrap().into_raw()), 0);
+        assert_eq!(command_set_unless(cmd, CString::new("false").unwrap().into_raw()), 0);
+        {
+            let c = Leaky::new(readptr!(cmd, "Command pointer").unwrap());
+            assert_eq!(c.creates, Some("/opt/app".into()));
+            assert_eq!(c.onlyif, Some("true".into()));
+            assert_eq!(c.unless, Some("false".into()));
+        }
+        assert_eq!(command_set_creates(cmd, ptr::null()), 0);
+        assert_eq!(Leaky::new(readptr!(cmd, "Command pointer").unwrap()).creates, None);
+        assert_eq!(command_free(cmd), 0);
+    }
+
+    #[test]
+    fn test_exec_guarded() {
+        let path: Option<String> = None;
+        let host = Box::into_raw(Box::new(Host::local(path).unwrap()));
+
+        let cmd = command_new(CString::new("true").unwrap().into_raw());
+        assert_eq!(command_set_unless(cmd, CString::new("true").unwrap().into_raw()), 0);
+        assert!(command_exec_guarded(cmd, host).is_null());
+
+        assert_eq!(command_set_unless(cmd, ptr::null()), 0);
+        let result = readptr!(command_exec_guarded(cmd, host), "CommandResult pointer").unwrap();
+        assert_eq!(result.exit_code, 0);
+
+        assert_eq!(command_free(cmd), 0);
+        unsafe { Box::from_raw(host) };
+    }
+
+    #[test]
+    fn test_exec_local() {
         let path: Option<String> = None;
         let host = Box::into_raw(Box::new(Host::local(path).unwrap()));
 
@@ -120,6 +265,31 @@ mod tests {
         unsafe { Box::from_raw(host) };
     }
 
+    #[test]
+    fn test_exec_stream() {
+        extern "C" fn handler(stream: Ffi__CommandStream, chunk: *const uint8_t, len: size_t, user_data: *mut c_void) {
+            let output = unsafe { &mut *(user_data as *mut Vec<u8>) };
+            assert_eq!(stream, Ffi__CommandStream::Stdout);
+            output.extend_from_slice(unsafe { ::std::slice::from_raw_parts(chunk, len) });
+        }
+
+        let path: Option<String> = None;
+        let host = Box::into_raw(Box::new(Host::local(path).unwrap()));
+
+        let echo = CString::new("echo moo").unwrap();
+        let cmd = command_new(echo.into_raw());
+        let mut output: Vec<u8> = Vec::new();
+
+        let result = readptr!(command_exec_stream(cmd, host, Some(handler), &mut output as *mut _ as *mut c_void), "CommandResult pointer").unwrap();
+        assert_eq!(result.exit_code, 0);
+        assert_eq!(output, b"moo\n");
+
+        assert!(command_exec_stream(cmd, host, None, ptr::null_mut()).is_null());
+
+        assert_eq!(command_free(cmd), 0);
+        unsafe { Box::from_raw(host) };
+    }
+
     #[cfg(feature = "remote-run")]
     #[test]
     fn test_exec() {
@@ -11,7 +11,10 @@
 pub mod ffi;
 
 use error::Result;
+use file::FileTarget;
 use host::Host;
+use host::data::redact;
+use std::fmt;
 use target::Target;
 
 /// Primitive for running shell commands.
@@ -24,8 +27,8 @@ use target::Target;
 ///
 /// ```no_run
 /// # use inapi::Host;
-#[cfg_attr(feature = "local-run", doc = "let path: Option<String> = None;")]
-#[cfg_attr(feature = "local-run", doc = "let mut host = Host::local(path).unwrap();")]
+#[cfg_attr(not(feature = "remote-run"), doc = "let path: Option<String> = None;")]
+#[cfg_attr(not(feature = "remote-run"), doc = "let mut host = Host::local(path).unwrap();")]
 #[cfg_attr(feature = "remote-run", doc = "let mut host = Host::connect(\"hosts/myhost.json\").unwrap();")]
 /// ```
 ///
@@ -33,8 +36,8 @@ use target::Target;
 ///
 /// ```no_run
 /// # use inapi::{Command, Host};
-#[cfg_attr(feature = "local-run", doc = "let path: Option<String> = None;")]
-#[cfg_attr(feature = "local-run", doc = "let mut host = Host::local(path).unwrap();")]
+#[cfg_attr(not(feature = "remote-run"), doc = "let path: Option<String> = None;")]
+#[cfg_attr(not(feature = "remote-run"), doc = "let mut host = Host::local(path).unwrap();")]
 #[cfg_attr(feature = "remote-run", doc = "# let mut host = Host::connect(\"hosts/myhost.json\").unwrap();")]
 ///let cmd = Command::new("whoami");
 ///let result = cmd.exec(&mut host).unwrap();
@@ -47,17 +50,71 @@ use target::Target;
 pub struct Command {
     /// The shell command
     cmd: String,
+    /// Seconds to wait for the command to complete
+    timeout: Option<u64>,
+    /// Skip the command if this path exists
+    creates: Option<String>,
+    /// Only run the command if this check command succeeds
+    onlyif: Option<String>,
+    /// Only run the command if this check command fails
+    unless: Option<String>,
 }
 
 /// Result attributes returned from the managed host.
-#[derive(Debug)]
+///
+/// Decrypted secrets are redacted from its `Debug` output.
 pub struct CommandResult {
-    /// Exit code for the shell command's process
+    /// Exit code for the shell command's process, or -1 if the
+    /// process was terminated by a signal
     pub exit_code: i32,
-    /// Process's standard output
+    /// Signal that terminated the shell command's process, if any
+    pub signal: Option<i32>,
+    /// Process's standard output, lossily converted to UTF-8 and
+    /// trimmed
     pub stdout: String,
-    /// Process's standard error
+    /// Process's standard error, lossily converted to UTF-8 and
+    /// trimmed
     pub stderr: String,
+    /// Process's untrimmed standard output
+    pub stdout_raw: Vec<u8>,
+    /// Process's untrimmed standard error
+    pub stderr_raw: Vec<u8>,
+}
+
+impl CommandResult {
+    #[doc(hidden)]
+    pub fn from_raw(exit_code: Option<i32>, signal: Option<i32>, stdout: Vec<u8>, stderr: Vec<u8>) -> CommandResult {
+        CommandResult {
+            exit_code: exit_code.unwrap_or(-1),
+            signal: signal,
+            stdout: String::from_utf8_lossy(&stdout).trim().to_string(),
+            stderr: String::from_utf8_lossy(&stderr).trim().to_string(),
+            stdout_raw: stdout,
+            stderr_raw: stderr,
+        }
+    }
+}
+
+impl fmt::Debug for CommandResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CommandResult")
+         .field("exit_code", &self.exit_code)
+         .field("signal", &self.signal)
+         .field("stdout", &redact(&self.stdout))
+         .field("stderr", &redact(&self.stderr))
+         .field("stdout_raw", &redact(&String::from_utf8_lossy(&self.stdout_raw)))
+         .field("stderr_raw", &redact(&String::from_utf8_lossy(&self.stderr_raw)))
+         .finish()
+    }
+}
+
+/// Output stream that a chunk of streamed output was read from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CommandStream {
+    /// Process's standard output
+    Stdout,
+    /// Process's standard error
+    Stderr,
 }
 
 impl Command {
@@ -65,9 +122,57 @@ impl Command {
     pub fn new(cmd: &str) -> Command {
         Command {
             cmd: cmd.to_string(),
+            timeout: None,
+            creates: None,
+            onlyif: None,
+            unless: None,
         }
     }
 
+    /// Set the maximum number of seconds that the command may run
+    /// for. If the command has not completed by this time, its
+    /// process group is killed and `exec()` returns
+    /// `Error::Timeout`. Pass `None` to wait indefinitely.
+    ///
+    ///# Examples
+    ///
+    /// ```no_run
+    ///# use inapi::{Command, Error, Host};
+    #[cfg_attr(not(feature = "remote-run"), doc = "# let path: Option<String> = None;")]
+    #[cfg_attr(not(feature = "remote-run"), doc = "# let mut host = Host::local(path).unwrap();")]
+    #[cfg_attr(feature = "remote-run", doc = "# let mut host = Host::connect(\"hosts/myhost.json\").unwrap();")]
+    ///let mut cmd = Command::new("apt-get -y upgrade");
+    ///cmd.set_timeout(Some(600));
+    ///
+    ///match cmd.exec(&mut host) {
+    ///    Err(Error::Timeout(_)) => println!("Upgrade took too long"),
+    ///    r => println!("{:?}", r),
+    ///}
+    /// ```
+    pub fn set_timeout(&mut self, timeout: Option<u64>) {
+        self.timeout = timeout;
+    }
+
+    /// Skip the command when `exec_guarded()` is called if `path`
+    /// already exists on the host. Pass `None` to remove the guard.
+    pub fn set_creates(&mut self, path: Option<&str>) {
+        self.creates = path.map(|p| p.to_string());
+    }
+
+    /// Skip the command when `exec_guarded()` is called unless the
+    /// check command `check` exits successfully. Pass `None` to
+    /// remove the guard.
+    pub fn set_onlyif(&mut self, check: Option<&str>) {
+        self.onlyif = check.map(|c| c.to_string());
+    }
+
+    /// Skip the command when `exec_guarded()` is called if the check
+    /// command `check` exits successfully. Pass `None` to remove the
+    /// guard.
+    pub fn set_unless(&mut self, check: Option<&str>) {
+        self.unless = check.map(|c| c.to_string());
+    }
+
     /// Execute command on shell.
     ///
     /// Command structs are reusable accross multiple hosts, which is
@@ -80,24 +185,106 @@ impl Command {
     ///# use inapi::{Command, Host};
     ///let cmd = Command::new("whoami");
     ///
-    #[cfg_attr(feature = "local-run", doc = "let path: Option<String> = None;")]
-    #[cfg_attr(feature = "local-run", doc = "let mut web1 = Host::local(path).unwrap();")]
+    #[cfg_attr(not(feature = "remote-run"), doc = "let path: Option<String> = None;")]
+    #[cfg_attr(not(feature = "remote-run"), doc = "let mut web1 = Host::local(path).unwrap();")]
     #[cfg_attr(feature = "remote-run", doc = "let mut web1 = Host::connect(\"data/hosts/web1.json\").unwrap();")]
     ///let w1_result = cmd.exec(&mut web1).unwrap();
     ///
-    #[cfg_attr(feature = "local-run", doc = "let path: Option<String> = None;")]
-    #[cfg_attr(feature = "local-run", doc = "let mut web2 = Host::local(path).unwrap();")]
+    #[cfg_attr(not(feature = "remote-run"), doc = "let path: Option<String> = None;")]
+    #[cfg_attr(not(feature = "remote-run"), doc = "let mut web2 = Host::local(path).unwrap();")]
     #[cfg_attr(feature = "remote-run", doc = "let mut web2 = Host::connect(\"data/hosts/web2.json\").unwrap();")]
     ///let w2_result = cmd.exec(&mut web2).unwrap();
     /// ```
     #[allow(unused_variables)]
     pub fn exec(&self, host: &mut Host) -> Result<CommandResult> {
-        Target::exec(host, &self.cmd)
+        Target::exec(host, &self.cmd, self.timeout)
+    }
+
+    /// Execute command on shell, passing output to `handler` as it
+    /// is produced.
+    ///
+    /// Output is delivered in raw chunks, which are not guaranteed
+    /// to align with line or UTF-8 character boundaries. As output
+    /// has already been passed to the handler, the `stdout` and
+    /// `stderr` fields of the returned `CommandResult` are empty.
+    ///
+    ///# Examples
+    ///
+    /// ```no_run
+    ///# use inapi::{Command, CommandStream, Host};
+    ///# use std::io::{self, Write};
+    #[cfg_attr(not(feature = "remote-run"), doc = "# let path: Option<String> = None;")]
+    #[cfg_attr(not(feature = "remote-run"), doc = "# let mut host = Host::local(path).unwrap();")]
+    #[cfg_attr(feature = "remote-run", doc = "# let mut host = Host::connect(\"hosts/myhost.json\").unwrap();")]
+    ///let cmd = Command::new("apt-get -y upgrade");
+    ///let result = cmd.exec_stream(&mut host, |stream, chunk| {
+    ///    match stream {
+    ///        CommandStream::Stdout => io::stdout().write_all(chunk).unwrap(),
+    ///        CommandStream::Stderr => io::stderr().write_all(chunk).unwrap(),
+    ///    }
+    ///}).unwrap();
+    ///println!("Upgrade exited with {}", result.exit_code);
+    /// ```
+    pub fn exec_stream<F>(&self, host: &mut Host, mut handler: F) -> Result<CommandResult>
+        where F: FnMut(CommandStream, &[u8])
+    {
+        Target::exec_stream(host, &self.cmd, self.timeout, &mut handler)
+    }
+
+    /// Execute command on shell if its guards allow it.
+    ///
+    /// Guards are checked in the order `creates`, `onlyif`, then
+    /// `unless`, and the command only runs if all of them pass. Check
+    /// commands are subject to the same timeout as the command
+    /// itself.
+    ///
+    /// If the function returns `Some`, the command was required to
+    /// run in order to get the host into the required state. If the
+    /// function returns `None`, the host is already in the required
+    /// state.
+    ///
+    ///# Examples
+    ///
+    /// ```no_run
+    ///# use inapi::{Command, Host};
+    #[cfg_attr(not(feature = "remote-run"), doc = "# let path: Option<String> = None;")]
+    #[cfg_attr(not(feature = "remote-run"), doc = "# let mut host = Host::local(path).unwrap();")]
+    #[cfg_attr(feature = "remote-run", doc = "# let mut host = Host::connect(\"hosts/myhost.json\").unwrap();")]
+    ///let mut cmd = Command::new("tar -xzf /tmp/app.tar.gz -C /opt");
+    ///cmd.set_creates(Some("/opt/app"));
+    ///cmd.set_unless(Some("pgrep -x app"));
+    ///
+    ///match cmd.exec_guarded(&mut host).unwrap() {
+    ///    Some(result) => println!("Extracted app with exit code {}", result.exit_code),
+    ///    None => println!("App already extracted"),
+    ///}
+    /// ```
+    pub fn exec_guarded(&self, host: &mut Host) -> Result<Option<CommandResult>> {
+        if let Some(ref path) = self.creates {
+            if try!(Target::file_exists(host, path.as_str())) {
+                return Ok(None);
+            }
+        }
+
+        if let Some(ref check) = self.onlyif {
+            if try!(Target::exec(host, check, self.timeout)).exit_code != 0 {
+                return Ok(None);
+            }
+        }
+
+        if let Some(ref check) = self.unless {
+            if try!(Target::exec(host, check, self.timeout)).exit_code == 0 {
+                return Ok(None);
+            }
+        }
+
+        Ok(Some(try!(self.exec(host))))
     }
 }
 
 pub trait CommandTarget {
-    fn exec(host: &mut Host, cmd: &str) -> Result<CommandResult>;
+    fn exec(host: &mut Host, cmd: &str, timeout: Option<u64>) -> Result<CommandResult>;
+    fn exec_stream(host: &mut Host, cmd: &str, timeout: Option<u64>, handler: &mut FnMut(CommandStream, &[u8])) -> Result<CommandResult>;
 }
 
 #[cfg(test)]
@@ -105,15 +292,15 @@ mod tests {
     use Host;
     #[cfg(feature = "remote-run")]
     use czmq::{ZMsg, ZSys};
-    #[cfg(feature = "local-run")]
+    use error::Error;
     use std::{process, str};
+    use tempdir::TempDir;
     #[cfg(feature = "remote-run")]
     use std::thread;
     use super::*;
 
-    #[cfg(feature = "local-run")]
     #[test]
-    fn test_exec() {
+    fn test_exec_local() {
         let path: Option<String> = None;
         let mut host = Host::local(path).unwrap();
         let cmd = Command::new("whoami");
@@ -122,8 +309,78 @@ mod tests {
         let output = process::Command::new("sh").arg("-c").arg(&cmd.cmd).output().unwrap();
 
         assert_eq!(result.exit_code, output.status.code().unwrap());
+        assert_eq!(result.signal, None);
         assert_eq!(result.stdout, str::from_utf8(&output.stdout).unwrap().trim().to_string());
         assert_eq!(result.stderr, str::from_utf8(&output.stderr).unwrap().trim().to_string());
+        assert_eq!(result.stdout_raw, output.stdout);
+        assert_eq!(result.stderr_raw, output.stderr);
+    }
+
+    #[test]
+    fn test_exec_guarded_local() {
+        let path: Option<String> = None;
+        let mut host = Host::local(path).unwrap();
+        let tempdir = TempDir::new("command_guard").unwrap();
+        let file = format!("{}/moo", tempdir.path().display());
+
+        let mut cmd = Command::new(&format!("touch {}", file));
+        cmd.set_creates(Some(&file));
+        cmd.set_onlyif(Some("true"));
+        cmd.set_unless(Some("false"));
+        assert_eq!(cmd.exec_guarded(&mut host).unwrap().unwrap().exit_code, 0);
+        assert!(cmd.exec_guarded(&mut host).unwrap().is_none());
+
+        let mut cmd = Command::new("true");
+        cmd.set_onlyif(Some("false"));
+        assert!(cmd.exec_guarded(&mut host).unwrap().is_none());
+
+        cmd.set_onlyif(None);
+        cmd.set_unless(Some("true"));
+        assert!(cmd.exec_guarded(&mut host).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_exec_binary_signal_local() {
+        let path: Option<String> = None;
+        let mut host = Host::local(path).unwrap();
+        let cmd = Command::new("printf '\\377 moo \\n'; kill -9 $$");
+        let result = cmd.exec(&mut host).unwrap();
+
+        assert_eq!(result.exit_code, -1);
+        assert_eq!(result.signal, Some(9));
+        assert_eq!(result.stdout, "\u{FFFD} moo");
+        assert_eq!(result.stdout_raw, b"\xff moo \n");
+    }
+
+    #[test]
+    fn test_exec_stream_local() {
+        let path: Option<String> = None;
+        let mut host = Host::local(path).unwrap();
+        let cmd = Command::new("echo moo; echo cow >&2");
+
+        let mut stdout = Vec::new();
+        let mut stderr = Vec::new();
+        let result = cmd.exec_stream(&mut host, |stream, chunk| match stream {
+            CommandStream::Stdout => stdout.extend_from_slice(chunk),
+            CommandStream::Stderr => stderr.extend_from_slice(chunk),
+        }).unwrap();
+
+        assert_eq!(result.exit_code, 0);
+        assert_eq!(stdout, b"moo\n");
+        assert_eq!(stderr, b"cow\n");
+    }
+
+    #[test]
+    fn test_exec_timeout_local() {
+        let path: Option<String> = None;
+        let mut host = Host::local(path).unwrap();
+        let mut cmd = Command::new("sleep 5");
+        cmd.set_timeout(Some(1));
+
+        match cmd.exec(&mut host) {
+            Err(Error::Timeout(_)) => (),
+            _ => panic!("Expected command to time out"),
+        }
     }
 
     #[cfg(feature = "remote-run")]
@@ -152,9 +409,155 @@ mod tests {
         let result = cmd.exec(&mut host).unwrap();
 
         assert_eq!(result.exit_code, 0);
+        assert_eq!(result.signal, None);
         assert_eq!(result.stdout, "cow");
         assert_eq!(result.stderr, "err");
 
         agent_mock.join().unwrap();
     }
+
+    #[cfg(feature = "remote-run")]
+    #[test]
+    fn test_exec_guarded() {
+        ZSys::init();
+
+        let (client, mut server) = ZSys::create_pipe().unwrap();
+
+        let agent_mock = thread::spawn(move || {
+            let req = ZMsg::recv(&mut server).unwrap();
+            assert_eq!("file::exists", req.popstr().unwrap().unwrap());
+            assert_eq!("/opt/app", req.popstr().unwrap().unwrap());
+
+            let rep = ZMsg::new();
+            rep.addstr("Ok").unwrap();
+            rep.addstr("0").unwrap();
+            rep.send(&mut server).unwrap();
+
+            let req = ZMsg::recv(&mut server).unwrap();
+            assert_eq!("command::exec", req.popstr().unwrap().unwrap());
+            assert_eq!("pgrep app", req.popstr().unwrap().unwrap());
+
+            let rep = ZMsg::new();
+            rep.addstr("Ok").unwrap();
+            rep.addstr("0").unwrap();
+            rep.addstr("123").unwrap();
+            rep.addstr("").unwrap();
+            rep.send(&mut server).unwrap();
+        });
+
+        let mut host = Host::test_new(None, Some(client), None, None);
+
+        let mut cmd = Command::new("moo");
+        cmd.set_creates(Some("/opt/app"));
+        cmd.set_unless(Some("pgrep app"));
+        assert!(cmd.exec_guarded(&mut host).unwrap().is_none());
+
+        agent_mock.join().unwrap();
+    }
+
+    #[cfg(feature = "remote-run")]
+    #[test]
+    fn test_exec_binary_signal() {
+        ZSys::init();
+
+        let (client, mut server) = ZSys::create_pipe().unwrap();
+
+        let agent_mock = thread::spawn(move || {
+            let req = ZMsg::recv(&mut server).unwrap();
+            assert_eq!("command::exec", req.popstr().unwrap().unwrap());
+
+            let rep = ZMsg::new();
+            rep.addstr("Ok").unwrap();
+            rep.addstr("-1").unwrap();
+            rep.addbytes(b"\xff cow \n").unwrap();
+            rep.addstr("").unwrap();
+            rep.addstr("9").unwrap();
+            rep.send(&mut server).unwrap();
+        });
+
+        let mut host = Host::test_new(None, Some(client), None, None);
+
+        let cmd = Command::new("moo");
+        let result = cmd.exec(&mut host).unwrap();
+
+        assert_eq!(result.exit_code, -1);
+        assert_eq!(result.signal, Some(9));
+        assert_eq!(result.stdout, "\u{FFFD} cow");
+        assert_eq!(result.stdout_raw, b"\xff cow \n");
+
+        agent_mock.join().unwrap();
+    }
+
+    #[cfg(feature = "remote-run")]
+    #[test]
+    fn test_exec_stream() {
+        ZSys::init();
+
+        let (client, mut server) = ZSys::create_pipe().unwrap();
+
+        let agent_mock = thread::spawn(move || {
+            let req = ZMsg::recv(&mut server).unwrap();
+            assert_eq!("", req.popstr().unwrap().unwrap());
+            assert_eq!("command::exec_stream", req.popstr().unwrap().unwrap());
+            assert_eq!("moo", req.popstr().unwrap().unwrap());
+
+            for &(stream, chunk) in &[("stdout", "cow"), ("stderr", "err")] {
+                let rep = ZMsg::new();
+                rep.addstr("").unwrap();
+                rep.addstr("Stream").unwrap();
+                rep.addstr(stream).unwrap();
+                rep.addstr(chunk).unwrap();
+                rep.send(&mut server).unwrap();
+            }
+
+            let rep = ZMsg::new();
+            rep.addstr("").unwrap();
+            rep.addstr("Ok").unwrap();
+            rep.addstr("0").unwrap();
+            rep.send(&mut server).unwrap();
+        });
+
+        let mut host = Host::test_new(None, None, None, None);
+        host.test_set_stream_sock(client);
+
+        let mut chunks = Vec::new();
+        let cmd = Command::new("moo");
+        let result = cmd.exec_stream(&mut host, |stream, chunk| chunks.push((stream, chunk.to_vec()))).unwrap();
+
+        assert_eq!(result.exit_code, 0);
+        assert_eq!(chunks, vec![
+            (CommandStream::Stdout, b"cow".to_vec()),
+            (CommandStream::Stderr, b"err".to_vec()),
+        ]);
+
+        agent_mock.join().unwrap();
+    }
+
+    #[cfg(feature = "remote-run")]
+    #[test]
+    fn test_exec_timeout() {
+        ZSys::init();
+
+        let (client, mut server) = ZSys::create_pipe().unwrap();
+
+        let agent_mock = thread::spawn(move || {
+            let req = ZMsg::recv(&mut server).unwrap();
+            assert_eq!("command::exec", req.popstr().unwrap().unwrap());
+            assert_eq!("sleep 5", req.popstr().unwrap().unwrap());
+            assert_eq!("1", req.popstr().unwrap().unwrap());
+            // Never reply, simulating a hung agent
+        });
+
+        let mut host = Host::test_new(None, Some(client), None, None);
+
+        let mut cmd = Command::new("sleep 5");
+        cmd.set_timeout(Some(1));
+
+        match cmd.exec(&mut host) {
+            Err(Error::Timeout(_)) => (),
+            _ => panic!("Expected command to time out"),
+        }
+
+        agent_mock.join().unwrap();
+    }
 }
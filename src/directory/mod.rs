@@ -30,8 +30,8 @@ pub enum DirectoryOpts {
 ///
 /// ```no_run
 ///# use inapi::Host;
-#[cfg_attr(feature = "local-run", doc = "let path: Option<String> = None;")]
-#[cfg_attr(feature = "local-run", doc = "let mut host = Host::local(path).unwrap();")]
+#[cfg_attr(not(feature = "remote-run"), doc = "let path: Option<String> = None;")]
+#[cfg_attr(not(feature = "remote-run"), doc = "let mut host = Host::local(path).unwrap();")]
 #[cfg_attr(feature = "remote-run", doc = "let mut host = Host::connect(\"hosts/myhost.json\").unwrap();")]
 /// ```
 ///
@@ -39,8 +39,8 @@ pub enum DirectoryOpts {
 ///
 /// ```no_run
 ///# use inapi::{Host, Directory, DirectoryOpts};
-#[cfg_attr(feature = "local-run", doc = "let path: Option<String> = None;")]
-#[cfg_attr(feature = "local-run", doc = "let mut host = Host::local(path).unwrap();")]
+#[cfg_attr(not(feature = "remote-run"), doc = "let path: Option<String> = None;")]
+#[cfg_attr(not(feature = "remote-run"), doc = "let mut host = Host::local(path).unwrap();")]
 #[cfg_attr(feature = "remote-run", doc = "# let mut host = Host::connect(\"hosts/myhost.json\").unwrap();")]
 ///let dir = Directory::new(&mut host, "/path/to/dir").unwrap();
 ///dir.create(&mut host, Some(&[DirectoryOpts::DoRecursive])).unwrap();
@@ -149,9 +149,8 @@ mod tests {
     #[cfg(feature = "remote-run")]
     use std::thread;
 
-    #[cfg(feature = "local-run")]
     #[test]
-    fn test_new_ok() {
+    fn test_new_ok_local() {
         let path: Option<String> = None;
         let mut host = Host::local(path).unwrap();
         let dir = Directory::new(&mut host, "/path/to/dir");
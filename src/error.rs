@@ -8,13 +8,17 @@
 
 #[cfg(feature = "remote-run")]
 use czmq;
+use host::data::redact;
 use libc::c_char;
 use mustache;
 use regex;
 use serde_json;
+use serde_yaml;
 use std::{convert, error, ffi, fmt, io, num, ptr, result, str, string};
 use std::any::Any;
 use std::ffi::CString;
+use std::fmt::Write;
+use toml;
 #[cfg(feature = "remote-run")]
 use zfilexfer;
 
@@ -36,7 +40,6 @@ pub extern "C" fn geterr() -> *const c_char {
     }
 }
 
-#[derive(Debug)]
 /// Global error type.
 pub enum Error {
     /// An error string returned from the host's Intecture Agent
@@ -56,12 +59,16 @@ pub enum Error {
     #[cfg(feature = "remote-run")]
     /// Message frames missing in the response from host's Intecture Agent
     Frame(MissingFrame),
+    /// Value in data is missing or has the wrong type
+    Data(DataError),
     /// Generic error string
     Generic(String),
-    #[cfg(feature = "remote-run")]
     /// Cannot run command on disconnected host
     HostDisconnected,
     #[cfg(feature = "remote-run")]
+    /// Host's public key differs from the known hosts file
+    HostKeyChanged(String),
+    #[cfg(feature = "remote-run")]
     /// Invalid response from host
     HostResponse,
     /// Invalid file descriptor
@@ -84,10 +91,19 @@ pub enum Error {
     Regex(regex::Error),
     /// Serde JSON error
     SerdeJson(serde_json::Error),
+    /// Serde YAML error
+    SerdeYaml(serde_yaml::Error),
     /// Cast str
     StrFromUtf8(str::Utf8Error),
     /// Cast String
     StringFromUtf8(string::FromUtf8Error),
+    /// TOML parser error
+    Toml(toml::de::Error),
+    #[cfg(feature = "remote-run")]
+    /// Incompatible or unsupported Agent protocol
+    Protocol(String),
+    /// Operation exceeded its time limit
+    Timeout(String),
     #[cfg(feature = "remote-run")]
     /// ZFileXfer error
     ZFileXfer(zfilexfer::Error),
@@ -97,38 +113,101 @@ unsafe impl Send for Error {}
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
-            Error::Agent(ref e) => write!(f, "Agent error: {}", e),
+        let mut msg = String::new();
+        try!(match *self {
+            Error::Agent(ref e) => write!(msg, "Agent error: {}", e),
             #[cfg(feature = "remote-run")]
-            Error::Auth(ref e) => write!(f, "Auth error: {}", e),
+            Error::Auth(ref e) => write!(msg, "Auth error: {}", e),
             #[cfg(feature = "remote-run")]
-            Error::BuildFailed(ref e) => write!(f, "Failed to build payload: {}", e),
+            Error::BuildFailed(ref e) => write!(msg, "Failed to build payload: {}", e),
             #[cfg(feature = "remote-run")]
-            Error::RunFailed(ref e) => write!(f, "Failed to run payload: {}", e),
+            Error::RunFailed(ref e) => write!(msg, "Failed to run payload: {}", e),
             #[cfg(feature = "remote-run")]
-            Error::Czmq(ref e) => write!(f, "CZMQ error: {}", e),
+            Error::Czmq(ref e) => write!(msg, "CZMQ error: {}", e),
             #[cfg(feature = "remote-run")]
-            Error::Frame(ref e) => write!(f, "Missing frame {} in message: {}", e.order, e.name),
-            Error::Generic(ref e) => write!(f, "Error: {}", e),
+            Error::Frame(ref e) => write!(msg, "Missing frame {} in message: {}", e.order, e.name),
+            Error::Data(ref e) => write!(msg, "Data error at {}: {}", e.pointer, e.message),
+            Error::Generic(ref e) => write!(msg, "Error: {}", e),
+            Error::HostDisconnected => write!(msg, "Cannot run command while host is disconnected"),
             #[cfg(feature = "remote-run")]
-            Error::HostDisconnected => write!(f, "Cannot run command while host is disconnected"),
+            Error::HostKeyChanged(ref e) => write!(msg, "The public key for {} has changed. If this is expected, remove the host from the known hosts file.", e),
             #[cfg(feature = "remote-run")]
-            Error::HostResponse => write!(f, "Invalid response from host"),
-            Error::InvalidFileDescriptor => write!(f, "Invalid file descriptor"),
-            Error::Io(ref e) => write!(f, "IO error: {}", e),
-            Error::Mustache(ref e) => write!(f, "Mustache error: {:?}", e),
-            Error::NulError(ref e) => write!(f, "Nul error: {}", e),
-            Error::NullPtr(ref e) => write!(f, "Received null when we expected a {} pointer", e),
-            Error::ParseFloat(ref e) => write!(f, "Parse error: {}", e),
-            Error::ParseInt(ref e) => write!(f, "Parse error: {}", e),
-            Error::QueryParser(ref e) => write!(f, "Query parser error: {}", e),
-            Error::Regex(ref e) => write!(f, "Regex error: {}", e),
-            Error::SerdeJson(ref e) => write!(f, "Serde JSON error: {}", e),
-            Error::StrFromUtf8(ref e) => write!(f, "Convert from UTF8 slice to str error: {}", e),
-            Error::StringFromUtf8(ref e) => write!(f, "Convert from UTF8 slice to String error: {}", e),
+            Error::HostResponse => write!(msg, "Invalid response from host"),
+            Error::InvalidFileDescriptor => write!(msg, "Invalid file descriptor"),
+            Error::Io(ref e) => write!(msg, "IO error: {}", e),
+            Error::Mustache(ref e) => write!(msg, "Mustache error: {:?}", e),
+            Error::NulError(ref e) => write!(msg, "Nul error: {}", e),
+            Error::NullPtr(ref e) => write!(msg, "Received null when we expected a {} pointer", e),
+            Error::ParseFloat(ref e) => write!(msg, "Parse error: {}", e),
+            Error::ParseInt(ref e) => write!(msg, "Parse error: {}", e),
+            Error::QueryParser(ref e) => write!(msg, "Query parser error: {}", e),
+            Error::Regex(ref e) => write!(msg, "Regex error: {}", e),
+            Error::SerdeJson(ref e) => write!(msg, "Serde JSON error: {}", e),
+            Error::SerdeYaml(ref e) => write!(msg, "Serde YAML error: {}", e),
+            Error::StrFromUtf8(ref e) => write!(msg, "Convert from UTF8 slice to str error: {}", e),
+            Error::StringFromUtf8(ref e) => write!(msg, "Convert from UTF8 slice to String error: {}", e),
+            Error::Toml(ref e) => write!(msg, "TOML error: {}", e),
             #[cfg(feature = "remote-run")]
-            Error::ZFileXfer(ref e) => write!(f, "ZFileXfer error: {}", e),
-        }
+            Error::Protocol(ref e) => write!(msg, "Protocol error: {}", e),
+            Error::Timeout(ref e) => write!(msg, "Timed out: {}", e),
+            #[cfg(feature = "remote-run")]
+            Error::ZFileXfer(ref e) => write!(msg, "ZFileXfer error: {}", e),
+        });
+
+        // Errors can quote command output or data, so keep secrets
+        // out of them.
+        f.write_str(&redact(&msg))
+    }
+}
+
+impl fmt::Debug for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Debug output is printed by unwrap() and expect(), so it is
+        // redacted like Display. Strings are redacted before they are
+        // quoted, as escaping would stop a secret from matching.
+        let mut msg = String::new();
+        try!(match *self {
+            Error::Agent(ref e) => write!(msg, "Agent({:?})", redact(e)),
+            #[cfg(feature = "remote-run")]
+            Error::Auth(ref e) => write!(msg, "Auth({:?})", redact(e)),
+            #[cfg(feature = "remote-run")]
+            Error::BuildFailed(ref e) => write!(msg, "BuildFailed({:?})", redact(e)),
+            #[cfg(feature = "remote-run")]
+            Error::RunFailed(ref e) => write!(msg, "RunFailed({:?})", redact(e)),
+            #[cfg(feature = "remote-run")]
+            Error::Czmq(ref e) => write!(msg, "Czmq({:?})", e),
+            #[cfg(feature = "remote-run")]
+            Error::Frame(ref e) => write!(msg, "Frame({:?})", e),
+            Error::Data(ref e) => write!(msg, "Data(DataError {{ pointer: {:?}, message: {:?}, expected: {:?}, actual: {:?} }})",
+                                         e.pointer, redact(&e.message), e.expected, e.actual),
+            Error::Generic(ref e) => write!(msg, "Generic({:?})", redact(e)),
+            Error::HostDisconnected => write!(msg, "HostDisconnected"),
+            #[cfg(feature = "remote-run")]
+            Error::HostKeyChanged(ref e) => write!(msg, "HostKeyChanged({:?})", e),
+            #[cfg(feature = "remote-run")]
+            Error::HostResponse => write!(msg, "HostResponse"),
+            Error::InvalidFileDescriptor => write!(msg, "InvalidFileDescriptor"),
+            Error::Io(ref e) => write!(msg, "Io({:?})", e),
+            Error::Mustache(ref e) => write!(msg, "Mustache({:?})", e),
+            Error::NulError(ref e) => write!(msg, "NulError({:?})", e),
+            Error::NullPtr(ref e) => write!(msg, "NullPtr({:?})", e),
+            Error::ParseFloat(ref e) => write!(msg, "ParseFloat({:?})", e),
+            Error::ParseInt(ref e) => write!(msg, "ParseInt({:?})", e),
+            Error::QueryParser(ref e) => write!(msg, "QueryParser({:?})", redact(e)),
+            Error::Regex(ref e) => write!(msg, "Regex({:?})", e),
+            Error::SerdeJson(ref e) => write!(msg, "SerdeJson({:?})", e),
+            Error::SerdeYaml(ref e) => write!(msg, "SerdeYaml({:?})", e),
+            Error::StrFromUtf8(ref e) => write!(msg, "StrFromUtf8({:?})", e),
+            Error::StringFromUtf8(ref e) => write!(msg, "StringFromUtf8({:?})", e),
+            Error::Toml(ref e) => write!(msg, "Toml({:?})", e),
+            #[cfg(feature = "remote-run")]
+            Error::Protocol(ref e) => write!(msg, "Protocol({:?})", redact(e)),
+            Error::Timeout(ref e) => write!(msg, "Timeout({:?})", redact(e)),
+            #[cfg(feature = "remote-run")]
+            Error::ZFileXfer(ref e) => write!(msg, "ZFileXfer({:?})", e),
+        });
+
+        f.write_str(&redact(&msg))
     }
 }
 
@@ -146,10 +225,12 @@ impl error::Error for Error {
             Error::Czmq(ref e) => e.description(),
             #[cfg(feature = "remote-run")]
             Error::Frame(_) => "The Agent's reply was missing a part ('frame') of the expected message",
+            Error::Data(_) => "Value in data is missing or has the wrong type",
             Error::Generic(ref e) => e,
-            #[cfg(feature = "remote-run")]
             Error::HostDisconnected => "Cannot run command on disconnected host",
             #[cfg(feature = "remote-run")]
+            Error::HostKeyChanged(_) => "Host's public key has changed",
+            #[cfg(feature = "remote-run")]
             Error::HostResponse => "Invalid response from host",
             Error::InvalidFileDescriptor => "Invalid file descriptor",
             Error::Io(ref e) => e.description(),
@@ -161,8 +242,13 @@ impl error::Error for Error {
             Error::QueryParser(ref e) => e,
             Error::Regex(ref e) => e.description(),
             Error::SerdeJson(ref e) => e.description(),
+            Error::SerdeYaml(ref e) => e.description(),
             Error::StrFromUtf8(ref e) => e.description(),
             Error::StringFromUtf8(ref e) => e.description(),
+            Error::Toml(ref e) => e.description(),
+            #[cfg(feature = "remote-run")]
+            Error::Protocol(ref e) => e,
+            Error::Timeout(ref e) => e,
             #[cfg(feature = "remote-run")]
             Error::ZFileXfer(ref e) => e.description(),
         }
@@ -226,6 +312,12 @@ impl convert::From<serde_json::Error> for Error {
     }
 }
 
+impl convert::From<serde_yaml::Error> for Error {
+    fn from(err: serde_yaml::Error) -> Error {
+        Error::SerdeYaml(err)
+    }
+}
+
 impl convert::From<str::Utf8Error> for Error {
     fn from(err: str::Utf8Error) -> Error {
         Error::StrFromUtf8(err)
@@ -238,6 +330,12 @@ impl convert::From<string::FromUtf8Error> for Error {
     }
 }
 
+impl convert::From<toml::de::Error> for Error {
+    fn from(err: toml::de::Error) -> Error {
+        Error::Toml(err)
+    }
+}
+
 impl convert::From<num::ParseFloatError> for Error {
     fn from(err: num::ParseFloatError) -> Error {
         Error::ParseFloat(err)
@@ -257,6 +355,30 @@ impl convert::From<zfilexfer::Error> for Error {
     }
 }
 
+#[derive(Debug)]
+pub struct DataError {
+    /// JSON pointer to the value that is wrong, which may be inside
+    /// the value that was requested
+    pub pointer: String,
+    /// What is wrong with the value
+    pub message: String,
+    /// What the value should have been, e.g. "u16"
+    pub expected: Option<String>,
+    /// JSON type of the value, e.g. "string"
+    pub actual: Option<String>,
+}
+
+impl DataError {
+    pub fn new(pointer: &str, message: &str) -> DataError {
+        DataError {
+            pointer: pointer.into(),
+            message: message.into(),
+            expected: None,
+            actual: None,
+        }
+    }
+}
+
 #[cfg(feature = "remote-run")]
 #[derive(Debug)]
 pub struct MissingFrame {
@@ -43,8 +43,8 @@ pub struct FileOwner {
 ///
 /// ```no_run
 /// # use inapi::Host;
-#[cfg_attr(feature = "local-run", doc = "let path: Option<String> = None;")]
-#[cfg_attr(feature = "local-run", doc = "let mut host = Host::local(path).unwrap();")]
+#[cfg_attr(not(feature = "remote-run"), doc = "let path: Option<String> = None;")]
+#[cfg_attr(not(feature = "remote-run"), doc = "let mut host = Host::local(path).unwrap();")]
 #[cfg_attr(feature = "remote-run", doc = "let mut host = Host::connect(\"hosts/myhost.json\").unwrap();")]
 /// ```
 ///
@@ -52,8 +52,8 @@ pub struct FileOwner {
 ///
 /// ```no_run
 /// # use inapi::{Host, File, FileOptions};
-#[cfg_attr(feature = "local-run", doc = "# let path: Option<String> = None;")]
-#[cfg_attr(feature = "local-run", doc = "# let mut host = Host::local(path).unwrap();")]
+#[cfg_attr(not(feature = "remote-run"), doc = "# let path: Option<String> = None;")]
+#[cfg_attr(not(feature = "remote-run"), doc = "# let mut host = Host::local(path).unwrap();")]
 #[cfg_attr(feature = "remote-run", doc = "# let mut host = Host::connect(\"hosts/myhost.json\").unwrap();")]
 ///let file = File::new(&mut host, "/path/to/destination_file").unwrap();
 #[cfg_attr(feature = "remote-run", doc = "file.upload(&mut host, \"/path/to/local_file\", None);")]
@@ -93,17 +93,39 @@ impl File {
     #[cfg(feature = "remote-run")]
     /// Upload a file to the managed host.
     pub fn upload<P: AsRef<Path>>(&self, host: &mut Host, local_path: P, options: Option<&[zfilexfer::FileOptions]>) -> Result<()> {
+        if host.ssh_session().is_some() {
+            let mut file = try!(fs::File::open(&local_path));
+            return self.upload_ssh(host, &mut file, options);
+        }
+
         let mut file = try!(zfilexfer::File::open(&local_path, options));
         host.send_fs_file(&mut file, &self.path)
     }
 
     #[cfg(feature = "remote-run")]
     /// Upload a file handle to the managed host.
-    pub fn upload_file(&self, host: &mut Host, file: fs::File, options: Option<&[zfilexfer::FileOptions]>) -> Result<()> {
+    pub fn upload_file(&self, host: &mut Host, mut file: fs::File, options: Option<&[zfilexfer::FileOptions]>) -> Result<()> {
+        if host.ssh_session().is_some() {
+            return self.upload_ssh(host, &mut file, options);
+        }
+
         let mut zfile = try!(zfilexfer::File::open_file(file, options));
         host.send_fs_file(&mut zfile, &self.path)
     }
 
+    #[cfg(feature = "remote-run")]
+    fn upload_ssh(&self, host: &mut Host, file: &mut fs::File, options: Option<&[zfilexfer::FileOptions]>) -> Result<()> {
+        // Chunk size only applies to ZFileXfer, so ignore it
+        let backup = options.and_then(|opts| opts.iter().filter_map(|o| match *o {
+            zfilexfer::FileOptions::BackupExisting(ref suffix) => Some(suffix.as_str()),
+            _ => None,
+        }).next());
+
+        let path = try!(self.path.to_str().ok_or(Error::Generic("Path is not valid UTF-8".into())));
+        let session = try!(host.ssh_session().ok_or(Error::HostDisconnected));
+        session.upload(file, path, backup)
+    }
+
     /// Delete the file.
     pub fn delete(&self, host: &mut Host) -> Result<()> {
         Target::file_delete(host, &self.path)
@@ -165,7 +187,6 @@ mod tests {
     use std::thread;
     use super::*;
 
-    #[cfg(feature = "local-run")]
     #[test]
     fn test_new_ok() {
         let path: Option<String> = None;
@@ -0,0 +1,528 @@
+// Copyright 2015-2017 Intecture Developers. See the COPYRIGHT file at the
+// top-level directory of this distribution and at
+// https://intecture.io/COPYRIGHT.
+//
+// Licensed under the Mozilla Public License 2.0 <LICENSE or
+// https://www.tldrlegal.com/l/mpl-2.0>. This file may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Builder for connections to an Agent.
+
+use czmq::{ZCert, ZMsg, ZSock, SocketType};
+use error::{Error, Result};
+use project::ProjectConfig;
+use read_conf;
+use serde_json::Value;
+use std::rc::Rc;
+use super::{AgentOptions, Host, KnownHosts, Transport, telemetry};
+
+/// Secret key placeholder for certificates that only hold a public
+/// key.
+const NO_SECRET: &'static str = "0000000000000000000000000000000000000000";
+
+/// Default time in seconds to wait for the Auth server to reply.
+const AUTH_TIMEOUT: u64 = 10;
+
+/// Where to find a certificate.
+enum Cert {
+    /// Certificate file, as saved by `ZCert::save_public()` or
+    /// `ZCert::save_secret()`
+    File(String),
+    /// Z85 encoded public and secret keys
+    Keys(String, String),
+}
+
+impl Cert {
+    fn load(&self) -> Result<ZCert> {
+        match *self {
+            Cert::File(ref path) => Ok(try!(ZCert::load(path))),
+            Cert::Keys(ref public, ref secret) => Ok(try!(ZCert::from_txt(public, secret))),
+        }
+    }
+}
+
+/// Route for connections to an Agent that isn't directly reachable,
+/// e.g. a host in a private subnet behind a bastion.
+///
+/// CURVE encryption is end-to-end, so the proxy can't read requests
+/// or impersonate the host.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Proxy {
+    /// Connect through a SOCKS5 proxy at "host:port", e.g. one
+    /// started with `ssh -D` on the bastion.
+    Socks5(String),
+    /// Connect to a TCP relay, such as an SSH tunnel or haproxy, that
+    /// forwards to the Agent's API and file ports respectively.
+    Relay(String, String),
+}
+
+impl Proxy {
+    /// Read a proxy from a host's data.
+    ///
+    /// A SOCKS5 proxy is set with the key "agent_socks_proxy", and a
+    /// relay with the keys "agent_relay_api" and "agent_relay_file",
+    /// whose values are ZMQ endpoints, e.g. "tcp://bastion:7101".
+    pub fn from_data(data: &Value) -> Result<Option<Proxy>> {
+        match (wantstr!(data => "/agent_socks_proxy"), wantstr!(data => "/agent_relay_api"), wantstr!(data => "/agent_relay_file")) {
+            (None, None, None) => Ok(None),
+            (Some(p), None, None) => Ok(Some(Proxy::Socks5(p.into()))),
+            (None, Some(a), Some(f)) => Ok(Some(Proxy::Relay(a.into(), f.into()))),
+            (None, _, _) => Err(Error::Generic("A relay needs both \"agent_relay_api\" and \"agent_relay_file\"".into())),
+            _ => Err(Error::Generic("Host cannot have both a SOCKS5 proxy and a relay".into())),
+        }
+    }
+}
+
+/// Builder for a Host that is managed by an Agent.
+///
+/// By default, the user's certificate is loaded from "user.crt" and
+/// the Auth server's certificate from "auth.crt" in the current
+/// directory, and the Auth server's address is read from
+/// "project.json". The Auth server is then asked for the host's
+/// public key.
+///
+/// Each of these can be overridden, and if the host's public key is
+/// pinned with `server_key()`, the Auth server isn't used at all.
+///
+/// Keys fetched from the Auth server can be cached in a known hosts
+/// file, set with `known_hosts()`. If a host's key changes, the
+/// connection is refused unless `strict_host_keys(false)` is set, in
+/// which case the cache is updated and the Host's
+/// `server_key_changed()` returns true. If the Auth server can't be
+/// reached, the cached key is used instead.
+///
+///# Examples
+///
+/// ```no_run
+/// # use inapi::{AgentOptions, HostBuilder};
+///let host = HostBuilder::new("myhost.example.com", 7101, 7102)
+///    .user_cert("/etc/intecture/user.crt")
+///    .server_key("j:V4!(N{x$$Hd3u^Q0OMrr6!Mh&)#u-h+V^}ia3V")
+///    .agent_options(AgentOptions { timeout: 600, ..AgentOptions::default() })
+///    .connect()
+///    .unwrap();
+/// ```
+pub struct HostBuilder {
+    hostname: String,
+    api_port: u32,
+    file_port: u32,
+    user_cert: Cert,
+    auth_cert: Cert,
+    auth_endpoint: Option<String>,
+    auth_timeout: u64,
+    server_key: Option<String>,
+    known_hosts: Option<String>,
+    strict_host_keys: bool,
+    proxy: Option<Proxy>,
+    options: AgentOptions,
+}
+
+impl HostBuilder {
+    /// Create a builder for the Agent on `hostname`.
+    pub fn new(hostname: &str, api_port: u32, file_port: u32) -> HostBuilder {
+        HostBuilder {
+            hostname: hostname.into(),
+            api_port: api_port,
+            file_port: file_port,
+            user_cert: Cert::File("user.crt".into()),
+            auth_cert: Cert::File("auth.crt".into()),
+            auth_endpoint: None,
+            auth_timeout: AUTH_TIMEOUT,
+            server_key: None,
+            known_hosts: None,
+            strict_host_keys: true,
+            proxy: None,
+            options: AgentOptions::default(),
+        }
+    }
+
+    /// Create a builder from a host's data, using the keys
+    /// "hostname", "api_port" and "file_port", and those described
+    /// by `AgentOptions` and `Proxy`.
+    pub fn from_data(data: &Value) -> Result<HostBuilder> {
+        let mut builder = HostBuilder::new(try!(needstr!(data => "/hostname")),
+                                           try!(needu64!(data => "/api_port")) as u32,
+                                           try!(needu64!(data => "/file_port")) as u32);
+        builder.proxy = try!(Proxy::from_data(data));
+        builder.options = AgentOptions::from_data(data);
+        Ok(builder)
+    }
+
+    /// Load the user's certificate from a file.
+    pub fn user_cert(mut self, path: &str) -> HostBuilder {
+        self.user_cert = Cert::File(path.into());
+        self
+    }
+
+    /// Use the user's Z85 encoded keys, rather than a certificate
+    /// file.
+    pub fn user_keys(mut self, public: &str, secret: &str) -> HostBuilder {
+        self.user_cert = Cert::Keys(public.into(), secret.into());
+        self
+    }
+
+    /// Load the Auth server's certificate from a file.
+    pub fn auth_cert(mut self, path: &str) -> HostBuilder {
+        self.auth_cert = Cert::File(path.into());
+        self
+    }
+
+    /// Use the Auth server's Z85 encoded public key, rather than a
+    /// certificate file.
+    pub fn auth_key(mut self, public: &str) -> HostBuilder {
+        self.auth_cert = Cert::Keys(public.into(), NO_SECRET.into());
+        self
+    }
+
+    /// Set the Auth server's address, rather than reading it from
+    /// "project.json".
+    pub fn auth_server(mut self, hostname: &str, api_port: u32) -> HostBuilder {
+        self.auth_endpoint = Some(format!("tcp://{}:{}", hostname, api_port));
+        self
+    }
+
+    /// Set the number of seconds to wait for the Auth server.
+    pub fn auth_timeout(mut self, timeout: u64) -> HostBuilder {
+        self.auth_timeout = timeout;
+        self
+    }
+
+    /// Pin the host's Z85 encoded public key, skipping the Auth
+    /// server lookup.
+    pub fn server_key(mut self, public: &str) -> HostBuilder {
+        self.server_key = Some(public.into());
+        self
+    }
+
+    /// Cache keys fetched from the Auth server in a known hosts file.
+    /// By default, keys are not cached.
+    pub fn known_hosts(mut self, path: &str) -> HostBuilder {
+        self.known_hosts = Some(path.into());
+        self
+    }
+
+    /// Don't cache or check keys fetched from the Auth server.
+    pub fn no_known_hosts(mut self) -> HostBuilder {
+        self.known_hosts = None;
+        self
+    }
+
+    /// Set whether to refuse to connect to a host whose key differs
+    /// from the known hosts file. Defaults to true.
+    pub fn strict_host_keys(mut self, strict: bool) -> HostBuilder {
+        self.strict_host_keys = strict;
+        self
+    }
+
+    /// Route connections to the Agent through a proxy.
+    pub fn proxy(mut self, proxy: Proxy) -> HostBuilder {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Set timeouts and heartbeating for the Agent connection.
+    pub fn agent_options(mut self, options: AgentOptions) -> HostBuilder {
+        self.options = options;
+        self
+    }
+
+    /// Connect to the Agent. Note that this function does not load
+    /// any user data.
+    pub fn connect(self) -> Result<Host> {
+        let user_cert = try!(self.user_cert.load());
+        let (server_key, key_changed) = match self.server_key {
+            Some(ref key) => (key.clone(), false),
+            None => try!(self.known_server_key(&user_cert)),
+        };
+        let server_cert = try!(ZCert::from_txt(&server_key, NO_SECRET));
+
+        let (api_endpoint, file_endpoint, socks_proxy) = match self.proxy {
+            Some(Proxy::Relay(ref api, ref file)) => (api.clone(), file.clone(), None),
+            Some(Proxy::Socks5(ref p)) => (format!("tcp://{}:{}", self.hostname, self.api_port),
+                                           format!("tcp://{}:{}", self.hostname, self.file_port),
+                                           Some(p.clone())),
+            None => (format!("tcp://{}:{}", self.hostname, self.api_port),
+                     format!("tcp://{}:{}", self.hostname, self.file_port),
+                     None),
+        };
+        let api_sock = try!(Host::curve_sock(SocketType::REQ, &user_cert, &server_cert, socks_proxy.as_ref(), &api_endpoint));
+        let file_sock = try!(Host::curve_sock(SocketType::DEALER, &user_cert, &server_cert, socks_proxy.as_ref(), &file_endpoint));
+
+        let mut me = Host::new(&self.hostname, Transport::Agent);
+        me.set_agent_options(self.options);
+        me.socks_proxy = socks_proxy;
+        me.api_sock = Some(api_sock);
+        me.api_endpoint = Some(api_endpoint);
+        me.file_sock = Some(file_sock);
+        me.user_cert = Some(user_cert);
+        me.server_cert = Some(server_cert);
+        me.server_key_changed = key_changed;
+        try!(me.handshake());
+        me.data = Rc::new(try!(telemetry::Telemetry::init(&mut me)));
+
+        Ok(me)
+    }
+
+    /// Get the host's key from the Auth server, checking it against
+    /// the known hosts file. Also returns whether the key differs
+    /// from the cached one.
+    fn known_server_key(&self, user_cert: &ZCert) -> Result<(String, bool)> {
+        let known = match self.known_hosts {
+            Some(ref path) => try!(KnownHosts::open(path)).get(&self.hostname).map(|k| k.to_string()),
+            None => None,
+        };
+
+        let (key, changed) = match (self.lookup_server_key(user_cert), known) {
+            (Ok(key), Some(known)) => {
+                if key == known {
+                    return Ok((key, false));
+                }
+
+                if self.strict_host_keys {
+                    return Err(Error::HostKeyChanged(self.hostname.clone()));
+                }
+
+                (key, true)
+            },
+            (Ok(key), None) => (key, false),
+            // Don't bypass the Auth server if it has rejected the host
+            (Err(Error::Auth(e)), _) => return Err(Error::Auth(e)),
+            // Connect offline using the cached key
+            (Err(_), Some(known)) => return Ok((known, false)),
+            (Err(e), None) => return Err(e),
+        };
+
+        // Re-read the file while saving, as other hosts in a
+        // HostGroup may have saved their keys since it was opened.
+        if let Some(ref path) = self.known_hosts {
+            try!(KnownHosts::save_key(path, &self.hostname, &key));
+        }
+
+        Ok((key, changed))
+    }
+
+    fn lookup_server_key(&self, user_cert: &ZCert) -> Result<String> {
+        let auth_cert = try!(self.auth_cert.load());
+        let auth_endpoint = match self.auth_endpoint {
+            Some(ref e) => e.clone(),
+            None => {
+                let config: ProjectConfig = try!(read_conf("project.json").map_err(|e| {
+                    Error::Generic(format!("No Auth server was given and project.json could not be loaded: {}", e))
+                }));
+                format!("tcp://{}:{}", config.auth_server, config.auth_api_port)
+            },
+        };
+
+        let timeout = self.auth_timeout.saturating_mul(1000);
+        let timeout = if timeout > i32::max_value() as u64 { i32::max_value() } else { timeout as i32 };
+
+        let mut auth_sock = ZSock::new(SocketType::REQ);
+        user_cert.apply(&mut auth_sock);
+        auth_sock.set_curve_serverkey(auth_cert.public_txt());
+        auth_sock.set_sndtimeo(Some(timeout));
+        auth_sock.set_rcvtimeo(Some(timeout));
+        try!(auth_sock.connect(&auth_endpoint));
+
+        // Get server cert from Auth server
+        let msg = ZMsg::new();
+        try!(msg.addstr("cert::lookup"));
+        try!(msg.addstr(&self.hostname));
+        try!(msg.send(&mut auth_sock));
+
+        let reply = try!(ZMsg::recv(&mut auth_sock));
+
+        if reply.size() != 2 {
+            return Err(Error::HostResponse);
+        }
+
+        match try!(reply.popstr().unwrap().or(Err(Error::HostResponse))).as_ref() {
+            "Ok" => Ok(try!(reply.popstr().unwrap().or(Err(Error::HostResponse)))),
+            "Err" => Err(Error::Auth(try!(reply.popstr().unwrap().or(Err(Error::HostResponse))))),
+            _ => Err(Error::HostResponse),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use czmq::{ZCert, ZMsg, ZSock, SocketType, ZSys};
+    use error::Error;
+    use host::KnownHosts;
+    use std::thread::{self, JoinHandle};
+    use super::*;
+    use tempdir::TempDir;
+
+    // The Agent rejects the handshake, which is enough to show that
+    // a CURVE connection was made.
+    fn mock_agent() -> (ZCert, u32, JoinHandle<()>) {
+        ZSys::init();
+
+        let server_cert = ZCert::new().unwrap();
+        let mut server = ZSock::new(SocketType::REP);
+        server_cert.apply(&mut server);
+        server.set_curve_server(true);
+        let port = server.bind("tcp://127.0.0.1:*[60000-]").unwrap();
+
+        let handle = thread::spawn(move || {
+            let msg = ZMsg::recv(&mut server).unwrap();
+            assert_eq!("hello", msg.popstr().unwrap().unwrap());
+
+            let reply = ZMsg::new();
+            reply.addstr("Err").unwrap();
+            reply.addstr("Unknown endpoint").unwrap();
+            reply.send(&mut server).unwrap();
+        });
+
+        (server_cert, port as u32, handle)
+    }
+
+    // The Auth server replies to a single lookup with `server_key`
+    fn mock_auth(server_key: String) -> (ZCert, u32, JoinHandle<()>) {
+        ZSys::init();
+
+        let auth_cert = ZCert::new().unwrap();
+        let mut auth = ZSock::new(SocketType::REP);
+        auth_cert.apply(&mut auth);
+        auth.set_curve_server(true);
+        let port = auth.bind("tcp://127.0.0.1:*[60000-]").unwrap();
+
+        let handle = thread::spawn(move || {
+            let msg = ZMsg::recv(&mut auth).unwrap();
+            assert_eq!("cert::lookup", msg.popstr().unwrap().unwrap());
+            assert_eq!("127.0.0.1", msg.popstr().unwrap().unwrap());
+
+            let reply = ZMsg::new();
+            reply.addstr("Ok").unwrap();
+            reply.addstr(&server_key).unwrap();
+            reply.send(&mut auth).unwrap();
+        });
+
+        (auth_cert, port as u32, handle)
+    }
+
+    fn builder(port: u32) -> HostBuilder {
+        let user_cert = ZCert::new().unwrap();
+        let auth_cert = ZCert::new().unwrap();
+
+        // Nothing listens on the Auth server's port
+        HostBuilder::new("127.0.0.1", port, port + 1)
+            .user_keys(user_cert.public_txt(), user_cert.secret_txt())
+            .auth_key(auth_cert.public_txt())
+            .auth_server("127.0.0.1", port + 2)
+            .auth_timeout(1)
+    }
+
+    #[test]
+    fn test_proxy_from_data() {
+        assert_eq!(Proxy::from_data(&json!({})).unwrap(), None);
+        assert_eq!(Proxy::from_data(&json!({"agent_socks_proxy": "bastion:1080"})).unwrap(),
+                   Some(Proxy::Socks5("bastion:1080".into())));
+        assert_eq!(Proxy::from_data(&json!({
+                       "agent_relay_api": "tcp://bastion:7201",
+                       "agent_relay_file": "tcp://bastion:7202",
+                   })).unwrap(),
+                   Some(Proxy::Relay("tcp://bastion:7201".into(), "tcp://bastion:7202".into())));
+        assert!(Proxy::from_data(&json!({"agent_relay_api": "tcp://bastion:7201"})).is_err());
+    }
+
+    #[test]
+    fn test_relay() {
+        let (server_cert, port, agent_mock) = mock_agent();
+
+        // The Agent is only reachable through the relay's endpoints
+        let user_cert = ZCert::new().unwrap();
+        let relay = Proxy::Relay(format!("tcp://127.0.0.1:{}", port), format!("tcp://127.0.0.1:{}", port + 1));
+        let result = HostBuilder::new("agent.invalid", 1, 2)
+            .user_keys(user_cert.public_txt(), user_cert.secret_txt())
+            .no_known_hosts()
+            .server_key(server_cert.public_txt())
+            .proxy(relay)
+            .connect();
+
+        match result {
+            Err(Error::Protocol(_)) => (),
+            _ => panic!("Expected handshake to be rejected"),
+        }
+
+        agent_mock.join().unwrap();
+    }
+
+    #[test]
+    fn test_server_key() {
+        let (server_cert, port, agent_mock) = mock_agent();
+
+        match builder(port).no_known_hosts().server_key(server_cert.public_txt()).connect() {
+            Err(Error::Protocol(_)) => (),
+            _ => panic!("Expected handshake to be rejected"),
+        }
+
+        agent_mock.join().unwrap();
+    }
+
+    #[test]
+    fn test_known_hosts_offline() {
+        let (server_cert, port, agent_mock) = mock_agent();
+
+        let dir = TempDir::new("test_known_hosts_offline").unwrap();
+        let path = dir.path().join("known_hosts");
+        let mut known_hosts = KnownHosts::open(&path).unwrap();
+        known_hosts.insert("127.0.0.1", server_cert.public_txt());
+        known_hosts.save().unwrap();
+
+        match builder(port).known_hosts(path.to_str().unwrap()).connect() {
+            Err(Error::Protocol(_)) => (),
+            _ => panic!("Expected handshake to be rejected"),
+        }
+
+        agent_mock.join().unwrap();
+    }
+
+    #[test]
+    fn test_known_hosts_changed() {
+        let dir = TempDir::new("test_known_hosts_changed").unwrap();
+        let path = dir.path().join("known_hosts");
+        let old_cert = ZCert::new().unwrap();
+        let new_cert = ZCert::new().unwrap();
+        let user_cert = ZCert::new().unwrap();
+
+        let mut known_hosts = KnownHosts::open(&path).unwrap();
+        known_hosts.insert("127.0.0.1", old_cert.public_txt());
+        known_hosts.save().unwrap();
+
+        let (auth_cert, port, auth_mock) = mock_auth(new_cert.public_txt().into());
+        let builder = HostBuilder::new("127.0.0.1", 1, 2)
+            .auth_key(auth_cert.public_txt())
+            .auth_server("127.0.0.1", port)
+            .auth_timeout(1)
+            .known_hosts(path.to_str().unwrap());
+        match builder.known_server_key(&user_cert) {
+            Err(Error::HostKeyChanged(_)) => (),
+            _ => panic!("Expected the changed key to be refused"),
+        }
+        auth_mock.join().unwrap();
+
+        let (auth_cert, port, auth_mock) = mock_auth(new_cert.public_txt().into());
+        let builder = HostBuilder::new("127.0.0.1", 1, 2)
+            .auth_key(auth_cert.public_txt())
+            .auth_server("127.0.0.1", port)
+            .auth_timeout(1)
+            .known_hosts(path.to_str().unwrap())
+            .strict_host_keys(false);
+        assert_eq!(builder.known_server_key(&user_cert).unwrap(), (new_cert.public_txt().to_string(), true));
+        auth_mock.join().unwrap();
+
+        assert_eq!(KnownHosts::open(&path).unwrap().get("127.0.0.1"), Some(new_cert.public_txt()));
+
+        // Keys aren't cached unless a known hosts file is given
+        assert!(HostBuilder::new("127.0.0.1", 1, 2).known_hosts.is_none());
+    }
+
+    #[test]
+    fn test_known_hosts_unreachable() {
+        let dir = TempDir::new("test_known_hosts_unreachable").unwrap();
+        let path = dir.path().join("known_hosts");
+
+        // Without a cached key, there is no way to connect
+        assert!(builder(1).known_hosts(path.to_str().unwrap()).connect().is_err());
+    }
+}
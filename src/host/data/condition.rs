@@ -6,10 +6,31 @@
 // https://www.tldrlegal.com/l/mpl-2.0>. This file may not be copied,
 // modified, or distributed except according to those terms.
 
+//! Conditions for data queries.
+//!
+//! A condition compares operands, which are JSON pointers into the
+//! host's data (e.g. `/_telemetry/os/platform`), literal values,
+//! lists of operands (e.g. `[1, "two", /three]`) or functions:
+//!
+//! - `exists(/ptr)` is true if the pointer resolves, even to null.
+//! - `version(x)` compares `x` as a version number, e.g.
+//!   `version(/_telemetry/os/version_str) >= 10.12`.
+//!
+//! The comparison operators are `=`, `!=`, `>`, `>=`, `<` and `<=`;
+//! `=~` and `!~` for regex matches; `in` for list membership;
+//! `contains` for substrings and list members; and `starts_with` and
+//! `ends_with` for strings. An operand that isn't compared is true
+//! unless it is `false` or null.
+//!
+//! Conditions are combined with `!`, `&&` and `||`, where `&&` binds
+//! more tightly than `||`, and grouped with parentheses.
+
 use error::{Error, Result};
+use regex::Regex;
 use serde_json::{Value, Number};
+use std::cmp::{self, Ordering};
 use std::fmt;
-use std::iter::{Enumerate, Peekable};
+use std::iter::Peekable;
 use std::str::Chars;
 use std::vec::IntoIter;
 
@@ -17,8 +38,15 @@ use std::vec::IntoIter;
 enum Token {
     GroupInit,
     GroupTerm,
+    ListInit,
+    ListTerm,
+    Comma,
+    Not,
+    Func(Function),
     Pointer(String),
     Value(Value),
+    /// Number, with its text as written in the query
+    Number(Value, String),
     Cop(ComparisonOperator),
     Lop(LogicalOperator),
 }
@@ -28,8 +56,14 @@ impl fmt::Display for Token {
         match *self {
             Token::GroupInit => write!(f, "("),
             Token::GroupTerm => write!(f, ")"),
+            Token::ListInit => write!(f, "["),
+            Token::ListTerm => write!(f, "]"),
+            Token::Comma => write!(f, ","),
+            Token::Not => write!(f, "!"),
+            Token::Func(ref func) => write!(f, "{}", func),
             Token::Pointer(ref s) => write!(f, "{}", s),
             Token::Value(ref v) => write!(f, "{}", v),
+            Token::Number(_, ref s) => write!(f, "{}", s),
             Token::Cop(ref c) => write!(f, "{}", c),
             Token::Lop(ref l) => write!(f, "{}", l),
         }
@@ -44,6 +78,12 @@ enum ComparisonOperator {
     GreaterThanEqualTo,
     LessThan,
     LessThanEqualTo,
+    Matches,
+    NotMatches,
+    In,
+    Contains,
+    StartsWith,
+    EndsWith,
 }
 
 impl fmt::Display for ComparisonOperator {
@@ -55,6 +95,12 @@ impl fmt::Display for ComparisonOperator {
             ComparisonOperator::GreaterThanEqualTo => write!(f, ">="),
             ComparisonOperator::LessThan => write!(f, "<"),
             ComparisonOperator::LessThanEqualTo => write!(f, "<="),
+            ComparisonOperator::Matches => write!(f, "=~"),
+            ComparisonOperator::NotMatches => write!(f, "!~"),
+            ComparisonOperator::In => write!(f, "in"),
+            ComparisonOperator::Contains => write!(f, "contains"),
+            ComparisonOperator::StartsWith => write!(f, "starts_with"),
+            ComparisonOperator::EndsWith => write!(f, "ends_with"),
         }
     }
 }
@@ -68,113 +114,208 @@ enum LogicalOperator {
 impl fmt::Display for LogicalOperator {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            LogicalOperator::And => write!(f, " && "),
-            LogicalOperator::Or => write!(f, " || "),
+            LogicalOperator::And => write!(f, "&&"),
+            LogicalOperator::Or => write!(f, "||"),
         }
     }
 }
 
+#[derive(Clone, Debug, PartialEq)]
+enum Function {
+    Exists,
+    Version,
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Function::Exists => write!(f, "exists"),
+            Function::Version => write!(f, "version"),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    /// Comparison, with the position of its operator
+    Compare(usize, Operand, ComparisonOperator, Operand),
+    Operand(Operand),
+}
+
+#[derive(Debug)]
+enum Operand {
+    Pointer(String),
+    Value(Value),
+    /// Number, with its text as written in the query
+    Number(Value, String),
+    List(Vec<Operand>),
+    Exists(String),
+    Version(Box<Operand>),
+}
+
 pub fn eval(data: &Value, query: &str) -> Result<bool> {
     let mut iter = query.chars().peekable();
-    let mut tokens = tokenize(&mut iter);
-
-    // In order to avoid code duplication, the token vector is
-    // wrapped in group tokens in order that we can pass it directly
-    // to the group parser.
-    // Note that only the GroupTerm token is required as the group
-    // parser expects that a higher level group has already matched
-    // the Group (start) token.
-    tokens.push(Token::GroupTerm);
-    let mut iter = tokens.into_iter().enumerate();
-    Ok(try!(parse(&mut iter, data)))
+    let tokens = try!(tokenize(&mut iter));
+
+    let mut parser = Parser {
+        tokens: tokens.into_iter().peekable(),
+        end: query.chars().count(),
+    };
+    let expr = try!(parser.expr());
+
+    // Anything left over wasn't part of the condition, e.g. an
+    // unmatched closing parenthesis.
+    if let Some((pos, token)) = parser.tokens.next() {
+        return Err(unexpected(pos, &token));
+    }
+
+    expr.eval(data)
 }
 
-fn tokenize(iter: &mut Peekable<Chars>) -> Vec<Token> {
+fn tokenize(iter: &mut Peekable<Chars>) -> Result<Vec<(usize, Token)>> {
     let mut buf = Vec::new();
     let mut buf_is_value = true;
+    let mut buf_start = 0;
     let mut escape = false;
     let mut quotes: Option<char> = None;
+    let mut pos = 0;
     let mut skip = 0;
     let mut tokens = Vec::new();
     let whitespace = ' ';
 
     while let Some(c) = iter.next() {
+        let here = pos;
+        pos += 1;
+
         {
             let next = iter.peek().unwrap_or(&whitespace);
 
             match c {
                 '(' if !escape && quotes.is_none() => {
-                    buf = tokenize_buf(&mut tokens, buf, buf_is_value, quotes.is_some());
-                    tokens.push(Token::GroupInit);
+                    // A value followed by a parenthesis is a function
+                    let func = if buf_is_value {
+                        match buf.iter().cloned().collect::<String>().as_ref() {
+                            "exists" => Some(Function::Exists),
+                            "version" => Some(Function::Version),
+                            _ => None,
+                        }
+                    } else {
+                        None
+                    };
+
+                    match func {
+                        Some(f) => {
+                            tokens.push((buf_start, Token::Func(f)));
+                            buf = Vec::new();
+                        },
+                        None => buf = tokenize_buf(&mut tokens, buf, buf_is_value, false, buf_start),
+                    }
+                    tokens.push((here, Token::GroupInit));
                 },
                 ')' if !escape && quotes.is_none() => {
-                    buf = tokenize_buf(&mut tokens, buf, buf_is_value, quotes.is_some());
-                    tokens.push(Token::GroupTerm);
+                    buf = tokenize_buf(&mut tokens, buf, buf_is_value, false, buf_start);
+                    tokens.push((here, Token::GroupTerm));
+                },
+                '[' if !escape && quotes.is_none() => {
+                    buf = tokenize_buf(&mut tokens, buf, buf_is_value, false, buf_start);
+                    tokens.push((here, Token::ListInit));
+                },
+                ']' if !escape && quotes.is_none() => {
+                    buf = tokenize_buf(&mut tokens, buf, buf_is_value, false, buf_start);
+                    tokens.push((here, Token::ListTerm));
+                },
+                ',' if !escape && quotes.is_none() => {
+                    buf = tokenize_buf(&mut tokens, buf, buf_is_value, false, buf_start);
+                    tokens.push((here, Token::Comma));
                 },
                 '&' if !escape && quotes.is_none() && *next == '&' => {
                     skip = 1;
-                    buf = tokenize_buf(&mut tokens, buf, buf_is_value, quotes.is_some());
-                    tokens.push(Token::Lop(LogicalOperator::And));
+                    buf = tokenize_buf(&mut tokens, buf, buf_is_value, false, buf_start);
+                    tokens.push((here, Token::Lop(LogicalOperator::And)));
                 },
                 '|' if !escape && quotes.is_none() && *next == '|' => {
                     skip = 1;
-                    buf = tokenize_buf(&mut tokens, buf, buf_is_value, quotes.is_some());
-                    tokens.push(Token::Lop(LogicalOperator::Or));
+                    buf = tokenize_buf(&mut tokens, buf, buf_is_value, false, buf_start);
+                    tokens.push((here, Token::Lop(LogicalOperator::Or)));
                 },
                 '=' if !escape && quotes.is_none() => {
-                    if *next == '=' {
+                    buf = tokenize_buf(&mut tokens, buf, buf_is_value, false, buf_start);
+                    if *next == '~' {
                         skip = 1;
+                        tokens.push((here, Token::Cop(ComparisonOperator::Matches)));
+                    } else {
+                        if *next == '=' {
+                            skip = 1;
+                        }
+                        tokens.push((here, Token::Cop(ComparisonOperator::Equals)));
                     }
-                    buf = tokenize_buf(&mut tokens, buf, buf_is_value, quotes.is_some());
-                    tokens.push(Token::Cop(ComparisonOperator::Equals));
                 },
                 '!' if !escape && quotes.is_none() && *next == '=' => {
                     skip = 1;
-                    buf = tokenize_buf(&mut tokens, buf, buf_is_value, quotes.is_some());
-                    tokens.push(Token::Cop(ComparisonOperator::NotEquals));
+                    buf = tokenize_buf(&mut tokens, buf, buf_is_value, false, buf_start);
+                    tokens.push((here, Token::Cop(ComparisonOperator::NotEquals)));
+                },
+                '!' if !escape && quotes.is_none() && *next == '~' => {
+                    skip = 1;
+                    buf = tokenize_buf(&mut tokens, buf, buf_is_value, false, buf_start);
+                    tokens.push((here, Token::Cop(ComparisonOperator::NotMatches)));
+                },
+                '!' if !escape && quotes.is_none() && buf.is_empty() => {
+                    tokens.push((here, Token::Not));
                 },
                 '>' if !escape && quotes.is_none() => {
-                    buf = tokenize_buf(&mut tokens, buf, buf_is_value, quotes.is_some());
+                    buf = tokenize_buf(&mut tokens, buf, buf_is_value, false, buf_start);
                     if *next == '=' {
                         skip = 1;
-                        tokens.push(Token::Cop(ComparisonOperator::GreaterThanEqualTo));
+                        tokens.push((here, Token::Cop(ComparisonOperator::GreaterThanEqualTo)));
                     } else {
-                        tokens.push(Token::Cop(ComparisonOperator::GreaterThan));
+                        tokens.push((here, Token::Cop(ComparisonOperator::GreaterThan)));
                     }
                 },
                 '<' if !escape && quotes.is_none() => {
-                    buf = tokenize_buf(&mut tokens, buf, buf_is_value, quotes.is_some());
+                    buf = tokenize_buf(&mut tokens, buf, buf_is_value, false, buf_start);
                     if *next == '=' {
                         skip = 1;
-                        tokens.push(Token::Cop(ComparisonOperator::LessThanEqualTo));
+                        tokens.push((here, Token::Cop(ComparisonOperator::LessThanEqualTo)));
                     } else {
-                        tokens.push(Token::Cop(ComparisonOperator::LessThan));
+                        tokens.push((here, Token::Cop(ComparisonOperator::LessThan)));
                     }
                 },
                 '"' | '\'' if !escape && (quotes.is_none() || quotes == Some(c)) => {
                     if quotes.is_none() {
+                        buf = tokenize_buf(&mut tokens, buf, buf_is_value, false, buf_start);
                         quotes = Some(c);
                         buf_is_value = true;
+                        buf_start = here;
                     } else {
                         quotes = None;
-                        buf = tokenize_buf(&mut tokens, buf, true, true);
+                        buf = tokenize_buf(&mut tokens, buf, true, true, buf_start);
                     }
                 },
                 '/' if buf.is_empty() && quotes.is_none() => {
                     buf_is_value = false;
+                    buf_start = here;
                     buf.push(c);
                 },
                 '\\' => {
                     if escape {
+                        if buf.is_empty() && quotes.is_none() {
+                            buf_is_value = true;
+                            buf_start = here;
+                        }
                         buf.push(c);
                     }
                 },
                 _ if c.is_whitespace() && !escape && quotes.is_none() => {
-                    buf = tokenize_buf(&mut tokens, buf, buf_is_value, quotes.is_some());
+                    buf = tokenize_buf(&mut tokens, buf, buf_is_value, false, buf_start);
                 },
                 _ => {
-                    if buf.is_empty() {
+                    if buf.is_empty() && quotes.is_none() {
                         buf_is_value = true;
+                        buf_start = here;
                     }
 
                     buf.push(c);
@@ -184,20 +325,25 @@ fn tokenize(iter: &mut Peekable<Chars>) -> Vec<Token> {
 
         while skip > 0 {
             iter.next();
+            pos += 1;
             skip -= 1;
         }
 
         escape = !escape && c == '\\';
     }
 
+    if quotes.is_some() {
+        return Err(Error::QueryParser(format!("Unterminated quote at position {}", buf_start)));
+    }
+
     if !buf.is_empty() {
-        tokenize_buf(&mut tokens, buf, buf_is_value, quotes.is_some());
+        tokenize_buf(&mut tokens, buf, buf_is_value, false, buf_start);
     }
 
-    tokens
+    Ok(tokens)
 }
 
-fn tokenize_buf(tokens: &mut Vec<Token>, buf: Vec<char>, value: bool, quotes: bool) -> Vec<char> {
+fn tokenize_buf(tokens: &mut Vec<(usize, Token)>, buf: Vec<char>, value: bool, quotes: bool, pos: usize) -> Vec<char> {
     if !buf.is_empty() {
         if value {
             // Attempt to match integer
@@ -207,113 +353,241 @@ fn tokenize_buf(tokens: &mut Vec<Token>, buf: Vec<char>, value: bool, quotes: bo
 
                     match s.parse::<f64>() {
                         Ok(i) => match Number::from_f64(i) {
-                            Some(n) => tokens.push(Token::Value(Value::Number(n))),
-                            None => tokens.push(Token::Value(Value::String(s))),
+                            Some(n) => tokens.push((pos, Token::Number(Value::Number(n), s))),
+                            None => tokens.push((pos, Token::Value(Value::String(s)))),
                         },
-                        Err(_) => tokens.push(Token::Value(Value::String(s))),
+                        Err(_) => tokens.push((pos, Token::Value(Value::String(s)))),
                     }
                 } else if buf.starts_with(&['-']) {
                     let s: String = buf.into_iter().collect();
 
                     match s.parse::<i64>() {
-                        Ok(i) => tokens.push(Token::Value(Value::Number(Number::from(i)))),
-                        Err(_) => tokens.push(Token::Value(Value::String(s))),
+                        Ok(i) => tokens.push((pos, Token::Number(Value::Number(Number::from(i)), s))),
+                        Err(_) => tokens.push((pos, Token::Value(Value::String(s)))),
                     }
                 } else {
                     let s: String = buf.into_iter().collect();
 
                     match s.parse::<u64>() {
-                        Ok(i) => tokens.push(Token::Value(Value::Number(Number::from(i)))),
-                        Err(_) => tokens.push(Token::Value(Value::String(s))),
+                        Ok(i) => tokens.push((pos, Token::Number(Value::Number(Number::from(i)), s))),
+                        Err(_) => tokens.push((pos, Token::Value(Value::String(s)))),
                     }
                 }
             } else {
                 let s: String = buf.into_iter().collect();
-                if !quotes && s.to_lowercase() == "true" {
-                    tokens.push(Token::Value(Value::Bool(true)));
-                } else if !quotes && s.to_lowercase() == "false" {
-                    tokens.push(Token::Value(Value::Bool(false)));
-                } else if !quotes && s.to_lowercase() == "null" {
-                    tokens.push(Token::Value(Value::Null));
+                let token = if quotes {
+                    Token::Value(Value::String(s))
                 } else {
-                    tokens.push(Token::Value(Value::String(s)));
-                }
+                    match s.to_lowercase().as_ref() {
+                        "true" => Token::Value(Value::Bool(true)),
+                        "false" => Token::Value(Value::Bool(false)),
+                        "null" => Token::Value(Value::Null),
+                        "in" => Token::Cop(ComparisonOperator::In),
+                        "contains" => Token::Cop(ComparisonOperator::Contains),
+                        "starts_with" => Token::Cop(ComparisonOperator::StartsWith),
+                        "ends_with" => Token::Cop(ComparisonOperator::EndsWith),
+                        _ => Token::Value(Value::String(s)),
+                    }
+                };
+                tokens.push((pos, token));
             }
         } else {
-            tokens.push(Token::Pointer(buf.into_iter().collect()));
+            tokens.push((pos, Token::Pointer(buf.into_iter().collect())));
         }
     }
 
     Vec::new()
 }
 
-fn parse(tokens: &mut Enumerate<IntoIter<Token>>, data: &Value) -> Result<bool> {
-    let mut status = false;
-    let mut previous = (Token::GroupInit, Token::GroupInit);
+/// Recursive descent parser for conditions:
+///
+/// ```text
+/// expr    := and ("||" and)*
+/// and     := unary ("&&" unary)*
+/// unary   := "!" unary | "(" expr ")" | operand (cop operand)?
+/// operand := pointer | value | "[" (operand ("," operand)*)? "]" | func "(" operand ")"
+/// ```
+struct Parser {
+    tokens: Peekable<IntoIter<(usize, Token)>>,
+    end: usize,
+}
 
-    while let Some((i, token)) = tokens.next() {
-        match token {
-            Token::GroupInit if match previous.0 { Token::GroupInit | Token::Lop(_) => true, _ => false } =>
-                {
-                    status = try!(parse(tokens, data));
+impl Parser {
+    fn expr(&mut self) -> Result<Expr> {
+        let mut lhs = try!(self.and());
+        while self.next_if(&Token::Lop(LogicalOperator::Or)) {
+            let rhs = try!(self.and());
+            lhs = Expr::Or(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
 
-                    // Correct the previous tokens as the actual
-                    // values are destroyed in the child parse() call
-                    previous = (Token::GroupTerm, Token::GroupInit);
-                    continue;
-                },
-            Token::GroupTerm if match previous.0 { Token::Cop(_) | Token::Lop(_) => false, _ => true} => break,
-            Token::Cop(_) if match previous.0 { Token::Pointer(_) | Token::Value(_) => true, _ => false } &&
-                             match previous.1 { Token::Cop(_) => false, _ => true} => (),
-            Token::Lop(ref l) if match previous.0 { Token::Pointer(_) | Token::Value(_) | Token::GroupTerm => true, _ => false } &&
-                             match previous.1 { Token::Lop(_) => false, _ => true} =>
-                if (*l == LogicalOperator::And && !status) || (*l == LogicalOperator::Or && status) {
-                    // Consume remaining tokens in this group as
-                    // we've finished processing this group.
-                    for (_, t) in tokens {
-                        if t == Token::GroupTerm {
-                            break;
-                        }
-                    }
-                    break;
-                },
-            Token::Pointer(_) |
-            Token::Value(_) if match previous.0 { Token::GroupInit | Token::Lop(_) => true, _ => false } => (),
-            Token::Pointer(_) |
-            Token::Value(_) if match previous.0 { Token::Cop(_) => true, _ => false } &&
-                               match previous.1 { Token::Pointer(_) | Token::Value(_) => true, _ => false } =>
-                match previous.0 {
-                    Token::Cop(ref c) => status = try!(eval_condition(&previous.1, &c, &token, data)),
-                    _ => unreachable!(),
-                },
-            _ => {
-                let mut err_str = format!("Unexpected token {} in query excerpt ...", token);
+    fn and(&mut self) -> Result<Expr> {
+        let mut lhs = try!(self.unary());
+        while self.next_if(&Token::Lop(LogicalOperator::And)) {
+            let rhs = try!(self.unary());
+            lhs = Expr::And(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
 
-                if i > 1 {
-                    err_str.push_str(&format!("{}", previous.1));
-                }
+    fn unary(&mut self) -> Result<Expr> {
+        if self.next_if(&Token::Not) {
+            return Ok(Expr::Not(Box::new(try!(self.unary()))));
+        }
+
+        if self.next_if(&Token::GroupInit) {
+            let expr = try!(self.expr());
+            try!(self.expect(Token::GroupTerm));
+            return Ok(expr);
+        }
+
+        let lhs = try!(self.operand());
+
+        let is_cop = match self.tokens.peek() {
+            Some(&(_, Token::Cop(_))) => true,
+            _ => false,
+        };
 
-                if i > 0 {
-                    err_str.push_str(&format!("{}", previous.0));
-                    err_str.push_str(&format!("{}", token));
+        if is_cop {
+            let (pos, cop) = match self.tokens.next() {
+                Some((pos, Token::Cop(c))) => (pos, c),
+                _ => unreachable!(),
+            };
+            let rhs = try!(self.operand());
+            Ok(Expr::Compare(pos, lhs, cop, rhs))
+        } else {
+            Ok(Expr::Operand(lhs))
+        }
+    }
+
+    fn operand(&mut self) -> Result<Operand> {
+        match self.tokens.next() {
+            Some((_, Token::Pointer(p))) => Ok(Operand::Pointer(p)),
+            Some((_, Token::Value(v))) => Ok(Operand::Value(v)),
+            Some((_, Token::Number(v, s))) => Ok(Operand::Number(v, s)),
+            Some((_, Token::ListInit)) => {
+                let mut items = Vec::new();
+                if self.next_if(&Token::ListTerm) {
+                    return Ok(Operand::List(items));
                 }
 
-                for _ in 0..2 {
-                    if let Some((_, tok)) = tokens.next() {
-                        err_str.push_str(&format!(" {}", tok));
+                loop {
+                    items.push(try!(self.operand()));
+
+                    match self.tokens.next() {
+                        Some((_, Token::Comma)) => (),
+                        Some((_, Token::ListTerm)) => return Ok(Operand::List(items)),
+                        Some((pos, t)) => return Err(unexpected(pos, &t)),
+                        None => return Err(self.eof()),
                     }
                 }
+            },
+            Some((pos, Token::Func(f))) => {
+                try!(self.expect(Token::GroupInit));
+                let arg = try!(self.operand());
+                try!(self.expect(Token::GroupTerm));
+
+                match (f, arg) {
+                    (Function::Exists, Operand::Pointer(p)) => Ok(Operand::Exists(p)),
+                    (Function::Exists, _) => Err(Error::QueryParser(format!("Argument to `exists` at position {} must be a pointer", pos))),
+                    (Function::Version, arg) => Ok(Operand::Version(Box::new(arg))),
+                }
+            },
+            Some((pos, t)) => Err(unexpected(pos, &t)),
+            None => Err(self.eof()),
+        }
+    }
 
-                err_str.push_str("...");
+    /// Consume the next token if it matches `token`.
+    fn next_if(&mut self, token: &Token) -> bool {
+        let matches = match self.tokens.peek() {
+            Some(&(_, ref t)) => t == token,
+            None => false,
+        };
 
-                return Err(Error::QueryParser(err_str));
-            }
+        if matches {
+            self.tokens.next();
+        }
+
+        matches
+    }
+
+    fn expect(&mut self, token: Token) -> Result<()> {
+        match self.tokens.next() {
+            Some((_, ref t)) if *t == token => Ok(()),
+            Some((pos, t)) => Err(Error::QueryParser(format!("Expected `{}` but found `{}` at position {}", token, t, pos))),
+            None => Err(Error::QueryParser(format!("Expected `{}` at end of query", token))),
+        }
+    }
+
+    fn eof(&self) -> Error {
+        Error::QueryParser(format!("Unexpected end of query at position {}", self.end))
+    }
+}
+
+fn unexpected(pos: usize, token: &Token) -> Error {
+    Error::QueryParser(format!("Unexpected token `{}` at position {}", token, pos))
+}
+
+impl Expr {
+    fn eval(&self, data: &Value) -> Result<bool> {
+        match *self {
+            Expr::And(ref a, ref b) => Ok(try!(a.eval(data)) && try!(b.eval(data))),
+            Expr::Or(ref a, ref b) => Ok(try!(a.eval(data)) || try!(b.eval(data))),
+            Expr::Not(ref e) => Ok(!try!(e.eval(data))),
+            Expr::Compare(pos, ref lhs, ref cop, ref rhs) => eval_condition(lhs, cop, rhs, data).map_err(|e| match e {
+                Error::QueryParser(s) => Error::QueryParser(format!("{} (at position {})", s, pos)),
+                e => e,
+            }),
+            Expr::Operand(ref o) => match try!(o.resolve(data)) {
+                Value::Bool(b) => Ok(b),
+                Value::Null => Ok(false),
+                _ => Ok(true),
+            },
+        }
+    }
+}
+
+impl Operand {
+    fn resolve(&self, data: &Value) -> Result<Value> {
+        match *self {
+            Operand::Pointer(ref p) => match data.pointer(p) {
+                Some(v) => Ok(v.clone()),
+                // Currently favouring Null value over error. Experience
+                // might suggest that a warning/error is more appropriate.
+                None => Ok(Value::Null),
+            },
+            Operand::Value(ref v) |
+            Operand::Number(ref v, _) => Ok(v.clone()),
+            Operand::List(ref items) => {
+                let mut values = Vec::new();
+                for item in items {
+                    values.push(try!(item.resolve(data)));
+                }
+                Ok(Value::Array(values))
+            },
+            Operand::Exists(ref p) => Ok(Value::Bool(data.pointer(p).is_some())),
+            Operand::Version(ref o) => o.resolve(data),
         }
+    }
 
-        previous = (token, previous.0);
+    /// Resolve the operand for a version comparison. Numbers keep
+    /// their text, so that "10.10" isn't read as 10.1.
+    fn resolve_version(&self, data: &Value) -> Result<Value> {
+        match *self {
+            Operand::Number(_, ref s) => Ok(Value::String(s.clone())),
+            Operand::Version(ref o) => o.resolve_version(data),
+            _ => self.resolve(data),
+        }
     }
 
-    Ok(status)
+    fn is_version(&self) -> bool {
+        match *self {
+            Operand::Version(_) => true,
+            _ => false,
+        }
+    }
 }
 
 macro_rules! cmp {
@@ -328,60 +602,150 @@ macro_rules! cmp {
             $pv.as_u64().unwrap() $op $cv.as_u64().unwrap()
         }
         else {
-            return Err(Error::QueryParser(format!("Cannot compare {:?} {} {:?}. Values must be numbers of same type.", $pv, $cv, stringify!($op))));
+            return Err(Error::QueryParser(format!("Cannot compare {:?} {} {:?}. Values must be numbers of same type.", $pv, stringify!($op), $cv)));
         }
     }
 }
 
-fn eval_condition(predicate: &Token, cop: &ComparisonOperator, criteria: &Token, data: &Value) -> Result<bool> {
+fn eval_condition(predicate: &Operand, cop: &ComparisonOperator, criteria: &Operand, data: &Value) -> Result<bool> {
+    let pv = try!(predicate.resolve(data));
+    let cv = try!(criteria.resolve(data));
+
+    if predicate.is_version() || criteria.is_version() {
+        let pv = try!(Version::from_value(&try!(predicate.resolve_version(data))));
+        let cv = try!(Version::from_value(&try!(criteria.resolve_version(data))));
+
+        return match *cop {
+            ComparisonOperator::Equals => Ok(pv == cv),
+            ComparisonOperator::NotEquals => Ok(pv != cv),
+            ComparisonOperator::GreaterThan => Ok(pv > cv),
+            ComparisonOperator::GreaterThanEqualTo => Ok(pv >= cv),
+            ComparisonOperator::LessThan => Ok(pv < cv),
+            ComparisonOperator::LessThanEqualTo => Ok(pv <= cv),
+            _ => Err(Error::QueryParser(format!("Operator `{}` cannot compare versions", cop))),
+        };
+    }
+
     let result = match *cop {
-        ComparisonOperator::Equals =>
-            try!(resolve_pointer(predicate, data)) == try!(resolve_pointer(criteria, data)),
-        ComparisonOperator::NotEquals =>
-            try!(resolve_pointer(predicate, data)) != try!(resolve_pointer(criteria, data)),
-        ComparisonOperator::GreaterThan => {
-            let pv = try!(resolve_pointer(predicate, data));
-            let cv = try!(resolve_pointer(criteria, data));
-            cmp!(pv > cv)
+        ComparisonOperator::Equals => pv == cv,
+        ComparisonOperator::NotEquals => pv != cv,
+        ComparisonOperator::GreaterThan => cmp!(pv > cv),
+        ComparisonOperator::GreaterThanEqualTo => cmp!(pv >= cv),
+        ComparisonOperator::LessThan => cmp!(pv < cv),
+        ComparisonOperator::LessThanEqualTo => cmp!(pv <= cv),
+        ComparisonOperator::Matches |
+        ComparisonOperator::NotMatches => {
+            let matched = match (pv.as_str(), cv.as_str()) {
+                (Some(s), Some(r)) => try!(Regex::new(r)).is_match(s),
+                _ => return Err(Error::QueryParser(format!("Cannot match {:?} against {:?}. Values must be strings.", pv, cv))),
+            };
+            if *cop == ComparisonOperator::Matches { matched } else { !matched }
         },
-        ComparisonOperator::GreaterThanEqualTo => {
-            let pv = try!(resolve_pointer(predicate, data));
-            let cv = try!(resolve_pointer(criteria, data));
-            cmp!(pv >= cv)
+        ComparisonOperator::In => match cv {
+            Value::Array(ref a) => a.contains(&pv),
+            _ => return Err(Error::QueryParser(format!("Cannot find {:?} in {:?}. Value must be a list.", pv, cv))),
         },
-        ComparisonOperator::LessThan => {
-            let pv = try!(resolve_pointer(predicate, data));
-            let cv = try!(resolve_pointer(criteria, data));
-            cmp!(pv < cv)
+        ComparisonOperator::Contains => match (&pv, &cv) {
+            (&Value::Array(ref a), _) => a.contains(&cv),
+            (&Value::String(ref s), &Value::String(ref c)) => s.contains(c.as_str()),
+            _ => return Err(Error::QueryParser(format!("Cannot find {:?} in {:?}. Value must be a string or list.", cv, pv))),
         },
-        ComparisonOperator::LessThanEqualTo => {
-            let pv = try!(resolve_pointer(predicate, data));
-            let cv = try!(resolve_pointer(criteria, data));
-            cmp!(pv <= cv)
+        ComparisonOperator::StartsWith |
+        ComparisonOperator::EndsWith => match (pv.as_str(), cv.as_str()) {
+            (Some(s), Some(c)) if *cop == ComparisonOperator::StartsWith => s.starts_with(c),
+            (Some(s), Some(c)) => s.ends_with(c),
+            _ => return Err(Error::QueryParser(format!("Cannot compare {:?} {} {:?}. Values must be strings.", pv, cop, cv))),
         },
     };
 
     Ok(result)
 }
 
-fn resolve_pointer(token: &Token, data: &Value) -> Result<Value> {
-    match *token {
-        Token::Pointer(ref p) => match data.pointer(p) {
-            Some(v) => Ok(v.clone()),
-            // Currently favouring Null value over error. Experience
-            // might suggest that a warning/error is more appropriate.
-            None => Ok(Value::Null),
-            // None => Err(Error::QueryParser(format!("JSON pointer \"{}\" does not exist in data", p)))
-        },
-        Token::Value(ref v) => Ok(v.clone()),
-        _ => Err(Error::QueryParser("Token must be Pointer or Value token".into())),
+/// Version number, compared component by component like semver,
+/// e.g. "10.12" = "10.12.0" < "10.12.1". Versions with a pre-release
+/// suffix (e.g. "1.0-beta") come before the release itself.
+#[derive(Debug)]
+struct Version {
+    parts: Vec<u64>,
+    pre: Option<String>,
+}
+
+impl Version {
+    fn parse(s: &str) -> Result<Version> {
+        // Ignore trailing descriptions (e.g. "16.04 LTS"), a leading
+        // "v" and build metadata
+        let v = s.split_whitespace().next().unwrap_or("");
+        let v = v.trim_left_matches(|c| c == 'v' || c == 'V');
+        let v = v.split('+').next().unwrap_or("");
+
+        let (release, pre) = match v.find('-') {
+            Some(i) => (&v[..i], Some(v[i + 1..].to_string())),
+            None => (v, None),
+        };
+
+        let mut parts = Vec::new();
+        for part in release.split('.') {
+            match part.parse::<u64>() {
+                Ok(p) => parts.push(p),
+                Err(_) => return Err(Error::QueryParser(format!("Invalid version \"{}\"", s))),
+            }
+        }
+
+        Ok(Version {
+            parts: parts,
+            pre: pre,
+        })
+    }
+
+    fn from_value(value: &Value) -> Result<Version> {
+        match *value {
+            Value::String(ref s) => Version::parse(s),
+            Value::Number(ref n) => Version::parse(&n.to_string()),
+            _ => Err(Error::QueryParser(format!("Cannot compare {:?} as a version", value))),
+        }
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Version) -> Ordering {
+        for i in 0..cmp::max(self.parts.len(), other.parts.len()) {
+            let a = self.parts.get(i).cloned().unwrap_or(0);
+            let b = other.parts.get(i).cloned().unwrap_or(0);
+
+            match a.cmp(&b) {
+                Ordering::Equal => (),
+                o => return o,
+            }
+        }
+
+        match (&self.pre, &other.pre) {
+            (&None, &None) => Ordering::Equal,
+            (&None, &Some(_)) => Ordering::Greater,
+            (&Some(_), &None) => Ordering::Less,
+            (&Some(ref a), &Some(ref b)) => a.cmp(b),
+        }
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Version) -> Option<Ordering> {
+        Some(self.cmp(other))
     }
 }
 
+impl PartialEq for Version {
+    fn eq(&self, other: &Version) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Version {}
+
 #[cfg(test)]
 mod tests {
+    use error::Error;
     use serde_json::{Value, Number, Map};
-    use super::{ComparisonOperator, LogicalOperator, Token, eval, tokenize};
+    use super::{ComparisonOperator, Function, LogicalOperator, Token, eval, tokenize};
 
     #[test]
     fn test_eval() {
@@ -397,6 +761,49 @@ mod tests {
         assert!(eval(&data, "(((/a=/b && /c!='e') || /d <= 0) || /e > /f) && /fake = NULL").expect("Query result bool"));
     }
 
+    #[test]
+    fn test_eval_extended() {
+        let data = json!({
+            "_telemetry": {
+                "os": {
+                    "platform": "centos",
+                    "version_str": "7.3.1611"
+                }
+            },
+            "enabled": false,
+            "hostname": "web01.example.com",
+            "roles": [ "web", "db" ]
+        });
+
+        assert!(eval(&data, "!/enabled && !(/hostname = 'db01')").unwrap());
+        assert!(eval(&data, "/_telemetry/os/platform in [debian, centos, 'redhat']").unwrap());
+        assert!(!eval(&data, "/_telemetry/os/platform in []").unwrap());
+        assert!(eval(&data, "/hostname =~ '^web[0-9]+\\.' && /hostname !~ db").unwrap());
+        assert!(eval(&data, "exists(/roles) && !exists(/fake)").unwrap());
+        assert!(eval(&data, "/hostname starts_with web && /hostname ends_with '.com' && /hostname contains example").unwrap());
+        assert!(eval(&data, "/roles contains db").unwrap());
+        assert!(eval(&data, "version(/_telemetry/os/version_str) >= 7.3 && version(/_telemetry/os/version_str) < '7.10'").unwrap());
+        assert!(eval(&data, "version('1.0.0-beta') < version(1) && version(v2.0) = version(2)").unwrap());
+        assert!(eval(&data, "version(/_telemetry/os/version_str) < 7.10").unwrap());
+        assert!(eval(&data, "version('10.9') < 10.10 && version(10.10) > '10.9'").unwrap());
+        assert!(!eval(&data, "version(10.10) = 10.1").unwrap());
+
+        // && binds more tightly than ||
+        assert!(eval(&data, "/enabled = false || /enabled = true && /fake = 1").unwrap());
+    }
+
+    #[test]
+    fn test_eval_errors() {
+        let data = json!({});
+
+        for &(query, pos) in &[("/a = 1 && ", "10"), ("(/a = 1", "end"), ("/a = 1)", "6"), ("/a in [1, 2", "11"), ("exists('a')", "0"), ("/a = 'b", "5")] {
+            match eval(&data, query) {
+                Err(Error::QueryParser(e)) => assert!(e.contains(pos), "Error \"{}\" for \"{}\" doesn't contain \"{}\"", e, query, pos),
+                r => panic!("Expected error for \"{}\", got {:?}", query, r),
+            }
+        }
+    }
+
     #[test]
     fn test_tokenize() {
         let expect_tokens = vec![
@@ -409,15 +816,26 @@ mod tests {
             Token::Cop(ComparisonOperator::LessThanEqualTo),
             Token::Pointer("/path/to/=token".into()),
             Token::GroupTerm,
-            Token::Value(Value::Number(Number::from(1u64))),
-            Token::Value(Value::Number(Number::from(-1i64))),
-            Token::Value(Value::Number(Number::from_f64(1.2).unwrap())),
+            Token::Number(Value::Number(Number::from(1u64)), "1".into()),
+            Token::Number(Value::Number(Number::from(-1i64)), "-1".into()),
+            Token::Number(Value::Number(Number::from_f64(1.2).unwrap()), "1.2".into()),
+            Token::Not,
+            Token::Func(Function::Exists),
+            Token::GroupInit,
+            Token::Pointer("/a".into()),
+            Token::GroupTerm,
+            Token::Cop(ComparisonOperator::In),
+            Token::ListInit,
+            Token::Value(Value::String("x".into())),
+            Token::Comma,
+            Token::Value(Value::String("in".into())),
+            Token::ListTerm,
         ];
 
-        let test_str = "/this/is/a/tok\\\\en = \"!=\" && (value<=/path/to/\\=token) 1 -1 1.2";
+        let test_str = "/this/is/a/tok\\\\en = \"!=\" && (value<=/path/to/\\=token) 1 -1 1.2 !exists(/a) in [x, 'in']";
         let mut iter = test_str.chars().peekable();
 
-        let tokens = tokenize(&mut iter);
+        let tokens: Vec<Token> = tokenize(&mut iter).unwrap().into_iter().map(|(_, t)| t).collect();
         assert_eq!(tokens, expect_tokens);
     }
 }
@@ -0,0 +1,232 @@
+// Copyright 2015-2017 Intecture Developers. See the COPYRIGHT file at the
+// top-level directory of this distribution and at
+// https://intecture.io/COPYRIGHT.
+//
+// Licensed under the Mozilla Public License 2.0 <LICENSE or
+// https://www.tldrlegal.com/l/mpl-2.0>. This file may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Deserialization of data that reports which value failed.
+//!
+//! `serde_json::from_value` only says what went wrong, so a bad port
+//! deep inside a payload's data is reported against the whole
+//! section. This deserializer tracks the JSON pointer of each value
+//! it visits and records the innermost one that fails.
+
+use error::{DataError, Error, Result};
+use serde::de::{self, Deserialize, DeserializeSeed, Visitor};
+use serde_json::{self, Map, Value};
+use std::cell::RefCell;
+use std::fmt;
+use std::result;
+use std::vec;
+use super::schema::type_name;
+
+/// The innermost value that failed to deserialize.
+struct Failure {
+    pointer: String,
+    expected: String,
+    actual: String,
+}
+
+/// Deserialize `value`, which is found at `pointer` in the data.
+pub fn from_value<T: Deserialize>(value: Value, pointer: &str) -> Result<T> {
+    let failure = RefCell::new(None);
+
+    let result = T::deserialize(ValueDeserializer {
+        value: value,
+        pointer: pointer.into(),
+        failure: &failure,
+    });
+
+    result.map_err(|e| {
+        let (pointer, expected, actual) = match failure.into_inner() {
+            Some(f) => (f.pointer, Some(f.expected), Some(f.actual)),
+            None => (pointer.into(), None, None),
+        };
+
+        Error::Data(DataError {
+            pointer: pointer,
+            message: e.to_string(),
+            expected: expected,
+            actual: actual,
+        })
+    })
+}
+
+/// Describes what a visitor expects, e.g. "u16".
+struct Expected<'a, V: 'a>(&'a V);
+
+impl<'a, V: Visitor> fmt::Display for Expected<'a, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.expecting(f)
+    }
+}
+
+struct ValueDeserializer<'a> {
+    value: Value,
+    pointer: String,
+    failure: &'a RefCell<Option<Failure>>,
+}
+
+impl<'a> ValueDeserializer<'a> {
+    /// Run `f`, recording this value as the failure if `f` fails and
+    /// no value inside it has failed already.
+    fn track<V, F>(self, visitor: V, f: F) -> result::Result<V::Value, serde_json::Error>
+        where V: Visitor,
+              F: FnOnce(Self, V) -> result::Result<V::Value, serde_json::Error>
+    {
+        let expected = Expected(&visitor).to_string();
+        let actual = type_name(&self.value);
+        let pointer = self.pointer.clone();
+        let failure = self.failure;
+
+        let result = f(self, visitor);
+
+        if result.is_err() {
+            let mut failure = failure.borrow_mut();
+            if failure.is_none() {
+                *failure = Some(Failure {
+                    pointer: pointer,
+                    expected: expected,
+                    actual: actual.into(),
+                });
+            }
+        }
+
+        result
+    }
+}
+
+impl<'a> de::Deserializer for ValueDeserializer<'a> {
+    type Error = serde_json::Error;
+
+    fn deserialize<V: Visitor>(self, visitor: V) -> result::Result<V::Value, serde_json::Error> {
+        self.track(visitor, |me, visitor| match me.value {
+            Value::Array(a) => {
+                let len = a.len();
+                let mut seq = SeqDeserializer {
+                    iter: a.into_iter(),
+                    index: 0,
+                    pointer: me.pointer,
+                    failure: me.failure,
+                };
+
+                let value = try!(visitor.visit_seq(&mut seq));
+                if seq.iter.len() == 0 {
+                    Ok(value)
+                } else {
+                    Err(de::Error::invalid_length(len, &"fewer elements in array"))
+                }
+            },
+            Value::Object(o) => {
+                let len = o.len();
+                let mut map = MapDeserializer {
+                    iter: o.into_iter(),
+                    value: None,
+                    pointer: me.pointer,
+                    failure: me.failure,
+                };
+
+                let value = try!(visitor.visit_map(&mut map));
+                if map.iter.len() == 0 {
+                    Ok(value)
+                } else {
+                    Err(de::Error::invalid_length(len, &"fewer elements in map"))
+                }
+            },
+            v => de::Deserializer::deserialize(v, visitor),
+        })
+    }
+
+    fn deserialize_option<V: Visitor>(self, visitor: V) -> result::Result<V::Value, serde_json::Error> {
+        if self.value.is_null() {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_enum<V: Visitor>(self, name: &'static str, variants: &'static [&'static str], visitor: V) -> result::Result<V::Value, serde_json::Error> {
+        self.track(visitor, |me, visitor| de::Deserializer::deserialize_enum(me.value, name, variants, visitor))
+    }
+
+    fn deserialize_newtype_struct<V: Visitor>(self, _name: &'static str, visitor: V) -> result::Result<V::Value, serde_json::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    forward_to_deserialize! {
+        bool u8 u16 u32 u64 i8 i16 i32 i64 f32 f64 char str string unit seq
+        seq_fixed_size bytes byte_buf map unit_struct tuple_struct struct
+        struct_field tuple ignored_any
+    }
+}
+
+struct SeqDeserializer<'a> {
+    iter: vec::IntoIter<Value>,
+    index: usize,
+    pointer: String,
+    failure: &'a RefCell<Option<Failure>>,
+}
+
+impl<'a> de::SeqVisitor for SeqDeserializer<'a> {
+    type Error = serde_json::Error;
+
+    fn visit_seed<T: DeserializeSeed>(&mut self, seed: T) -> result::Result<Option<T::Value>, serde_json::Error> {
+        match self.iter.next() {
+            Some(value) => {
+                let pointer = format!("{}/{}", self.pointer, self.index);
+                self.index += 1;
+
+                seed.deserialize(ValueDeserializer {
+                    value: value,
+                    pointer: pointer,
+                    failure: self.failure,
+                }).map(Some)
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+struct MapDeserializer<'a> {
+    iter: <Map<String, Value> as IntoIterator>::IntoIter,
+    /// Value for the last key visited, and its pointer
+    value: Option<(String, Value)>,
+    pointer: String,
+    failure: &'a RefCell<Option<Failure>>,
+}
+
+impl<'a> de::MapVisitor for MapDeserializer<'a> {
+    type Error = serde_json::Error;
+
+    fn visit_key_seed<K: DeserializeSeed>(&mut self, seed: K) -> result::Result<Option<K::Value>, serde_json::Error> {
+        match self.iter.next() {
+            Some((key, value)) => {
+                let pointer = format!("{}/{}", self.pointer, key.replace("~", "~0").replace("/", "~1"));
+                self.value = Some((pointer, value));
+                seed.deserialize(Value::String(key)).map(Some)
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn visit_value_seed<V: DeserializeSeed>(&mut self, seed: V) -> result::Result<V::Value, serde_json::Error> {
+        match self.value.take() {
+            Some((pointer, value)) => seed.deserialize(ValueDeserializer {
+                value: value,
+                pointer: pointer,
+                failure: self.failure,
+            }),
+            None => Err(de::Error::custom("value is missing")),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
@@ -0,0 +1,187 @@
+// Copyright 2015-2017 Intecture Developers. See the COPYRIGHT file at the
+// top-level directory of this distribution and at
+// https://intecture.io/COPYRIGHT.
+//
+// Licensed under the Mozilla Public License 2.0 <LICENSE or
+// https://www.tldrlegal.com/l/mpl-2.0>. This file may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Interpolation of values within data.
+//!
+//! A string can reference other values in the merged data using JSON
+//! pointers, e.g. `"${/_telemetry/hostname}.example.com"`. A string
+//! that consists of a single reference, e.g. `"${/ports}"`, is
+//! replaced by the referenced value, whatever its type. Otherwise
+//! the referenced value must be a string, number or boolean.
+//!
+//! Only `${` followed by a "/" starts a reference, so strings such as
+//! `"export PATH=${HOME}/bin"` are left alone. References are
+//! resolved recursively, and circular references are an error. Use
+//! `$${/` for a literal `${/`.
+
+use error::{Error, Result};
+use serde_json::{Map, Value};
+
+/// Resolve every reference in `data`.
+pub fn interpolate(data: &Value) -> Result<Value> {
+    resolve(data, data, &mut Vec::new())
+}
+
+/// Resolve the references in a string, e.g. a path in the data
+/// hierarchy.
+pub fn interpolate_str(root: &Value, s: &str) -> Result<Value> {
+    resolve_str(root, s, &mut Vec::new())
+}
+
+/// Get the JSON pointers that a string references, without
+/// resolving them. Unterminated references are ignored.
+pub fn references(s: &str) -> Vec<&str> {
+    let mut pointers = Vec::new();
+    let mut rest = s;
+
+    while let Some(i) = rest.find('$') {
+        rest = &rest[i..];
+
+        if rest.starts_with("$${/") {
+            rest = &rest[4..];
+        }
+        else if rest.starts_with("${/") {
+            match rest.find('}') {
+                Some(end) => {
+                    pointers.push(&rest[2..end]);
+                    rest = &rest[end + 1..];
+                },
+                None => break,
+            }
+        } else {
+            rest = &rest[1..];
+        }
+    }
+
+    pointers
+}
+
+fn resolve(root: &Value, value: &Value, stack: &mut Vec<String>) -> Result<Value> {
+    match *value {
+        Value::String(ref s) => resolve_str(root, s, stack),
+        Value::Array(ref a) => {
+            let mut array = Vec::new();
+            for v in a {
+                array.push(try!(resolve(root, v, stack)));
+            }
+            Ok(Value::Array(array))
+        },
+        Value::Object(ref o) => {
+            let mut obj = Map::new();
+            for (k, v) in o {
+                obj.insert(k.clone(), try!(resolve(root, v, stack)));
+            }
+            Ok(Value::Object(obj))
+        },
+        _ => Ok(value.clone()),
+    }
+}
+
+fn resolve_str(root: &Value, s: &str, stack: &mut Vec<String>) -> Result<Value> {
+    if s.starts_with("${/") && s.find('}') == Some(s.len() - 1) {
+        return resolve_pointer(root, &s[2..s.len() - 1], stack);
+    }
+
+    let mut out = String::new();
+    let mut rest = s;
+
+    while let Some(i) = rest.find('$') {
+        out.push_str(&rest[..i]);
+        rest = &rest[i..];
+
+        if rest.starts_with("$${/") {
+            out.push_str("${/");
+            rest = &rest[4..];
+        }
+        else if rest.starts_with("${/") {
+            let end = try!(rest.find('}').ok_or(Error::Generic(format!("Unterminated reference in \"{}\"", s))));
+
+            match try!(resolve_pointer(root, &rest[2..end], stack)) {
+                Value::String(ref v) => out.push_str(v),
+                Value::Number(ref n) => out.push_str(&n.to_string()),
+                Value::Bool(b) => out.push_str(&b.to_string()),
+                _ => return Err(Error::Generic(format!("Cannot interpolate `{}` into \"{}\". Value must be a string, number or boolean.", &rest[2..end], s))),
+            }
+
+            rest = &rest[end + 1..];
+        } else {
+            out.push('$');
+            rest = &rest[1..];
+        }
+    }
+
+    out.push_str(rest);
+    Ok(Value::String(out))
+}
+
+fn resolve_pointer(root: &Value, pointer: &str, stack: &mut Vec<String>) -> Result<Value> {
+    if stack.iter().any(|p| p == pointer) {
+        stack.push(pointer.into());
+        return Err(Error::Generic(format!("Circular reference in data: {}", stack.join(" -> "))));
+    }
+
+    let value = try!(root.pointer(pointer).ok_or(Error::Generic(format!("Reference `{}` does not exist in data", pointer))));
+
+    stack.push(pointer.into());
+    let result = resolve(root, value, stack);
+    stack.pop();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interpolate() {
+        let data = json!({
+            "_telemetry": { "hostname": "web01" },
+            "fqdn": "${/_telemetry/hostname}.example.com",
+            "url": "https://${/fqdn}:${/port}/",
+            "port": 443,
+            "ports": "${/all_ports}",
+            "all_ports": [ 80, "${/port}" ],
+            "literal": "$${/port} costs $5",
+        });
+
+        let value = interpolate(&data).unwrap();
+        assert_eq!(value["fqdn"], json!("web01.example.com"));
+        assert_eq!(value["url"], json!("https://web01.example.com:443/"));
+        assert_eq!(value["ports"], json!([ 80, 443 ]));
+        assert_eq!(value["literal"], json!("${/port} costs $5"));
+    }
+
+    #[test]
+    fn test_interpolate_non_pointer() {
+        let data = json!({
+            "profile": "export PATH=${HOME}/bin:$PATH",
+            "whole": "${HOME}",
+            "escaped": "$${HOME}",
+            "mixed": "${USER}@${/host}",
+            "host": "web01",
+        });
+
+        let value = interpolate(&data).unwrap();
+        assert_eq!(value["profile"], json!("export PATH=${HOME}/bin:$PATH"));
+        assert_eq!(value["whole"], json!("${HOME}"));
+        assert_eq!(value["escaped"], json!("$${HOME}"));
+        assert_eq!(value["mixed"], json!("${USER}@web01"));
+
+        assert_eq!(references("${HOME}/${/a}-$${/b}-${/c/d}"), vec![ "/a", "/c/d" ]);
+        assert!(references("${/a").is_empty());
+    }
+
+    #[test]
+    fn test_interpolate_errors() {
+        assert!(interpolate(&json!({ "a": "${/b}", "b": "${/a}" })).is_err());
+        assert!(interpolate(&json!({ "a": { "b": "${/a}" } })).is_err());
+        assert!(interpolate(&json!({ "a": "${/missing}" })).is_err());
+        assert!(interpolate(&json!({ "a": "x${/b}", "b": [] })).is_err());
+        assert!(interpolate(&json!({ "a": "${/b" })).is_err());
+    }
+}
@@ -7,44 +7,471 @@
 // modified, or distributed except according to those terms.
 
 //! Parser for Intecture data files.
+//!
+//! Data files can be written in JSON, YAML (".yaml" or ".yml") or
+//! TOML (".toml"), depending on their extension. Files without a
+//! recognised extension are parsed as JSON. Every format is parsed
+//! into the same `Value`, so merging and queries behave identically.
+//!
+//! Once merged, strings can reference other values using JSON
+//! pointers, e.g. `"${/_telemetry/hostname}.example.com"`.
+//!
+//! # Includes
+//!
+//! A data file can include other files with the `_include` array.
+//! Earlier entries take precedence over later ones. Entries can be:
+//!
+//! - A path relative to the `data/` directory, e.g. "roles/web.json".
+//! - A path starting with "./" or "../", which is relative to the
+//!   including file.
+//! - A glob, e.g. "roles/*.json", where "*" and "?" match within a
+//!   path component. Matches are included in alphabetical order.
+//! - A payload's data, e.g. "payload: nginx" or "payload: nginx::php".
+//!
+//! Prefix a path or glob with "optional:" to ignore it when no file
+//! matches, e.g. "optional: ./local.json". Including a file that is
+//! already being included is an error.
+//!
+//! # Hierarchy
+//!
+//! Rather than every host including the same chain of files, the
+//! project can declare a hierarchy of data files in "project.json",
+//! most important first:
+//!
+//! ```json
+//! "hierarchy": [
+//!     "env/${/environment}.json",
+//!     "os/${/_telemetry/os/platform}.json",
+//!     "common.json"
+//! ]
+//! ```
+//!
+//! The project is the nearest directory above the host's data file
+//! with a "project.json", or else the current directory. Paths are
+//! relative to the project's `data/` directory and can reference the
+//! host data file's keys and telemetry. Every host inherits the
+//! levels that exist for it, with less importance than the host's
+//! data file and its includes. Levels that reference a missing value
+//! are skipped, as are levels without a data file.
+//!
+//! # Merging
+//!
+//! Values from a data file take precedence over those from the files
+//! it includes. How a value is combined with the value it inherits
+//! is decided by its merge strategy:
+//!
+//! - "append": inherited array items are added after this value's
+//!   items. This is the default for arrays.
+//! - "prepend": inherited array items are added before this value's
+//!   items.
+//! - "union": like "append", but without duplicate items.
+//! - "merge": inherited object keys are merged recursively with this
+//!   value's keys. This is the default for objects.
+//! - "replace": the inherited value is ignored. This is the only
+//!   strategy for strings, numbers, booleans and null.
+//! - "delete": the key is removed, along with any inherited value.
+//!
+//! Strategies for arrays and objects are interchangeable, so "union"
+//! applied to an object merges it, and "merge" applied to an array
+//! appends to it.
+//!
+//! A key's strategy is the first of:
+//!
+//! 1. A suffix on the key: `"key!"` for "replace", or
+//!    `"key|<strategy>"`, e.g. `"packages|union"`. Keys with any
+//!    other text after the last "|" are left as they are.
+//! 2. An entry in a `_merge` object alongside the key, e.g.
+//!    `"_merge": { "packages": "union" }`.
+//! 3. The `_merge_default` key in the root of the host's data file,
+//!    which applies throughout the hierarchy.
+//! 4. The default for the value's type.
+//!
+//! Values can be stored encrypted, e.g. `{ "_secret": "<ciphertext>" }`,
+//! and are decrypted when the data file is opened. See `SecretKey`.
+//!
+//! # Provenance
+//!
+//! To find out where a value came from, `explain` merges a host's
+//! data again with tracing enabled, recording the data file and `?`
+//! condition that supplied each value.
 
 #[macro_use]
 mod macros;
 mod condition;
+mod de;
+mod interpolate;
+mod schema;
+mod secret;
 
-use error::{Error, Result};
+use error::{DataError, Error, Result};
+use read_conf;
+use serde::Deserialize;
 use serde_json::{self, Value, Map};
+use serde_yaml;
+use std::collections::{BTreeMap, HashMap};
+use std::collections::btree_map;
 use std::fs;
+use std::io::Read;
 use std::path::{Path, PathBuf};
+use super::Host;
+use toml;
+
+pub use self::schema::{validate, Violation};
+pub use self::secret::{redact, redact_value, SecretKey};
+
+/// Extensions of data files, in order of preference when looking up
+/// a payload's data file.
+const EXTENSIONS: &'static [&'static str] = &["json", "yaml", "yml", "toml"];
 
 #[doc(hidden)]
 pub fn open<P: AsRef<Path>>(path: P) -> Result<Value> {
-    let mut p = PathBuf::from("data");
-    p.push(path);
-    open_raw(&p)
+    open_raw(full_path(path))
+}
+
+/// Get the path of a data file relative to the project root.
+pub fn full_path<P: AsRef<Path>>(path: P) -> PathBuf {
+    Path::new("data").join(path)
 }
 
 fn open_raw<P: AsRef<Path>>(path: P) -> Result<Value> {
     let mut fh = try!(fs::File::open(path.as_ref()));
-    let data: Value = try!(serde_json::from_reader(&mut fh));
+    let mut data: Value = match path.as_ref().extension().and_then(|e| e.to_str()) {
+        Some("yaml") | Some("yml") => try!(serde_yaml::from_reader(&mut fh)),
+        Some("toml") => {
+            let mut s = String::new();
+            try!(fh.read_to_string(&mut s));
+            try!(toml::from_str(&s))
+        },
+        _ => try!(serde_json::from_reader(&mut fh)),
+    };
 
     if !data.is_object() {
         Err(Error::Generic("Value is not an object".into()))
     } else {
+        try!(secret::decrypt_all(&mut data, &mut None));
         Ok(data)
     }
 }
 
-pub fn merge(mut me: Value, mut last_value: Value) -> Result<Value> {
-    for dep in try!(dependencies(&mut me)) {
-        last_value = try!(merge(dep, last_value));
+/// Deserialize the value at `pointer` in `data`, e.g. to read a
+/// payload's section of the data into a struct. Use "" for the whole
+/// value. If deserialization fails, the error points to the value
+/// inside the section that is wrong.
+pub fn get<T: Deserialize>(data: &Value, pointer: &str) -> Result<T> {
+    let value = match data.pointer(pointer) {
+        Some(v) => v.clone(),
+        None => return Err(Error::Data(DataError::new(pointer, "could not find value"))),
+    };
+
+    de::from_value(value, pointer)
+}
+
+/// Strategy for merging a value with the value it inherits.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Strategy {
+    Append,
+    Prepend,
+    Union,
+    Merge,
+    Replace,
+    Delete,
+}
+
+impl Strategy {
+    fn parse(s: &str) -> Result<Strategy> {
+        match s {
+            "append" => Ok(Strategy::Append),
+            "prepend" => Ok(Strategy::Prepend),
+            "union" => Ok(Strategy::Union),
+            "merge" => Ok(Strategy::Merge),
+            "replace" => Ok(Strategy::Replace),
+            "delete" => Ok(Strategy::Delete),
+            _ => Err(Error::Generic(format!("Unknown merge strategy `{}`", s))),
+        }
+    }
+}
+
+/// Where a value in merged data came from.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Origin {
+    /// Path of the data file that supplied the value
+    pub file: String,
+    /// Query of the `?` branch that supplied the value, if any
+    pub condition: Option<String>,
+}
+
+/// Origins of the values in merged data, by JSON pointer.
+///
+/// Values that didn't come from a data file, such as telemetry, have
+/// no origin.
+#[derive(Debug)]
+pub struct Trace {
+    enabled: bool,
+    origins: BTreeMap<String, Origin>,
+}
+
+impl Trace {
+    fn new(enabled: bool) -> Trace {
+        Trace {
+            enabled: enabled,
+            origins: BTreeMap::new(),
+        }
+    }
+
+    /// Get the origin of the value at `pointer`.
+    pub fn get(&self, pointer: &str) -> Option<&Origin> {
+        self.origins.get(pointer)
+    }
+
+    /// Iterate over the origins of every value, in pointer order.
+    pub fn iter(&self) -> btree_map::Iter<String, Origin> {
+        self.origins.iter()
+    }
+
+    fn record(&mut self, pointer: &str, origin: &Origin) {
+        if self.enabled {
+            self.origins.insert(pointer.into(), origin.clone());
+        }
+    }
+
+    /// Forget the origins of the values below `pointer`, returning
+    /// them with their pointers relative to `pointer`.
+    fn take_below(&mut self, pointer: &str) -> Vec<(String, Origin)> {
+        let prefix = format!("{}/", pointer);
+        let keys: Vec<String> = self.origins.keys()
+                                            .filter(|k| k.starts_with(&prefix))
+                                            .cloned()
+                                            .collect();
+
+        keys.into_iter()
+            .map(|k| {
+                let origin = self.origins.remove(&k).unwrap();
+                (k[pointer.len()..].to_owned(), origin)
+            })
+            .collect()
+    }
+
+    /// Forget the origins of `pointer` and the values below it.
+    fn clear(&mut self, pointer: &str) {
+        self.origins.remove(pointer);
+        self.take_below(pointer);
+    }
+
+    /// Restore origins taken from the array item at index `from`
+    /// under `pointer`.
+    fn restore_item(&mut self, taken: &[(String, Origin)], from: usize, pointer: &str) {
+        let index = format!("/{}", from);
+        for &(ref rel, ref origin) in taken {
+            if rel == &index || rel.starts_with(&format!("{}/", index)) {
+                self.origins.insert(format!("{}{}", pointer, &rel[index.len()..]), origin.clone());
+            }
+        }
+    }
+}
+
+/// Explain where a value in a host's data came from, e.g.
+/// `explain(&host, "/nginx/port")`.
+///
+/// The host's data files are merged again with tracing enabled, so
+/// the result reflects their current contents. Returns `None` if the
+/// value doesn't exist or didn't come from a data file.
+pub fn explain(host: &Host, pointer: &str) -> Result<Option<Origin>> {
+    match try!(trace(host)) {
+        Some(trace) => Ok(trace.get(pointer).cloned()),
+        None => Ok(None),
+    }
+}
+
+/// Trace where every value in a host's data came from. Use this
+/// instead of `explain` to look up more than one value, as the data
+/// files are only merged once.
+///
+/// Returns `None` if the host's data wasn't loaded from a file.
+pub fn trace(host: &Host) -> Result<Option<Trace>> {
+    let path = match host.data_path {
+        Some(ref p) => p,
+        None => return Ok(None),
+    };
+
+    let value = try!(open(path));
+    let telemetry = match host.data().get("_telemetry") {
+        Some(t) => json!({ "_telemetry": t.clone() }),
+        None => Value::Null,
+    };
+    let (_, trace) = try!(merge_trace(value, full_path(path), telemetry));
+
+    Ok(Some(trace))
+}
+
+/// Merge `me` and its dependencies into `last_value`, then resolve
+/// references between values. Relative includes are resolved
+/// against the `data/` directory.
+pub fn merge(me: Value, last_value: Value) -> Result<Value> {
+    merge_with(me, String::new(), last_value, &mut Trace::new(false))
+}
+
+/// Merge data like `merge`, where `path` is the data file that `me`
+/// was read from.
+pub fn merge_from<P: AsRef<Path>>(me: Value, path: P, last_value: Value) -> Result<Value> {
+    merge_with(me, path.as_ref().to_string_lossy().into_owned(), last_value, &mut Trace::new(false))
+}
+
+/// Merge data like `merge`, recording where each value came from.
+/// `path` is the data file that `me` was read from.
+pub fn merge_trace<P: AsRef<Path>>(me: Value, path: P, last_value: Value) -> Result<(Value, Trace)> {
+    let mut trace = Trace::new(true);
+    let value = try!(merge_with(me, path.as_ref().to_string_lossy().into_owned(), last_value, &mut trace));
+    Ok((value, trace))
+}
+
+fn merge_with(me: Value, file: String, mut last_value: Value, trace: &mut Trace) -> Result<Value> {
+    let default = match me.get("_merge_default") {
+        Some(&Value::String(ref s)) => match try!(Strategy::parse(s)) {
+            Strategy::Delete => return Err(Error::Generic("Value of `_merge_default` cannot be \"delete\"".into())),
+            s => Some(s),
+        },
+        Some(_) => return Err(Error::Generic("Value of `_merge_default` is not a string".into())),
+        None => None,
+    };
+
+    // Only a host's data file inherits the hierarchy
+    if !file.is_empty() {
+        let project = project_dir(&file);
+        let levels = try!(hierarchy_levels(&project));
+        let deps = try!(hierarchy(&levels, &project, &me, &last_value, &file));
+        for (level, level_file) in deps {
+            last_value = try!(merge_deps(level, level_file, last_value, default, trace, &mut Vec::new()));
+        }
+    }
+
+    let mut value = try!(merge_deps(me, file, last_value, default, trace, &mut Vec::new()));
+    if let Some(o) = value.as_object_mut() {
+        o.remove("_merge_default");
+        trace.clear("/_merge_default");
+    }
+
+    interpolate::interpolate(&value)
+}
+
+fn merge_deps(mut me: Value,
+              file: String,
+              mut last_value: Value,
+              default: Option<Strategy>,
+              trace: &mut Trace,
+              stack: &mut Vec<(PathBuf, String)>) -> Result<Value>
+{
+    if !file.is_empty() {
+        let id = fs::canonicalize(&file).unwrap_or_else(|_| PathBuf::from(&file));
+
+        if let Some(i) = stack.iter().position(|&(ref p, _)| *p == id) {
+            let mut chain: Vec<&str> = stack[i..].iter().map(|&(_, ref f)| f.as_str()).collect();
+            chain.push(&file);
+            return Err(Error::Generic(format!("Circular `_include`: {}", chain.join(" -> "))));
+        }
+
+        stack.push((id, file.clone()));
+    }
+
+    for (dep, dep_file) in try!(dependencies(&mut me, &file)) {
+        last_value = try!(merge_deps(dep, dep_file, last_value, default, trace, stack));
+    }
+
+    if !file.is_empty() {
+        stack.pop();
     }
 
+    let origin = Origin {
+        file: file,
+        condition: None,
+    };
     let lv_clone = last_value.clone();
-    Ok(try!(merge_values(me, last_value, &lv_clone)))
+    Ok(try!(merge_values(me, last_value, &lv_clone, None, default, "", &origin, trace)))
+}
+
+/// Find the project that the data file `file` belongs to: the
+/// nearest directory above it with a "project.json", or else the
+/// current directory.
+fn project_dir(file: &str) -> PathBuf {
+    let mut dir = Path::new(file).parent();
+
+    while let Some(d) = dir {
+        if d.join("project.json").is_file() {
+            return d.to_path_buf();
+        }
+        dir = d.parent();
+    }
+
+    PathBuf::new()
+}
+
+/// The part of "project.json" that data merging needs. Projects
+/// that only manage hosts over SSH or locally have no Auth settings,
+/// so the full `ProjectConfig` can't be required here.
+#[derive(Deserialize)]
+struct HierarchyConfig {
+    hierarchy: Option<Vec<String>>,
+}
+
+/// Get the data hierarchy from the project's "project.json", if any.
+fn hierarchy_levels(project: &Path) -> Result<Vec<String>> {
+    let path = project.join("project.json");
+    if !path.is_file() {
+        return Ok(Vec::new());
+    }
+
+    let config: HierarchyConfig = try!(read_conf(&path).map_err(|e| {
+        Error::Generic(format!("Could not load the data hierarchy from {}: {}", path.display(), e))
+    }));
+    Ok(config.hierarchy.unwrap_or(Vec::new()))
 }
 
-fn dependencies(me: &mut Value) -> Result<Vec<Value>> {
+/// Open the levels of the hierarchy that exist for the host data
+/// `me`, along with their paths, lowest importance first. `file` is
+/// the path of `me` and `project` is the directory of its project.
+fn hierarchy(levels: &[String], project: &Path, me: &Value, last_value: &Value, file: &str) -> Result<Vec<(Value, String)>> {
+    if levels.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    // Lookups can use the host's own keys as well as telemetry
+    let mut context = match *last_value {
+        Value::Object(ref o) => o.clone(),
+        _ => Map::new(),
+    };
+    if let Some(o) = me.as_object() {
+        for (k, v) in o {
+            context.insert(k.clone(), v.clone());
+        }
+    }
+    let context = Value::Object(context);
+    let host_file = fs::canonicalize(file).ok();
+
+    let mut deps = Vec::new();
+
+    for level in levels.iter().rev() {
+        // Skip levels that don't apply to this host
+        if interpolate::references(level).iter().any(|p| context.pointer(p).is_none()) {
+            continue;
+        }
+
+        let path = match try!(interpolate::interpolate_str(&context, level)) {
+            Value::String(p) => project.join(full_path(p)),
+            _ => return Err(Error::Generic(format!("Hierarchy level \"{}\" is not a path", level))),
+        };
+
+        if !path.is_file() || (host_file.is_some() && fs::canonicalize(&path).ok() == host_file) {
+            continue;
+        }
+
+        deps.push((try!(open_raw(&path)), path.to_string_lossy().into_owned()));
+    }
+
+    Ok(deps)
+}
+
+/// Open the data files that `me` includes, along with their paths.
+/// `file` is the path of `me`, if known.
+fn dependencies(me: &mut Value, file: &str) -> Result<Vec<(Value, String)>> {
     let mut deps = Vec::new();
     let mut payloads: Vec<String> = Vec::new();
 
@@ -65,14 +492,42 @@ fn dependencies(me: &mut Value) -> Result<Vec<Value>> {
                     buf.push(try!(parts.get(0).ok_or(Error::Generic("Empty payload in `_include`".into()))));
                     buf.push("data");
                     buf.push(parts.get(1).unwrap_or(&"main"));
-                    buf.set_extension("json");
 
-                    if let Ok(d) = open_raw(&buf) {
-                        deps.push(d);
+                    if let Some(p) = find_ext(buf) {
+                        if let Ok(d) = open_raw(&p) {
+                            deps.push((d, p.to_string_lossy().into_owned()));
+                        }
                     }
                     payloads.insert(0, payload.into());
                 } else {
-                    deps.push(try!(open(s)));
+                    let (optional, s) = if s.starts_with("optional:") {
+                        (true, s[9..].trim())
+                    } else {
+                        (false, s)
+                    };
+
+                    let path = if s.starts_with("./") || s.starts_with("../") {
+                        Path::new(file).parent().map(|p| p.to_owned()).unwrap_or(PathBuf::from("data")).join(s)
+                    } else {
+                        full_path(s)
+                    };
+
+                    let paths = if s.contains('*') || s.contains('?') {
+                        try!(glob(&path))
+                    } else if optional && !path.exists() {
+                        Vec::new()
+                    } else {
+                        vec![path]
+                    };
+
+                    if paths.is_empty() && !optional {
+                        return Err(Error::Generic(format!("`_include` entry \"{}\" did not match any files", s)));
+                    }
+
+                    // Matches sorted later have lower importance
+                    for p in paths.into_iter().rev() {
+                        deps.push((try!(open_raw(&p)), p.to_string_lossy().into_owned()));
+                    }
                 }
             } else {
                 return Err(Error::Generic("Non-string value in `_include`".into()));
@@ -87,59 +542,238 @@ fn dependencies(me: &mut Value) -> Result<Vec<Value>> {
     Ok(deps)
 }
 
-fn merge_values(into: Value, mut from: Value, parent_from: &Value) -> Result<Value> {
+/// Find the files matching a path with wildcards in any of its
+/// components, in alphabetical order.
+fn glob(pattern: &Path) -> Result<Vec<PathBuf>> {
+    let mut candidates = vec![PathBuf::new()];
+
+    for component in pattern.components() {
+        let part = component.as_os_str().to_string_lossy().into_owned();
+        let mut matches = Vec::new();
+
+        for dir in candidates {
+            if part.contains('*') || part.contains('?') {
+                let entries = match fs::read_dir(if dir.as_os_str().is_empty() { Path::new(".") } else { dir.as_path() }) {
+                    Ok(e) => e,
+                    Err(_) => continue,
+                };
+
+                for entry in entries {
+                    let name = try!(entry).file_name().to_string_lossy().into_owned();
+                    if !name.starts_with('.') && wildcard_match(&part, &name) {
+                        matches.push(dir.join(name));
+                    }
+                }
+            } else {
+                matches.push(dir.join(component.as_os_str()));
+            }
+        }
+
+        candidates = matches;
+    }
+
+    let mut files: Vec<PathBuf> = candidates.into_iter().filter(|p| p.is_file()).collect();
+    files.sort();
+    Ok(files)
+}
+
+/// Match a name against a pattern, where "*" matches any run of
+/// characters and "?" matches a single character.
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    let mut star = None;
+
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        }
+        else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        }
+        else if let Some((star_p, star_n)) = star {
+            p = star_p + 1;
+            n = star_n + 1;
+            star = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Find a data file with any of the supported extensions.
+fn find_ext(mut path: PathBuf) -> Option<PathBuf> {
+    for ext in EXTENSIONS {
+        path.set_extension(ext);
+        if path.is_file() {
+            return Some(path);
+        }
+    }
+
+    None
+}
+
+fn merge_values(into: Value,
+                from: Value,
+                parent_from: &Value,
+                strategy: Option<Strategy>,
+                default: Option<Strategy>,
+                path: &str,
+                origin: &Origin,
+                trace: &mut Trace) -> Result<Value>
+{
     match into {
         Value::Null |
         Value::Bool(_) |
         Value::Number(_) |
-        Value::String(_) => Ok(into),
-        Value::Array(mut a) => {
-            if from.is_array() {
-                a.append(from.as_array_mut().unwrap());
-            }
-            else if !from.is_null() {
-                a.push(from);
+        Value::String(_) => {
+            trace.clear(path);
+            trace.record(path, origin);
+            Ok(into)
+        },
+        Value::Array(a) => {
+            // Track the index of inherited items so that their
+            // origins can follow them.
+            let mut a: Vec<(Value, Option<usize>)> = a.into_iter().map(|v| (v, None)).collect();
+            let mut inherited: Vec<(Value, Option<usize>)> = match from {
+                Value::Array(f) => f.into_iter().enumerate().map(|(i, v)| (v, Some(i))).collect(),
+                Value::Null => Vec::new(),
+                f => vec![(f, None)],
+            };
+
+            match strategy.unwrap_or(Strategy::Append) {
+                Strategy::Prepend => {
+                    inherited.append(&mut a);
+                    a = inherited;
+                },
+                Strategy::Union => {
+                    a.append(&mut inherited);
+
+                    let mut unique: Vec<(Value, Option<usize>)> = Vec::new();
+                    for (v, i) in a {
+                        if !unique.iter().any(|&(ref u, _)| *u == v) {
+                            unique.push((v, i));
+                        }
+                    }
+                    a = unique;
+                },
+                _ => a.append(&mut inherited),
             }
 
+            let taken = trace.take_below(path);
+            trace.record(path, origin);
+
             let mut b = Vec::new();
+            let mut untraced = Trace::new(false);
+
+            for (index, (v, from_index)) in a.into_iter().enumerate() {
+                let item_path = format!("{}/{}", path, index);
 
-            for v in a {
-                b.push(try!(merge_values(v, Value::Null, parent_from)));
+                match from_index {
+                    Some(i) => {
+                        trace.restore_item(&taken, i, &item_path);
+                        b.push(try!(merge_values(v, Value::Null, parent_from, None, default, &item_path, origin, &mut untraced)));
+                    },
+                    None => b.push(try!(merge_values(v, Value::Null, parent_from, None, default, &item_path, origin, trace))),
+                }
             }
 
             Ok(Value::Array(b))
         },
-        Value::Object(o) => {
+        Value::Object(mut o) => {
+            let mut strategies = HashMap::new();
+            match o.remove("_merge") {
+                Some(Value::Object(m)) => for (key, s) in m {
+                    match s {
+                        Value::String(ref s) => { strategies.insert(key, try!(Strategy::parse(s))); },
+                        _ => return Err(Error::Generic(format!("Merge strategy for `{}` is not a string", key))),
+                    }
+                },
+                Some(_) => return Err(Error::Generic("Value of `_merge` is not an object".into())),
+                None => (),
+            }
+
             let mut obj = Map::new();
+            let mut deleted: Vec<String> = strategies.iter()
+                                                     .filter(|&(_, s)| *s == Strategy::Delete)
+                                                     .map(|(k, _)| k.clone())
+                                                     .collect();
+
+            if !from.is_object() {
+                trace.take_below(path);
+            }
+            trace.record(path, origin);
 
             for (mut key, mut value) in o {
+                let mut key_origin = origin.clone();
+
                 if key.ends_with("?") || key.ends_with("?!") {
                     if key.pop().unwrap() == '!' {
                         key.pop();
                         key.push('!');
                     }
 
-                    value = try!(query_value(&parent_from, value)).unwrap_or(Value::Null);
+                    value = match try!(query_value(&parent_from, value)) {
+                        Some((v, condition)) => {
+                            if condition.is_some() {
+                                key_origin.condition = condition;
+                            }
+                            v
+                        },
+                        None => Value::Null,
+                    };
                 }
 
-                let mut merge_val = Value::Null;
+                let mut key_strategy = None;
 
                 if key.ends_with("!") {
                     key.pop();
+                    key_strategy = Some(Strategy::Replace);
+                }
+                else if let Some(i) = key.rfind('|') {
+                    // Keys can contain "|" without naming a strategy
+                    let suffix = Strategy::parse(&key[i + 1..]).ok();
+                    if suffix.is_some() {
+                        key_strategy = suffix;
+                        key.truncate(i);
+                    }
+                }
+
+                let key_strategy = key_strategy.or(strategies.get(&key).cloned()).or(default);
+                let key_path = format!("{}/{}", path, key.replace("~", "~0").replace("/", "~1"));
+
+                if key_strategy == Some(Strategy::Delete) {
+                    deleted.push(key);
+                    continue;
+                }
+
+                let mut merge_val = Value::Null;
+
+                if key_strategy == Some(Strategy::Replace) {
+                    trace.clear(&key_path);
                 }
                 else if let Some(o1) = from.get(&key) {
                     merge_val = o1.clone();
                 }
 
-                value = try!(merge_values(value, merge_val, &parent_from));
+                value = try!(merge_values(value, merge_val, &parent_from, key_strategy, default, &key_path, &key_origin, trace));
 
                 obj.insert(key, value);
             }
 
+            for key in &deleted {
+                trace.clear(&format!("{}/{}", path, key.replace("~", "~0").replace("/", "~1")));
+            }
+
             // Insert any missing values
             if let Some(o1) = from.as_object() {
                 for (key, value) in o1 {
-                    if !obj.contains_key(key) {
+                    if !obj.contains_key(key) && !deleted.contains(key) {
                         obj.insert(key.clone(), value.clone());
                     }
                 }
@@ -150,7 +784,9 @@ fn merge_values(into: Value, mut from: Value, parent_from: &Value) -> Result<Val
     }
 }
 
-fn query_value(data: &Value, value: Value) -> Result<Option<Value>> {
+/// Find the first option that matches its query, returning its value
+/// and the query that matched.
+fn query_value(data: &Value, value: Value) -> Result<Option<(Value, Option<String>)>> {
     match value {
         Value::Array(a) => {
             for opt in a {
@@ -161,21 +797,18 @@ fn query_value(data: &Value, value: Value) -> Result<Option<Value>> {
         },
         Value::Object(mut o) => {
             if let Some(v) = o.remove("_") {
-                if let Some(q) = o.get("?") {
-                    match *q {
-                        Value::String(ref s) => {
-                            if try!(condition::eval(data, s)) {
-                                return Ok(Some(v));
-                            }
-                        },
-                        _ => return Err(Error::Generic("Query must be string".into())),
-                    };
-                } else {
-                    return Ok(Some(v));
+                match o.remove("?") {
+                    Some(Value::String(s)) => {
+                        if try!(condition::eval(data, &s)) {
+                            return Ok(Some((v, Some(s))));
+                        }
+                    },
+                    Some(_) => return Err(Error::Generic("Query must be string".into())),
+                    None => return Ok(Some((v, None))),
                 }
             }
         },
-        _ => return Ok(Some(value)),
+        _ => return Ok(Some((value, None))),
     }
 
     Ok(None)
@@ -214,6 +847,297 @@ mod tests {
         assert_eq!(value, expected_value);
     }
 
+    #[test]
+    fn test_formats() {
+        let tempdir = TempDir::new("formats_test").unwrap();
+        let path = tempdir.path();
+        fs::create_dir_all(path.join("payloads/payload/data")).unwrap();
+
+        let bottom = path.join("bottom.toml");
+        let mut fh = fs::File::create(&bottom).unwrap();
+        fh.write_all(b"d = 4\n\n[payload]\nb = [3]\n").unwrap();
+
+        let mut fh = fs::File::create(path.join("payloads/payload/data/main.yml")).unwrap();
+        fh.write_all(b"pvalue: payload\n").unwrap();
+
+        let top = path.join("top.yaml");
+        let mut fh = fs::File::create(&top).unwrap();
+        fh.write_all(format!("a: 1
+payload:
+  b: [1, 2]
+  c?:
+    - _: [5]
+      "?": /d = 4
+_include:
+  - {}
+  - \"payload: {}\"
+", bottom.display(), path.join("payloads/payload").display()).as_bytes()).unwrap();
+
+        let value = merge(open(&top).unwrap(), Value::Null).unwrap();
+        assert_eq!(value["a"], json!(1));
+        assert_eq!(value["d"], json!(4));
+        assert_eq!(value["payload"]["b"], json!([1, 2, 3]));
+        assert_eq!(value["payload"]["c"], json!([5]));
+        assert_eq!(value["pvalue"], json!("payload"));
+    }
+
+    #[test]
+    fn test_includes() {
+        let tempdir = TempDir::new("includes_test").unwrap();
+        let path = tempdir.path();
+        fs::create_dir_all(path.join("hosts")).unwrap();
+        fs::create_dir_all(path.join("roles")).unwrap();
+
+        let write = |name: &str, data: Value| {
+            let mut fh = fs::File::create(path.join(name)).unwrap();
+            fh.write_all(data.to_string().as_bytes()).unwrap();
+        };
+
+        write("roles/a.json", json!({ "a": 1, "list": [ "a" ] }));
+        write("roles/b.json", json!({ "a": 2, "b": 2, "list": [ "b" ] }));
+        write("common.json", json!({ "common": true }));
+        write("hosts/web.json", json!({
+            "_include": [ "../common.json", path.join("roles/*.json").to_str().unwrap(), "optional: ./missing.json" ],
+        }));
+
+        let web = path.join("hosts/web.json");
+        let value = merge_from(open(&web).unwrap(), &web, Value::Null).unwrap();
+        assert_eq!(value["common"], json!(true));
+        assert_eq!(value["a"], json!(1));
+        assert_eq!(value["b"], json!(2));
+        assert_eq!(value["list"], json!([ "a", "b" ]));
+
+        write("hosts/missing.json", json!({ "_include": [ "./nope.json" ] }));
+        let missing = path.join("hosts/missing.json");
+        assert!(merge_from(open(&missing).unwrap(), &missing, Value::Null).is_err());
+
+        write("hosts/loop1.json", json!({ "_include": [ "./loop2.json" ] }));
+        write("hosts/loop2.json", json!({ "_include": [ "./loop1.json" ] }));
+        let loop1 = path.join("hosts/loop1.json");
+        match merge_from(open(&loop1).unwrap(), &loop1, Value::Null) {
+            Err(Error::Generic(e)) => assert!(e.starts_with("Circular `_include`")),
+            _ => panic!("Expected a circular include error"),
+        }
+
+        assert!(wildcard_match("*.json", "web.json"));
+        assert!(wildcard_match("w?b*", "web.json"));
+        assert!(!wildcard_match("*.json", "web.yaml"));
+    }
+
+    #[test]
+    fn test_hierarchy() {
+        let tempdir = TempDir::new("hierarchy_test").unwrap();
+        let path = tempdir.path();
+        fs::create_dir_all(path.join("env")).unwrap();
+        fs::create_dir_all(path.join("os")).unwrap();
+
+        for name in &[ "env/prod.json", "os/linux.json", "host.json" ] {
+            let mut fh = fs::File::create(path.join(name)).unwrap();
+            fh.write_all(b"{}").unwrap();
+        }
+
+        let dir = path.to_str().unwrap();
+        let levels = vec![
+            format!("{}/host.json", dir),
+            format!("{}/env/${{/environment}}.json", dir),
+            format!("{}/role/${{/role}}.json", dir),
+            format!("{}/os/${{/_telemetry/os/platform}}.json", dir),
+            format!("{}/os/missing.json", dir),
+        ];
+        let me = json!({ "environment": "prod" });
+        let telemetry = json!({ "_telemetry": { "os": { "platform": "linux" } } });
+
+        let host = path.join("host.json");
+        let deps = hierarchy(&levels, Path::new(""), &me, &telemetry, host.to_str().unwrap()).unwrap();
+        let files: Vec<&str> = deps.iter().map(|&(_, ref f)| f.as_str()).collect();
+        assert_eq!(files, vec![
+            path.join("os/linux.json").to_str().unwrap(),
+            path.join("env/prod.json").to_str().unwrap(),
+        ]);
+
+        let project = Path::new("");
+        assert!(hierarchy(&[ "${/environment}".to_string() ], project, &json!({ "environment": 1 }), &Value::Null, "").is_err());
+        assert!(hierarchy(&[ "env/${/environment.json".to_string() ], project, &me, &telemetry, "").is_err());
+        assert!(hierarchy(&[ "env/${/environment}.json".to_string() ], project, &json!({ "environment": "${/stage" }), &Value::Null, "").is_err());
+    }
+
+    #[test]
+    fn test_merge_hierarchy() {
+        let tempdir = TempDir::new("merge_hierarchy_test").unwrap();
+        let path = tempdir.path();
+        fs::create_dir_all(path.join("data/env")).unwrap();
+        fs::create_dir_all(path.join("data/hosts")).unwrap();
+
+        let write = |name: &str, data: Value| {
+            let mut fh = fs::File::create(path.join(name)).unwrap();
+            fh.write_all(data.to_string().as_bytes()).unwrap();
+        };
+
+        write("project.json", json!({
+            "language": "Rust",
+            "auth_server": "127.0.0.1",
+            "auth_api_port": 7101,
+            "auth_update_port": 7102,
+            "hierarchy": [ "env/${/environment}.json", "role/${/role}.json", "common.json" ],
+        }));
+        write("data/common.json", json!({ "a": "common", "b": "common", "c": "common", "d": "common" }));
+        write("data/env/prod.json", json!({ "a": "prod", "b": "prod", "c": "prod" }));
+        write("data/hosts/base.json", json!({ "a": "include", "b": "include" }));
+        write("data/hosts/web.json", json!({
+            "_include": [ "./base.json" ],
+            "environment": "prod",
+            "a": "host",
+        }));
+
+        let web = path.join("data/hosts/web.json");
+        let value = merge_from(open(&web).unwrap(), &web, Value::Null).unwrap();
+        assert_eq!(value["a"], json!("host"));
+        assert_eq!(value["b"], json!("include"));
+        assert_eq!(value["c"], json!("prod"));
+        assert_eq!(value["d"], json!("common"));
+
+        // Projects without Auth settings can still have a hierarchy
+        write("project.json", json!({ "hierarchy": [ "common.json" ] }));
+        let value = merge_from(open(&web).unwrap(), &web, Value::Null).unwrap();
+        assert_eq!(value["a"], json!("host"));
+        assert_eq!(value["c"], json!("common"));
+        assert_eq!(value["d"], json!("common"));
+
+        write("project.json", json!({ "language": "Rust" }));
+        let value = merge_from(open(&web).unwrap(), &web, Value::Null).unwrap();
+        assert!(value.get("d").is_none());
+
+        write("project.json", json!({ "hierarchy": "common.json" }));
+        assert!(merge_from(open(&web).unwrap(), &web, Value::Null).is_err());
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Nginx {
+        port: u16,
+        sites: Vec<String>,
+    }
+
+    #[test]
+    fn test_get() {
+        let data = json!({
+            "nginx": { "port": 80, "sites": [ "example.com" ] },
+            "bad": { "port": "eighty", "sites": [] },
+        });
+
+        let nginx: Nginx = get(&data, "/nginx").unwrap();
+        assert_eq!(nginx, Nginx { port: 80, sites: vec!["example.com".into()] });
+        assert_eq!(get::<u16>(&data, "/nginx/port").unwrap(), 80);
+
+        match get::<Nginx>(&data, "/bad") {
+            Err(Error::Data(e)) => {
+                assert_eq!(e.pointer, "/bad/port");
+                assert_eq!(e.expected, Some("u16".into()));
+                assert_eq!(e.actual, Some("string".into()));
+                assert!(e.message.contains("expected u16"));
+            },
+            _ => panic!("Expected a data error"),
+        }
+
+        match get::<Nginx>(&data, "/nginx/port") {
+            Err(Error::Data(e)) => {
+                assert_eq!(e.pointer, "/nginx/port");
+                assert_eq!(e.expected, Some("struct Nginx".into()));
+                assert_eq!(e.actual, Some("number".into()));
+            },
+            _ => panic!("Expected a data error"),
+        }
+
+        match get::<Vec<u16>>(&json!({ "ports": [ 80, "https" ] }), "/ports") {
+            Err(Error::Data(e)) => assert_eq!(e.pointer, "/ports/1"),
+            _ => panic!("Expected a data error"),
+        }
+
+        assert!(get::<Nginx>(&data, "/missing").is_err());
+    }
+
+    #[test]
+    fn test_merge_strategies() {
+        let inherited = json!({
+            "append": [ 2, 3 ],
+            "prepend": [ 2, 3 ],
+            "union": [ 2, 3 ],
+            "replace": { "a": 1 },
+            "delete": 1,
+            "nested": { "a": [ 1 ], "b": 2, "c": 3 },
+        });
+
+        let value = merge(json!({
+            "append": [ 1, 2 ],
+            "prepend|prepend": [ 1, 2 ],
+            "union|union": [ 1, 2 ],
+            "replace|replace": { "b": 2 },
+            "delete|delete": null,
+            "nested": {
+                "_merge": { "a": "replace", "c": "delete" },
+                "a": [ 2 ],
+            },
+        }), inherited.clone()).unwrap();
+
+        assert_eq!(value, json!({
+            "append": [ 1, 2, 2, 3 ],
+            "prepend": [ 2, 3, 1, 2 ],
+            "union": [ 1, 2, 3 ],
+            "replace": { "b": 2 },
+            "nested": { "a": [ 2 ], "b": 2 },
+        }));
+
+        let value = merge(json!({
+            "_merge_default": "union",
+            "append|append": [ 1, 2 ],
+            "union": [ 1, 2 ],
+            "nested": { "a": [ 1, 4 ] },
+        }), inherited.clone()).unwrap();
+
+        assert_eq!(value["append"], json!([ 1, 2, 2, 3 ]));
+        assert_eq!(value["union"], json!([ 1, 2, 3 ]));
+        assert_eq!(value["nested"]["a"], json!([ 1, 4 ]));
+        assert!(value.get("_merge_default").is_none());
+
+        let value = merge(json!({ "a|b": 1, "c|union|d": [ 2 ], "e|f|union": [ 3 ] }), json!({ "e|f": [ 3, 4 ] })).unwrap();
+        assert_eq!(value, json!({ "a|b": 1, "c|union|d": [ 2 ], "e|f": [ 3, 4 ] }));
+        assert!(merge(json!({ "_merge": [] }), Value::Null).is_err());
+        assert!(merge(json!({ "_merge_default": "delete" }), Value::Null).is_err());
+    }
+
+    #[test]
+    fn test_merge_trace() {
+        let tempdir = TempDir::new("trace_test").unwrap();
+        let bottom = tempdir.path().join("bottom.json");
+        let top = tempdir.path().join("top.json");
+
+        let mut fh = fs::File::create(&bottom).unwrap();
+        fh.write_all(json!({
+            "port": 80,
+            "list": [ 1 ],
+            "name": "default",
+        }).to_string().as_bytes()).unwrap();
+
+        let mut fh = fs::File::create(&top).unwrap();
+        fh.write_all(json!({
+            "_include": [ bottom.to_str().unwrap() ],
+            "list": [ 2 ],
+            "tls?": [{ "_": true, "?": "/port = 80" }],
+            "name": "web",
+        }).to_string().as_bytes()).unwrap();
+
+        let (value, trace) = merge_trace(open(&top).unwrap(), &top, Value::Null).unwrap();
+        assert_eq!(value["list"], json!([ 2, 1 ]));
+
+        let top = Origin { file: top.to_str().unwrap().into(), condition: None };
+        let bottom = Origin { file: bottom.to_str().unwrap().into(), condition: None };
+        assert_eq!(trace.get("/port"), Some(&bottom));
+        assert_eq!(trace.get("/name"), Some(&top));
+        assert_eq!(trace.get("/list/0"), Some(&top));
+        assert_eq!(trace.get("/list/1"), Some(&bottom));
+        assert_eq!(trace.get("/tls").unwrap().condition, Some("/port = 80".into()));
+        assert!(trace.get("/missing").is_none());
+    }
+
     fn create_data(path: &mut PathBuf) -> Value {
         let mut fh = fs::File::create(format!("{}/data/middle.json", path.display())).unwrap();
         let payload_path = format!("{}/payloads/payload::default", path.display());
@@ -0,0 +1,263 @@
+// Copyright 2015-2017 Intecture Developers. See the COPYRIGHT file at the
+// top-level directory of this distribution and at
+// https://intecture.io/COPYRIGHT.
+//
+// Licensed under the Mozilla Public License 2.0 <LICENSE or
+// https://www.tldrlegal.com/l/mpl-2.0>. This file may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Validation of data against a JSON Schema.
+//!
+//! A subset of JSON Schema is supported: "type", "enum",
+//! "properties", "required", "additionalProperties", "items",
+//! "minItems", "maxItems", "minimum", "maximum", "minLength",
+//! "maxLength" and "pattern". Other keywords are ignored.
+//!
+//! Keys starting with "_", such as "_telemetry", are never reported
+//! as additional properties.
+
+use error::{Error, Result};
+use regex::Regex;
+use serde_json::{self, Map, Value};
+use std::fmt;
+
+/// A value that doesn't match its schema.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Violation {
+    /// JSON pointer to the offending value
+    pub pointer: String,
+    /// What is wrong with the value
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let pointer = if self.pointer.is_empty() { "/" } else { &self.pointer };
+        write!(f, "{}: {}", pointer, self.message)
+    }
+}
+
+/// Validate `data` against `schema`, returning every violation.
+/// Returns an error if the schema itself is invalid.
+pub fn validate(schema: &Value, data: &Value) -> Result<Vec<Violation>> {
+    let mut violations = Vec::new();
+    try!(check(schema, data, "", &mut violations));
+    Ok(violations)
+}
+
+fn check(schema: &Value, value: &Value, pointer: &str, violations: &mut Vec<Violation>) -> Result<()> {
+    let schema = match *schema {
+        Value::Object(ref o) => o,
+        Value::Bool(true) => return Ok(()),
+        Value::Bool(false) => {
+            violate(violations, pointer, "no value is allowed here".into());
+            return Ok(());
+        },
+        _ => return Err(Error::Generic(format!("Schema for `{}` is not an object", pointer))),
+    };
+
+    if let Some(t) = schema.get("type") {
+        let types: Vec<&str> = match *t {
+            Value::String(ref s) => vec![s.as_str()],
+            Value::Array(ref a) => a.iter().filter_map(|t| t.as_str()).collect(),
+            _ => return Err(Error::Generic(format!("Schema type for `{}` is not a string or array", pointer))),
+        };
+
+        if !types.iter().any(|t| is_type(value, t)) {
+            violate(violations, pointer, format!("expected {}, found {}", types.join(" or "), type_name(value)));
+            return Ok(());
+        }
+    }
+
+    if let Some(e) = schema.get("enum") {
+        match *e {
+            Value::Array(ref a) => if !a.contains(value) {
+                violate(violations, pointer, format!("expected one of {}", try!(serde_json::to_string(e))));
+            },
+            _ => return Err(Error::Generic(format!("Schema enum for `{}` is not an array", pointer))),
+        }
+    }
+
+    match *value {
+        Value::Object(ref o) => try!(check_object(schema, o, pointer, violations)),
+        Value::Array(ref a) => {
+            if let Some(min) = schema.get("minItems").and_then(|m| m.as_u64()) {
+                if (a.len() as u64) < min {
+                    violate(violations, pointer, format!("expected at least {} items, found {}", min, a.len()));
+                }
+            }
+
+            if let Some(max) = schema.get("maxItems").and_then(|m| m.as_u64()) {
+                if a.len() as u64 > max {
+                    violate(violations, pointer, format!("expected at most {} items, found {}", max, a.len()));
+                }
+            }
+
+            match schema.get("items") {
+                Some(&Value::Array(ref schemas)) => for (i, (s, v)) in schemas.iter().zip(a).enumerate() {
+                    try!(check(s, v, &format!("{}/{}", pointer, i), violations));
+                },
+                Some(s) => for (i, v) in a.iter().enumerate() {
+                    try!(check(s, v, &format!("{}/{}", pointer, i), violations));
+                },
+                None => (),
+            }
+        },
+        Value::String(ref s) => {
+            let len = s.chars().count() as u64;
+
+            if let Some(min) = schema.get("minLength").and_then(|m| m.as_u64()) {
+                if len < min {
+                    violate(violations, pointer, format!("expected at least {} characters, found {}", min, len));
+                }
+            }
+
+            if let Some(max) = schema.get("maxLength").and_then(|m| m.as_u64()) {
+                if len > max {
+                    violate(violations, pointer, format!("expected at most {} characters, found {}", max, len));
+                }
+            }
+
+            if let Some(pattern) = schema.get("pattern").and_then(|p| p.as_str()) {
+                if !try!(Regex::new(pattern)).is_match(s) {
+                    violate(violations, pointer, format!("expected a match for pattern `{}`", pattern));
+                }
+            }
+        },
+        Value::Number(ref n) => {
+            let n = n.as_f64().unwrap_or(0.0);
+
+            if let Some(min) = schema.get("minimum").and_then(|m| m.as_f64()) {
+                if n < min {
+                    violate(violations, pointer, format!("expected at least {}, found {}", min, n));
+                }
+            }
+
+            if let Some(max) = schema.get("maximum").and_then(|m| m.as_f64()) {
+                if n > max {
+                    violate(violations, pointer, format!("expected at most {}, found {}", max, n));
+                }
+            }
+        },
+        _ => (),
+    }
+
+    Ok(())
+}
+
+fn check_object(schema: &Map<String, Value>, object: &Map<String, Value>, pointer: &str, violations: &mut Vec<Violation>) -> Result<()> {
+    if let Some(required) = schema.get("required").and_then(|r| r.as_array()) {
+        for key in required.iter().filter_map(|k| k.as_str()) {
+            if !object.contains_key(key) {
+                violate(violations, pointer, format!("missing required key \"{}\"", key));
+            }
+        }
+    }
+
+    let properties = schema.get("properties").and_then(|p| p.as_object());
+
+    for (key, value) in object {
+        let child = format!("{}/{}", pointer, key.replace("~", "~0").replace("/", "~1"));
+
+        match properties.and_then(|p| p.get(key)) {
+            Some(s) => try!(check(s, value, &child, violations)),
+            None => match schema.get("additionalProperties") {
+                Some(&Value::Bool(false)) if !key.starts_with('_') => violate(violations, &child, "unknown key".into()),
+                Some(s) if s.is_object() => try!(check(s, value, &child, violations)),
+                _ => (),
+            },
+        }
+    }
+
+    Ok(())
+}
+
+fn violate(violations: &mut Vec<Violation>, pointer: &str, message: String) {
+    violations.push(Violation {
+        pointer: pointer.into(),
+        message: message,
+    });
+}
+
+fn is_type(value: &Value, t: &str) -> bool {
+    match t {
+        "null" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64() || value.as_f64().map(|f| f.fract() == 0.0).unwrap_or(false),
+        _ => false,
+    }
+}
+
+/// Get the JSON Schema type name of a value.
+pub fn type_name(value: &Value) -> &'static str {
+    match *value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let schema = json!({
+            "type": "object",
+            "required": [ "nginx" ],
+            "properties": {
+                "nginx": {
+                    "type": "object",
+                    "required": [ "port" ],
+                    "additionalProperties": false,
+                    "properties": {
+                        "port": { "type": "integer", "minimum": 1, "maximum": 65535 },
+                        "user": { "type": "string", "pattern": "^[a-z]+$" },
+                        "sites": { "type": "array", "items": { "type": "string" }, "minItems": 1 },
+                        "mode": { "enum": [ "dev", "prod" ] }
+                    }
+                }
+            }
+        });
+
+        let data = json!({
+            "_telemetry": {},
+            "nginx": {
+                "port": 80,
+                "user": "www",
+                "sites": [ "example.com" ],
+                "mode": "prod"
+            }
+        });
+        assert!(validate(&schema, &data).unwrap().is_empty());
+
+        let data = json!({
+            "nginx": {
+                "prot": 80,
+                "user": "WWW",
+                "sites": [ "example.com", 1 ],
+                "mode": "test"
+            }
+        });
+        let violations = validate(&schema, &data).unwrap();
+        let messages: Vec<String> = violations.iter().map(|v| v.to_string()).collect();
+        assert_eq!(messages, vec![
+            "/nginx: missing required key \"port\"",
+            "/nginx/mode: expected one of [\"dev\",\"prod\"]",
+            "/nginx/prot: unknown key",
+            "/nginx/sites/1: expected string, found number",
+            "/nginx/user: expected a match for pattern `^[a-z]+$`",
+        ]);
+
+        assert!(validate(&json!({ "pattern": "(" }), &json!("a")).is_err());
+        assert_eq!(validate(&json!({ "required": [ "a" ] }), &json!({})).unwrap()[0].to_string(), "/: missing required key \"a\"");
+    }
+}
@@ -0,0 +1,335 @@
+// Copyright 2015-2017 Intecture Developers. See the COPYRIGHT file at the
+// top-level directory of this distribution and at
+// https://intecture.io/COPYRIGHT.
+//
+// Licensed under the Mozilla Public License 2.0 <LICENSE or
+// https://www.tldrlegal.com/l/mpl-2.0>. This file may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Encrypted values in data files.
+//!
+//! Any value can be stored encrypted as an object with a single
+//! "_secret" key, e.g. `{ "_secret": "<ciphertext>" }`. Secrets are
+//! decrypted when their data file is opened, using the key in
+//! `secret.key`. Keep this file out of version control.
+//!
+//! Decrypted secrets are redacted from error messages and from the
+//! `Debug` output of command results. Host data is a plain `Value`,
+//! so use `redact_value` before logging it.
+//!
+//! Numbers, booleans and strings shorter than four characters are
+//! too likely to appear in unrelated text to be replaced within it.
+//! These secrets are only redacted by `redact_value`, where a whole
+//! value matches them.
+
+use error::{Error, Result};
+use rustc_serialize::base64::{FromBase64, ToBase64, STANDARD};
+use serde_json::{self, Map, Value};
+use sodiumoxide;
+use sodiumoxide::crypto::secretbox;
+use std::fmt;
+use std::fs;
+use std::io::{Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Path of the key used to decrypt secrets in data files.
+pub const DEFAULT_KEY_PATH: &'static str = "secret.key";
+/// Replacement for redacted secrets
+const REDACTED: &'static str = "[REDACTED]";
+/// Minimum length of a secret string that is redacted within text
+const MIN_REDACT_LEN: usize = 4;
+
+lazy_static! {
+    /// Plaintext of every secret string decrypted by this process
+    /// that is long enough to redact within text, longest first.
+    static ref SECRETS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+    /// Every other secret scalar, which is only redacted as a whole
+    /// value.
+    static ref SECRET_VALUES: Mutex<Vec<Value>> = Mutex::new(Vec::new());
+}
+
+/// Key for encrypting and decrypting secrets in data files.
+///
+///# Examples
+///
+/// Create a key, then encrypt a password to paste into a data file:
+///
+/// ```no_run
+/// # use inapi::{SecretKey, Value};
+///let key = SecretKey::generate();
+///key.save("secret.key").unwrap();
+///
+///let secret = key.encrypt(&Value::String("hunter2".into())).unwrap();
+///println!("\"db_password\": {}", secret);
+/// ```
+pub struct SecretKey {
+    key: secretbox::Key,
+}
+
+impl SecretKey {
+    /// Generate a new random key.
+    pub fn generate() -> SecretKey {
+        sodiumoxide::init();
+
+        SecretKey {
+            key: secretbox::gen_key(),
+        }
+    }
+
+    /// Load a key from a file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<SecretKey> {
+        let mut fh = try!(fs::File::open(path.as_ref()));
+        let mut encoded = String::new();
+        try!(fh.read_to_string(&mut encoded));
+
+        let bytes = try!(encoded.trim().from_base64()
+                                .map_err(|_| Error::Generic(format!("Secret key {} is not valid base64", path.as_ref().display()))));
+
+        match secretbox::Key::from_slice(&bytes) {
+            Some(key) => Ok(SecretKey { key: key }),
+            None => Err(Error::Generic(format!("Secret key {} must be {} bytes long", path.as_ref().display(), secretbox::KEYBYTES))),
+        }
+    }
+
+    /// Save the key to a file that only the current user can read.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut fh = try!(fs::OpenOptions::new()
+                                          .write(true)
+                                          .create(true)
+                                          .truncate(true)
+                                          .mode(0o600)
+                                          .open(path));
+        try!(fh.write_all(self.key.0.to_base64(STANDARD).as_bytes()));
+        Ok(())
+    }
+
+    /// Encrypt a value, returning the `{ "_secret": "<ciphertext>" }`
+    /// object to store in a data file.
+    pub fn encrypt(&self, value: &Value) -> Result<Value> {
+        let plaintext = try!(serde_json::to_vec(value));
+        let nonce = secretbox::gen_nonce();
+
+        let mut bytes = nonce.0.to_vec();
+        bytes.extend(secretbox::seal(&plaintext, &nonce, &self.key));
+
+        let mut secret = Map::new();
+        secret.insert("_secret".into(), Value::String(bytes.to_base64(STANDARD)));
+        Ok(Value::Object(secret))
+    }
+
+    /// Decrypt the ciphertext of a secret. The decrypted value is
+    /// redacted from then on.
+    pub fn decrypt(&self, ciphertext: &str) -> Result<Value> {
+        let bytes = try!(ciphertext.from_base64().map_err(|_| Error::Generic("Secret is not valid base64".into())));
+        if bytes.len() < secretbox::NONCEBYTES {
+            return Err(Error::Generic("Secret is too short".into()));
+        }
+
+        let (nonce, ciphertext) = bytes.split_at(secretbox::NONCEBYTES);
+        let nonce = secretbox::Nonce::from_slice(nonce).unwrap();
+        let plaintext = try!(secretbox::open(ciphertext, &nonce, &self.key)
+                                      .map_err(|_| Error::Generic("Could not decrypt secret. Was it encrypted with a different key?".into())));
+
+        let value = try!(serde_json::from_slice(&plaintext));
+        register(&value);
+        Ok(value)
+    }
+}
+
+impl fmt::Debug for SecretKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SecretKey({})", REDACTED)
+    }
+}
+
+/// Decrypt every secret in `value`. `key` is loaded from
+/// `DEFAULT_KEY_PATH` when the first secret is found.
+pub fn decrypt_all(value: &mut Value, key: &mut Option<SecretKey>) -> Result<()> {
+    if let Some(ciphertext) = try!(secret_ciphertext(value)) {
+        if key.is_none() {
+            *key = Some(try!(SecretKey::load(DEFAULT_KEY_PATH).map_err(|e| {
+                Error::Generic(format!("Could not load {} to decrypt secrets: {}", DEFAULT_KEY_PATH, e))
+            })));
+        }
+
+        *value = try!(key.as_ref().unwrap().decrypt(&ciphertext));
+        return Ok(());
+    }
+
+    match *value {
+        Value::Array(ref mut a) => for v in a.iter_mut() {
+            try!(decrypt_all(v, key));
+        },
+        Value::Object(ref mut o) => for (_, v) in o.iter_mut() {
+            try!(decrypt_all(v, key));
+        },
+        _ => (),
+    }
+
+    Ok(())
+}
+
+fn secret_ciphertext(value: &Value) -> Result<Option<String>> {
+    if let Value::Object(ref o) = *value {
+        if o.len() == 1 {
+            match o.get("_secret") {
+                Some(&Value::String(ref s)) => return Ok(Some(s.clone())),
+                Some(_) => return Err(Error::Generic("Value of `_secret` is not a string".into())),
+                None => (),
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+fn register(value: &Value) {
+    match *value {
+        Value::String(ref s) if s.chars().count() >= MIN_REDACT_LEN => {
+            let mut secrets = SECRETS.lock().unwrap_or_else(|e| e.into_inner());
+            if !secrets.contains(s) {
+                secrets.push(s.clone());
+                secrets.sort_by(|a, b| b.len().cmp(&a.len()));
+            }
+        },
+        Value::Array(ref a) => for v in a {
+            register(v);
+        },
+        Value::Object(ref o) => for v in o.values() {
+            register(v);
+        },
+        Value::Null => (),
+        Value::String(ref s) if s.is_empty() => (),
+        _ => {
+            let mut values = SECRET_VALUES.lock().unwrap_or_else(|e| e.into_inner());
+            if !values.contains(value) {
+                values.push(value.clone());
+            }
+        },
+    }
+}
+
+/// Replace any decrypted secrets in a string with "[REDACTED]".
+/// Secrets that are only redacted as whole values are left alone.
+pub fn redact(s: &str) -> String {
+    let secrets = SECRETS.lock().unwrap_or_else(|e| e.into_inner());
+    let mut redacted = s.to_owned();
+
+    for secret in secrets.iter() {
+        if redacted.contains(secret.as_str()) {
+            redacted = redacted.replace(secret.as_str(), REDACTED);
+        }
+    }
+
+    redacted
+}
+
+/// Copy a value, replacing any decrypted secrets with "[REDACTED]".
+pub fn redact_value(value: &Value) -> Value {
+    if is_secret_value(value) {
+        return Value::String(REDACTED.into());
+    }
+
+    match *value {
+        Value::String(ref s) => Value::String(redact(s)),
+        Value::Array(ref a) => Value::Array(a.iter().map(redact_value).collect()),
+        Value::Object(ref o) => {
+            let mut obj = Map::new();
+            for (k, v) in o {
+                obj.insert(k.clone(), redact_value(v));
+            }
+            Value::Object(obj)
+        },
+        _ => value.clone(),
+    }
+}
+
+fn is_secret_value(value: &Value) -> bool {
+    match *value {
+        Value::Array(_) | Value::Object(_) => false,
+        _ => SECRET_VALUES.lock().unwrap_or_else(|e| e.into_inner()).contains(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use error::DataError;
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_encrypt_decrypt() {
+        let dir = TempDir::new("test_secret").unwrap();
+        let path = dir.path().join("secret.key");
+
+        SecretKey::generate().save(&path).unwrap();
+        let key = SecretKey::load(&path).unwrap();
+        assert_eq!(format!("{:?}", key), "SecretKey([REDACTED])");
+
+        let mut data = json!({
+            "db": {
+                "user": "app",
+                "password": key.encrypt(&json!("correct horse")).unwrap(),
+            },
+            "tokens": [ key.encrypt(&json!({ "api": "abc123xyz" })).unwrap() ],
+        });
+
+        decrypt_all(&mut data, &mut Some(key)).unwrap();
+        assert_eq!(data["db"]["password"], json!("correct horse"));
+        assert_eq!(data["tokens"][0]["api"], json!("abc123xyz"));
+
+        assert_eq!(redact("Login failed for app:correct horse"), "Login failed for app:[REDACTED]");
+        assert_eq!(redact_value(&data)["db"], json!({ "user": "app", "password": "[REDACTED]" }));
+
+        let other = SecretKey::generate();
+        let mut data = json!({ "a": SecretKey::generate().encrypt(&json!(1)).unwrap() });
+        assert!(decrypt_all(&mut data, &mut Some(other)).is_err());
+        assert!(decrypt_all(&mut json!({ "_secret": 1 }), &mut None).is_err());
+    }
+
+    #[test]
+    fn test_redact_error() {
+        let key = SecretKey::generate();
+        let mut data = json!({ "password": key.encrypt(&json!("pa\"ss\\w0rd")).unwrap() });
+        decrypt_all(&mut data, &mut Some(key)).unwrap();
+
+        let err = Error::Agent("mysql: access denied for pa\"ss\\w0rd".into());
+        assert!(!format!("{}", err).contains("w0rd"));
+        assert!(!format!("{:?}", err).contains("w0rd"));
+        assert_eq!(format!("{:?}", err), r#"Agent("mysql: access denied for [REDACTED]")"#);
+
+        let err = Error::Data(DataError::new("/db/password", "pa\"ss\\w0rd is not a number"));
+        assert!(!format!("{:?}", err).contains("w0rd"));
+    }
+
+    #[test]
+    fn test_redact_short_secrets() {
+        let key = SecretKey::generate();
+        let mut data = json!({
+            "pin": key.encrypt(&json!(42)).unwrap(),
+            "enabled": key.encrypt(&json!(true)).unwrap(),
+            "code": key.encrypt(&json!("xy")).unwrap(),
+            "token": key.encrypt(&json!("s3cr3t-t0ken")).unwrap(),
+        });
+        decrypt_all(&mut data, &mut Some(key)).unwrap();
+
+        assert_eq!(redact("port 4242, xyz, true, s3cr3t-t0ken"), "port 4242, xyz, true, [REDACTED]");
+        assert_eq!(redact_value(&json!({
+            "pin": 42,
+            "port": 4242,
+            "enabled": true,
+            "debug": false,
+            "code": "xy",
+            "name": "xyz",
+        })), json!({
+            "pin": "[REDACTED]",
+            "port": 4242,
+            "enabled": "[REDACTED]",
+            "debug": false,
+            "code": "[REDACTED]",
+            "name": "xyz",
+        }));
+    }
+}
@@ -10,9 +10,9 @@
 
 use error::{Error, self};
 use ffi_helpers::{Ffi__Array, Leaky};
-use libc::{c_char, int8_t};
+use libc::{c_char, int8_t, uint8_t};
 #[cfg(feature = "remote-run")]
-use libc::{uint8_t, uint32_t};
+use libc::uint32_t;
 use serde_json::Value;
 use std::{mem, ptr};
 use std::ffi::CString;
@@ -32,7 +32,6 @@ pub enum Ffi__DataType {
     Object,
 }
 
-#[cfg(feature = "local-run")]
 #[no_mangle]
 pub extern "C" fn host_local(path_ptr: *const c_char) -> *mut Host {
     let path = if path_ptr.is_null() {
@@ -45,7 +44,6 @@ pub extern "C" fn host_local(path_ptr: *const c_char) -> *mut Host {
     Box::into_raw(Box::new(host))
 }
 
-#[cfg(feature = "remote-run")]
 #[no_mangle]
 pub extern "C" fn host_connect(path_ptr: *const c_char) -> *mut Host {
     let path = trynull!(ptrtostr!(path_ptr, "path string"));
@@ -79,7 +77,18 @@ pub extern "C" fn host_data(host_ptr: *mut Host) -> *const c_void {
     data_ref as *const c_void
 }
 
-#[cfg(feature = "remote-run")]
+#[no_mangle]
+pub extern "C" fn host_supports(host_ptr: *const Host, capability_ptr: *const c_char) -> int8_t {
+    let host = Leaky::new(tryrc!(readptr!(host_ptr, "Host pointer"), -1));
+    let capability = tryrc!(ptrtostr!(capability_ptr, "capability string"), -1);
+
+    if host.supports(capability) {
+        1
+    } else {
+        0
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn host_close(host_ptr: *mut Host) -> uint8_t {
     tryrc!(boxptr!(host_ptr, "Host pointer"));
@@ -243,17 +252,12 @@ mod tests {
     use ffi_helpers::Ffi__Array;
     use host::Host;
     use libc::c_char;
-    #[cfg(feature = "remote-run")]
-    use serde_json;
     use std::ffi::CString;
-    #[cfg(feature = "local-run")]
     use std::fs::File;
-    #[cfg(feature = "local-run")]
     use std::io::Write;
     use std::os::raw::c_void;
     use std::ptr;
     use super::*;
-    #[cfg(feature = "local-run")]
     use tempdir::TempDir;
 
     #[test]
@@ -359,7 +363,6 @@ mod tests {
         check_array(a1);
     }
 
-    #[cfg(feature = "local-run")]
     fn check_array(v: Vec<&str>) {
         let mut iter = v.into_iter();
         assert_eq!(iter.next().unwrap(), "_telemetry");
@@ -372,19 +375,6 @@ mod tests {
         assert_eq!(iter.next().unwrap(), "u64");
     }
 
-    #[cfg(feature = "remote-run")]
-    fn check_array(v: Vec<&str>) {
-        let mut iter = v.into_iter();
-        assert_eq!(iter.next().unwrap(), "array");
-        assert_eq!(iter.next().unwrap(), "bool");
-        assert_eq!(iter.next().unwrap(), "f64");
-        assert_eq!(iter.next().unwrap(), "i64");
-        assert_eq!(iter.next().unwrap(), "obj");
-        assert_eq!(iter.next().unwrap(), "string");
-        assert_eq!(iter.next().unwrap(), "u64");
-    }
-
-    #[cfg(feature = "local-run")]
     fn create_host() -> *mut Host {
         let td = TempDir::new("test_data_ffi").unwrap();
         let mut path = td.path().to_owned();
@@ -408,23 +398,4 @@ mod tests {
 
         Box::into_raw(Box::new(Host::local(Some(&path)).unwrap()))
     }
-
-    #[cfg(feature = "remote-run")]
-    fn create_host() -> *mut Host {
-        let v = serde_json::from_str("{
-            \"bool\": true,
-            \"i64\": -5,
-            \"u64\": 10,
-            \"f64\": 1.2,
-            \"string\": \"abc\",
-            \"array\": [
-                123,
-                \"def\"
-            ],
-            \"obj\": {
-                \"a\": \"b\"
-            }
-        }").unwrap();
-        Box::into_raw(Box::new(Host::test_new(None, None, None, Some(v))))
-    }
 }
@@ -0,0 +1,172 @@
+// Copyright 2015-2017 Intecture Developers. See the COPYRIGHT file at the
+// top-level directory of this distribution and at
+// https://intecture.io/COPYRIGHT.
+//
+// Licensed under the Mozilla Public License 2.0 <LICENSE or
+// https://www.tldrlegal.com/l/mpl-2.0>. This file may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Inventory of hosts for running tasks in parallel.
+
+use error::{Error, Result};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread;
+use super::Host;
+
+/// Default number of hosts that are managed simultaneously.
+const DEFAULT_CONCURRENCY: usize = 10;
+
+/// Inventory of hosts, identified by their data files.
+///
+/// A `Host` cannot be shared between threads, so rather than holding
+/// connected hosts, a `HostGroup` holds the paths to their data
+/// files. Each worker thread creates its own `Host` for every data
+/// file it is given, then passes it to your closure.
+///
+///# Examples
+///
+/// ```no_run
+/// # use inapi::{Command, HostGroup};
+///let mut group = HostGroup::new(&["hosts/web1.json", "hosts/web2.json", "hosts/db1.json"]);
+///group.set_concurrency(2);
+///
+///let results = group.run(|host| Command::new("whoami").exec(host));
+///
+///for (path, result) in results {
+///    match result {
+///        Ok(r) => println!("{}: {}", path.display(), r.stdout),
+///        Err(e) => println!("{} failed: {}", path.display(), e),
+///    }
+///}
+/// ```
+pub struct HostGroup {
+    /// Data file paths for each host
+    paths: Vec<PathBuf>,
+    /// Maximum number of hosts to manage simultaneously
+    concurrency: usize,
+}
+
+impl HostGroup {
+    /// Create a new HostGroup from a list of host data files.
+    pub fn new<P: AsRef<Path>>(paths: &[P]) -> HostGroup {
+        HostGroup {
+            paths: paths.iter().map(|p| p.as_ref().to_owned()).collect(),
+            concurrency: DEFAULT_CONCURRENCY,
+        }
+    }
+
+    /// Add a host data file to the group.
+    pub fn add<P: AsRef<Path>>(&mut self, path: P) {
+        self.paths.push(path.as_ref().to_owned());
+    }
+
+    /// Set the maximum number of hosts that are managed
+    /// simultaneously. The minimum is 1.
+    pub fn set_concurrency(&mut self, limit: usize) {
+        self.concurrency = if limit == 0 { 1 } else { limit };
+    }
+
+    /// Get the data file paths for each host in the group.
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    /// Connect to each host in the group and run `task` against it,
+    /// using up to the configured number of threads.
+    ///
+    /// Results are returned in the same order as the hosts were
+    /// added to the group. An error connecting to a host is reported
+    /// as that host's result and does not affect the other hosts.
+    pub fn run<F, T>(&self, task: F) -> Vec<(PathBuf, Result<T>)>
+        where F: Fn(&mut Host) -> Result<T> + Send + Sync + 'static,
+              T: Send + 'static
+    {
+        let task = Arc::new(task);
+        let queue = Arc::new(Mutex::new(self.paths.clone().into_iter().enumerate()));
+        let (tx, rx) = mpsc::channel();
+        let mut workers = Vec::new();
+
+        for _ in 0..self.concurrency.min(self.paths.len()) {
+            let task = task.clone();
+            let queue = queue.clone();
+            let tx = tx.clone();
+
+            workers.push(thread::spawn(move || {
+                loop {
+                    // Release the lock before running the task so
+                    // that other workers can pick up the next host.
+                    let next = queue.lock().unwrap().next();
+
+                    match next {
+                        Some((index, path)) => {
+                            let result = Host::connect(&path).and_then(|mut host| task(&mut host));
+                            if tx.send((index, result)).is_err() {
+                                break;
+                            }
+                        },
+                        None => break,
+                    }
+                }
+            }));
+        }
+
+        drop(tx);
+
+        let mut results: Vec<Option<Result<T>>> = self.paths.iter().map(|_| None).collect();
+        for (index, result) in rx {
+            results[index] = Some(result);
+        }
+
+        // A worker that panicked will not have sent its result, which
+        // is picked up below.
+        for worker in workers {
+            let _ = worker.join();
+        }
+
+        self.paths.iter().cloned().zip(results.into_iter().map(|r| {
+            r.unwrap_or(Err(Error::Generic("Worker thread panicked while managing host".into())))
+        })).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use error::Error;
+    use std::fs;
+    use std::io::Write;
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_run() {
+        let tempdir = TempDir::new("host_group").unwrap();
+        let mut paths = Vec::new();
+
+        for name in &["web1", "web2", "db1"] {
+            let path = tempdir.path().join(format!("{}.json", name));
+            let mut fh = fs::File::create(&path).unwrap();
+            fh.write_all(json!({ "name": name, "transport": "local" }).to_string().as_bytes()).unwrap();
+            paths.push(path);
+        }
+
+        let mut group = HostGroup::new(&paths);
+        group.add(tempdir.path().join("missing.json"));
+        group.set_concurrency(2);
+
+        let results = group.run(|host| {
+            match host.data()["name"].as_str() {
+                Some(n) if n == "db1" => Err(Error::Generic("moo".into())),
+                Some(n) => Ok(n.to_string()),
+                None => Err(Error::Generic("Missing name".into())),
+            }
+        });
+
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[0].0, paths[0]);
+        assert_eq!(results[0].1.as_ref().unwrap(), "web1");
+        assert_eq!(results[1].1.as_ref().unwrap(), "web2");
+        assert!(results[2].1.is_err());
+        assert!(results[3].1.is_err());
+    }
+}
@@ -0,0 +1,187 @@
+// Copyright 2015-2017 Intecture Developers. See the COPYRIGHT file at the
+// top-level directory of this distribution and at
+// https://intecture.io/COPYRIGHT.
+//
+// Licensed under the Mozilla Public License 2.0 <LICENSE or
+// https://www.tldrlegal.com/l/mpl-2.0>. This file may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Heartbeating and reconnection for Agent connections.
+//!
+//! Before a request is sent over a connection that has been idle for
+//! longer than the heartbeat interval, the Agent is pinged. If it
+//! doesn't reply, the socket is reset and the Agent pinged again, up
+//! to the configured number of retries.
+//!
+//! Requests themselves are never retried, as they may not be
+//! idempotent (e.g. running a command). If a reply is lost, the
+//! request fails, but the socket is reset so that the next request
+//! can succeed.
+
+use czmq::ZMsg;
+use error::{Error, Result};
+use serde_json::Value;
+use std::thread;
+use std::time::{Duration, Instant};
+use super::{Host, HostSendRecv, timeout_ms};
+
+/// Milliseconds to wait before the first reconnection attempt. Each
+/// subsequent attempt waits twice as long as the last.
+const RETRY_DELAY: u64 = 200;
+
+/// Options for connections to an Agent.
+///
+/// These can be set in the host's data file using the keys
+/// "agent_timeout", "agent_heartbeat", "agent_heartbeat_timeout",
+/// "agent_retries" and "agent_handshake_timeout".
+#[derive(Clone, Debug, PartialEq)]
+pub struct AgentOptions {
+    /// Seconds to wait for the Agent to reply to a request
+    pub timeout: u64,
+    /// Seconds that a connection can be idle before the Agent is
+    /// pinged to check that it's alive. 0 disables heartbeats.
+    pub heartbeat: u64,
+    /// Seconds to wait for the Agent to reply to a ping
+    pub heartbeat_timeout: u64,
+    /// Number of times to reconnect to an unresponsive Agent before
+    /// giving up
+    pub retries: u32,
+    /// Seconds to wait for the Agent to answer the protocol version
+    /// handshake when connecting
+    pub handshake_timeout: u64,
+}
+
+impl Default for AgentOptions {
+    fn default() -> AgentOptions {
+        AgentOptions {
+            timeout: 1800,
+            heartbeat: 60,
+            heartbeat_timeout: 5,
+            retries: 3,
+            handshake_timeout: 5,
+        }
+    }
+}
+
+impl AgentOptions {
+    /// Read options from a host's data, using defaults for any that
+    /// are missing.
+    pub fn from_data(data: &Value) -> AgentOptions {
+        let default = AgentOptions::default();
+
+        AgentOptions {
+            timeout: wantu64!(data => "/agent_timeout").unwrap_or(default.timeout),
+            heartbeat: wantu64!(data => "/agent_heartbeat").unwrap_or(default.heartbeat),
+            heartbeat_timeout: wantu64!(data => "/agent_heartbeat_timeout").unwrap_or(default.heartbeat_timeout),
+            retries: wantu64!(data => "/agent_retries").map(|r| r as u32).unwrap_or(default.retries),
+            handshake_timeout: wantu64!(data => "/agent_handshake_timeout").unwrap_or(default.handshake_timeout),
+        }
+    }
+}
+
+/// Check that the Agent is alive if the connection has been idle,
+/// reconnecting if it isn't.
+pub fn keepalive(host: &mut Host) -> Result<()> {
+    // Payload hosts have no endpoint of their own. Their requests
+    // are proxied by a Host that does its own heartbeating.
+    if host.options.heartbeat == 0
+        || host.api_endpoint.is_none()
+        || !host.supports("ping")
+        || host.last_seen.elapsed() < Duration::from_secs(host.options.heartbeat) {
+        return Ok(());
+    }
+
+    let mut delay = RETRY_DELAY;
+    for attempt in 0..host.options.retries + 1 {
+        if attempt > 0 {
+            thread::sleep(Duration::from_millis(delay));
+            delay *= 2;
+        }
+
+        if ping(host).is_ok() {
+            host.last_seen = Instant::now();
+            return Ok(());
+        }
+
+        // The REQ socket is stuck waiting for the lost reply
+        try!(host.reset_api_sock());
+    }
+
+    Err(Error::Timeout(format!("Agent on {} is not responding after {} attempts", host.hostname, host.options.retries + 1)))
+}
+
+fn ping(host: &mut Host) -> Result<()> {
+    let heartbeat_timeout = host.options.heartbeat_timeout.saturating_mul(1000);
+    let timeout = timeout_ms(host.options.timeout);
+    let sock = try!(host.api_sock.as_mut().ok_or(Error::HostDisconnected));
+
+    let msg = ZMsg::new();
+    try!(msg.addstr("ping"));
+    try!(msg.send(sock));
+
+    sock.set_rcvtimeo(Some(if heartbeat_timeout > i32::max_value() as u64 { i32::max_value() } else { heartbeat_timeout as i32 }));
+    let reply = ZMsg::recv(sock);
+    sock.set_rcvtimeo(Some(timeout));
+
+    try!(Host::extract_header(&try!(reply)));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use agent::LocalAgent;
+    use command::Command;
+    use czmq::ZSys;
+    use error::Error;
+    use std::thread;
+    use std::time::Duration;
+    use super::*;
+
+    #[test]
+    fn test_from_data() {
+        let options = AgentOptions::from_data(&json!({
+            "agent_timeout": 60,
+            "agent_retries": 0,
+            "agent_handshake_timeout": 2,
+        }));
+
+        assert_eq!(options, AgentOptions {
+            timeout: 60,
+            heartbeat: 60,
+            heartbeat_timeout: 5,
+            retries: 0,
+            handshake_timeout: 2,
+        });
+    }
+
+    #[test]
+    fn test_keepalive() {
+        ZSys::init();
+
+        let agent = LocalAgent::new().unwrap();
+        let endpoint = agent.endpoint().to_string();
+        let file_endpoint = agent.file_endpoint().to_string();
+        let mut host = agent.connect().unwrap();
+        host.set_agent_options(AgentOptions {
+            timeout: 5,
+            heartbeat: 1,
+            heartbeat_timeout: 1,
+            retries: 1,
+            handshake_timeout: 5,
+        });
+
+        // Kill the agent and wait for the connection to go idle
+        drop(agent);
+        thread::sleep(Duration::from_millis(1100));
+
+        match Command::new("echo moo").exec(&mut host) {
+            Err(Error::Timeout(_)) => (),
+            _ => panic!("Expected dead agent to time out"),
+        }
+
+        // Once the agent is back, the host reconnects
+        let _agent = LocalAgent::bind(&endpoint, &file_endpoint).unwrap();
+        let result = Command::new("echo moo").exec(&mut host).unwrap();
+        assert_eq!(result.stdout, "moo");
+    }
+}
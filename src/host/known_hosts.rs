@@ -0,0 +1,185 @@
+// Copyright 2015-2017 Intecture Developers. See the COPYRIGHT file at the
+// top-level directory of this distribution and at
+// https://intecture.io/COPYRIGHT.
+//
+// Licensed under the Mozilla Public License 2.0 <LICENSE or
+// https://www.tldrlegal.com/l/mpl-2.0>. This file may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Cache of hosts' public keys.
+//!
+//! The known hosts file has one host per line: the hostname and its
+//! Z85 encoded public key, separated by a space. Lines starting with
+//! "#" are ignored.
+//!
+//! Hosts in a `HostGroup` connect in parallel, so access to known
+//! hosts files is serialized by a process-wide lock.
+
+use error::{Error, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+lazy_static! {
+    /// Held while a known hosts file is read or written
+    static ref FILE_LOCK: Mutex<()> = Mutex::new(());
+}
+
+/// Store of hosts' public keys, used to detect key changes and to
+/// connect to hosts while the Auth server is unreachable.
+///
+///# Examples
+///
+/// Forget a host's key after it has been legitimately replaced:
+///
+/// ```no_run
+/// # use inapi::KnownHosts;
+///let mut known_hosts = KnownHosts::open("known_hosts").unwrap();
+///known_hosts.remove("myhost.example.com");
+///known_hosts.save().unwrap();
+/// ```
+pub struct KnownHosts {
+    path: PathBuf,
+    hosts: BTreeMap<String, String>,
+}
+
+impl KnownHosts {
+    /// Open a known hosts file. A file that doesn't exist yet is
+    /// treated as empty.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<KnownHosts> {
+        let _lock = lock();
+        Self::read(path)
+    }
+
+    /// Add or replace the public key for a host in the known hosts
+    /// file at `path`. Unlike `insert()` followed by `save()`, this
+    /// keeps any keys that were saved by other threads since the
+    /// file was opened.
+    pub fn save_key<P: AsRef<Path>>(path: P, hostname: &str, key: &str) -> Result<()> {
+        let _lock = lock();
+        let mut known_hosts = try!(Self::read(path));
+        known_hosts.insert(hostname, key);
+        known_hosts.write()
+    }
+
+    fn read<P: AsRef<Path>>(path: P) -> Result<KnownHosts> {
+        let mut hosts = BTreeMap::new();
+
+        match fs::File::open(path.as_ref()) {
+            Ok(mut fh) => {
+                let mut contents = String::new();
+                try!(fh.read_to_string(&mut contents));
+
+                for (i, line) in contents.lines().enumerate() {
+                    let line = line.trim();
+                    if line.is_empty() || line.starts_with('#') {
+                        continue;
+                    }
+
+                    let mut parts = line.split_whitespace();
+                    match (parts.next(), parts.next(), parts.next()) {
+                        (Some(host), Some(key), None) => { hosts.insert(host.to_string(), key.to_string()); },
+                        _ => return Err(Error::Generic(format!("Invalid entry on line {} of {}", i + 1, path.as_ref().display()))),
+                    }
+                }
+            },
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => (),
+            Err(e) => return Err(e.into()),
+        }
+
+        Ok(KnownHosts {
+            path: path.as_ref().to_owned(),
+            hosts: hosts,
+        })
+    }
+
+    /// Get the public key for a host.
+    pub fn get(&self, hostname: &str) -> Option<&str> {
+        self.hosts.get(hostname).map(|k| k.as_ref())
+    }
+
+    /// Add or replace the public key for a host.
+    pub fn insert(&mut self, hostname: &str, key: &str) {
+        self.hosts.insert(hostname.into(), key.into());
+    }
+
+    /// Forget a host, returning its public key.
+    pub fn remove(&mut self, hostname: &str) -> Option<String> {
+        self.hosts.remove(hostname)
+    }
+
+    /// Write the known hosts back to the file.
+    pub fn save(&self) -> Result<()> {
+        let _lock = lock();
+        self.write()
+    }
+
+    fn write(&self) -> Result<()> {
+        let mut fh = try!(fs::File::create(&self.path));
+        for (host, key) in &self.hosts {
+            try!(writeln!(fh, "{} {}", host, key));
+        }
+        Ok(())
+    }
+}
+
+fn lock() -> MutexGuard<'static, ()> {
+    FILE_LOCK.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Write;
+    use std::thread;
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_open_save() {
+        let dir = TempDir::new("test_known_hosts").unwrap();
+        let path = dir.path().join("known_hosts");
+
+        let mut known_hosts = KnownHosts::open(&path).unwrap();
+        assert!(known_hosts.get("example.com").is_none());
+
+        known_hosts.insert("example.com", "abc#123");
+        known_hosts.insert("example.org", "def");
+        known_hosts.save().unwrap();
+
+        let mut known_hosts = KnownHosts::open(&path).unwrap();
+        assert_eq!(known_hosts.get("example.com"), Some("abc#123"));
+        assert_eq!(known_hosts.remove("example.org"), Some("def".into()));
+        assert!(known_hosts.get("example.org").is_none());
+
+        let mut fh = fs::File::create(&path).unwrap();
+        fh.write_all(b"# comment\nexample.com\n").unwrap();
+        assert!(KnownHosts::open(&path).is_err());
+    }
+
+    #[test]
+    fn test_save_key_parallel() {
+        let dir = TempDir::new("test_known_hosts_parallel").unwrap();
+        let path = dir.path().join("known_hosts");
+
+        let mut known_hosts = KnownHosts::open(&path).unwrap();
+        known_hosts.insert("example.com", "abc");
+        known_hosts.save().unwrap();
+
+        let handles: Vec<_> = (0..8).map(|i| {
+            let path = path.clone();
+            thread::spawn(move || KnownHosts::save_key(&path, &format!("host{}", i), &format!("key{}", i)).unwrap())
+        }).collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let known_hosts = KnownHosts::open(&path).unwrap();
+        assert_eq!(known_hosts.get("example.com"), Some("abc"));
+        for i in 0..8 {
+            assert_eq!(known_hosts.get(&format!("host{}", i)), Some(format!("key{}", i).as_str()));
+        }
+    }
+}
@@ -10,81 +10,150 @@
 
 #[macro_use]
 pub mod data;
+#[cfg(feature = "remote-run")]
+mod builder;
 pub mod ffi;
+pub mod group;
+#[cfg(feature = "remote-run")]
+mod keepalive;
+#[cfg(feature = "remote-run")]
+mod known_hosts;
+#[cfg(feature = "remote-run")]
+pub mod protocol;
+pub mod ssh;
 pub mod telemetry;
 
+#[cfg(feature = "remote-run")]
+pub use self::builder::{HostBuilder, Proxy};
+pub use self::group::HostGroup;
+#[cfg(feature = "remote-run")]
+pub use self::keepalive::AgentOptions;
+#[cfg(feature = "remote-run")]
+pub use self::known_hosts::KnownHosts;
 pub use self::telemetry::TelemetryTarget;
 
 #[cfg(feature = "remote-run")]
 use czmq::{ZCert, ZMsg, ZSock, SocketType};
+use error::{Error, Result};
 #[cfg(feature = "remote-run")]
-use error::Error;
-use error::Result;
+use libc;
+use serde::Deserialize;
 #[cfg(feature = "remote-run")]
 use serde_json;
 use serde_json::Value;
+use self::ssh::SshSession;
 #[cfg(feature = "remote-run")]
+use std::io;
 use std::mem;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 #[cfg(feature = "remote-run")]
+use std::time::{Duration, Instant};
+#[cfg(feature = "remote-run")]
 use zfilexfer;
 
-#[cfg(feature = "local-run")]
-/// Primitive for communicating with a managed host.
-///
-///# Examples
-///
-/// ```no_run
-/// # use inapi::{Command, Host};
-///let path: Option<String> = None;
-///let mut host = Host::local(path).unwrap();
-///
-///let cmd = Command::new("whoami");
-///let result = cmd.exec(&mut host).unwrap();
-/// ```
-pub struct Host {
-    /// Data for host, comprising data files and telemetry
-    data: Rc<Value>,
+#[cfg(feature = "remote-run")]
+/// Default send/receive timeout for Agent sockets in milliseconds.
+const SOCK_TIMEOUT: i32 = 1800000;
+#[cfg(feature = "remote-run")]
+/// Time in milliseconds that the Agent has to reply once a command
+/// has exceeded its timeout.
+const TIMEOUT_GRACE: i32 = 2000;
+
+/// Method used to communicate with a managed host.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Transport {
+    /// Run directly on the local machine
+    Local,
+    #[cfg(feature = "remote-run")]
+    /// Send requests to the Intecture Agent on the host
+    Agent,
+    /// Run shell commands on the host over SSH
+    Ssh,
 }
 
-#[cfg(feature = "remote-run")]
 /// Primitive for communicating with a managed host.
 ///
+/// Hosts using different transports can be used side by side, e.g.
+/// to configure a remote host based on the state of the local
+/// machine.
+///
 ///# Examples
 ///
 /// ```no_run
 /// # use inapi::{Command, Host};
-///let mut host = Host::connect("hosts/myhost.json").unwrap();
+///let path: Option<String> = None;
+///let mut local = Host::local(path).unwrap();
+#[cfg_attr(feature = "remote-run", doc = "let mut remote = Host::connect(\"hosts/myhost.json\").unwrap();")]
 ///
 ///let cmd = Command::new("whoami");
-///let result = cmd.exec(&mut host).unwrap();
+///let result = cmd.exec(&mut local).unwrap();
+#[cfg_attr(feature = "remote-run", doc = "let result = cmd.exec(&mut remote).unwrap();")]
 /// ```
 pub struct Host {
     /// Hostname or IP of managed host
     pub hostname: String,
+    /// Method used to communicate with the host
+    transport: Transport,
+    #[cfg(feature = "remote-run")]
     /// API socket
     api_sock: Option<ZSock>,
+    #[cfg(feature = "remote-run")]
+    /// API endpoint, used to reconnect the API socket
+    api_endpoint: Option<String>,
+    #[cfg(feature = "remote-run")]
     /// File transfer socket
     file_sock: Option<ZSock>,
+    #[cfg(feature = "remote-run")]
+    /// Streaming socket, created on demand
+    stream_sock: Option<ZSock>,
+    #[cfg(feature = "remote-run")]
+    /// User certificate for CURVE authentication
+    user_cert: Option<ZCert>,
+    #[cfg(feature = "remote-run")]
+    /// Host's certificate for CURVE authentication
+    server_cert: Option<ZCert>,
+    #[cfg(feature = "remote-run")]
+    /// SOCKS5 proxy for Agent sockets
+    socks_proxy: Option<String>,
+    #[cfg(feature = "remote-run")]
+    /// Whether the host's key differed from the known hosts file
+    server_key_changed: bool,
+    #[cfg(feature = "remote-run")]
+    /// Protocol version negotiated with the Agent
+    protocol_version: u32,
+    #[cfg(feature = "remote-run")]
+    /// Message types supported by the Agent
+    capabilities: Vec<String>,
+    #[cfg(feature = "remote-run")]
+    /// Timeouts and heartbeating for the Agent connection
+    options: AgentOptions,
+    #[cfg(feature = "remote-run")]
+    /// Time that the Agent last replied
+    last_seen: Instant,
+    #[cfg(feature = "remote-run")]
+    /// Replies to timed out requests that are still due on the API
+    /// socket, which could not be reconnected to discard them
+    stale_replies: usize,
+    /// SSH session, for hosts that are managed without an Agent
+    ssh: Option<SshSession>,
     /// Data for host, comprising data files and telemetry
     data: Rc<Value>,
+    /// Data file that the host's data was loaded from
+    data_path: Option<PathBuf>,
 }
 
 impl Host {
-    #[cfg(feature = "local-run")]
     /// Create a new Host connected to localhost.
     pub fn local<P: AsRef<Path>>(path: Option<P>) -> Result<Host> {
-        let mut me = Host {
-            data: Rc::new(Value::Null),
-        };
-
+        let mut me = Host::new("localhost", Transport::Local);
         let telemetry = try!(telemetry::Telemetry::init(&mut me));
 
         match path {
             Some(p) => {
-                let value = try!(data::open(p));
-                me.data = Rc::new(try!(data::merge(value, telemetry)));
+                let value = try!(data::open(p.as_ref()));
+                me.data = Rc::new(try!(data::merge_from(value, data::full_path(p.as_ref()), telemetry)));
+                me.data_path = Some(p.as_ref().to_owned());
             },
             None => me.data = Rc::new(telemetry),
         }
@@ -92,23 +161,38 @@ impl Host {
         Ok(me)
     }
 
-    #[cfg(feature = "remote-run")]
     /// Create a new Host connected to the endpoint specified in the
     /// data file.
     ///
-    /// This function expects to find the following keys in the root
-    /// namespace: "hostname", "api_port", "file_port".
+    /// The optional "transport" key selects how the host is managed.
+    /// For the default "agent" transport, this function expects to
+    /// find the following keys in the root namespace: "hostname",
+    /// "api_port", "file_port", and optionally the keys described by
+    /// `AgentOptions` and `Proxy`. For the "ssh" transport, it expects
+    /// "hostname", and optionally "ssh_port", "ssh_user" and
+    /// "ssh_identity". The "local" transport manages the local
+    /// machine, as `Host::local()` does.
     pub fn connect<P: AsRef<Path>>(path: P) -> Result<Host> {
         let value = try!(data::open(path.as_ref()));
-        let mut me = try!(Self::connect_endpoint(try!(needstr!(value => "/hostname")),
-                                                 try!(needu64!(value => "/api_port")) as u32,
-                                                 try!(needu64!(value => "/file_port")) as u32));
+        let mut me = match wantstr!(value => "/transport") {
+            Some("local") => return Self::local(Some(path)),
+            Some("ssh") => try!(Self::connect_ssh(try!(needstr!(value => "/hostname")),
+                                                  wantu64!(value => "/ssh_port").map(|p| p as u32),
+                                                  wantstr!(value => "/ssh_user"),
+                                                  wantstr!(value => "/ssh_identity"))),
+            #[cfg(feature = "remote-run")]
+            Some("agent") | None => try!(try!(HostBuilder::from_data(&value)).connect()),
+            #[cfg(not(feature = "remote-run"))]
+            Some("agent") | None => return Err(Error::Generic("The agent transport requires the `remote-run` feature".into())),
+            Some(t) => return Err(Error::Generic(format!("Unknown transport `{}`", t))),
+        };
 
         let mut telemetry = Rc::new(Value::Null);
         mem::swap(&mut telemetry, &mut me.data);
         // We can use unwrap() here safely as we can guarantee that
         // there is only one strong reference to telemetry.
-        me.data = Rc::new(try!(data::merge(value, Rc::try_unwrap(telemetry).unwrap())));
+        me.data = Rc::new(try!(data::merge_from(value, data::full_path(path.as_ref()), Rc::try_unwrap(telemetry).unwrap())));
+        me.data_path = Some(path.as_ref().to_owned());
 
         Ok(me)
     }
@@ -116,30 +200,48 @@ impl Host {
     #[cfg(feature = "remote-run")]
     /// Create a new Host connected to the specified endpoint. Note
     /// that this function does not load any user data.
+    ///
+    /// Certificates and the Auth server are found in the default
+    /// locations described by `HostBuilder`, which can be used to
+    /// override them.
     pub fn connect_endpoint(hostname: &str, api_port: u32, file_port: u32) -> Result<Host> {
-        let user_cert = try!(ZCert::load("user.crt"));
-        let server_cert = try!(Self::lookup_server_cert(hostname, &user_cert));
-
-        let mut api_sock = ZSock::new(SocketType::REQ);
-        user_cert.apply(&mut api_sock);
-        api_sock.set_curve_serverkey(server_cert.public_txt());
-        api_sock.set_sndtimeo(Some(1800000));
-        api_sock.set_rcvtimeo(Some(1800000));
-        try!(api_sock.connect(&format!("tcp://{}:{}", hostname, api_port)));
-
-        let mut file_sock = ZSock::new(SocketType::DEALER);
-        user_cert.apply(&mut file_sock);
-        file_sock.set_curve_serverkey(server_cert.public_txt());
-        file_sock.set_sndtimeo(Some(1800000));
-        file_sock.set_rcvtimeo(Some(1800000));
-        try!(file_sock.connect(&format!("tcp://{}:{}", hostname, file_port)));
-
-        let mut me = Host {
-            hostname: hostname.into(),
-            api_sock: Some(api_sock),
-            file_sock: Some(file_sock),
-            data: Rc::new(Value::Null),
-        };
+        HostBuilder::new(hostname, api_port, file_port).connect()
+    }
+
+    #[cfg(feature = "remote-run")]
+    /// Create a new Host connected to an Agent without CURVE
+    /// authentication or encryption. Note that this function does
+    /// not load any user data.
+    ///
+    /// This is intended for Agents that only listen on the loopback
+    /// interface, such as a `LocalAgent`. Never use it to connect to
+    /// an Agent over an untrusted network. Files can only be uploaded
+    /// if `file_endpoint` is given.
+    pub fn connect_insecure(api_endpoint: &str, file_endpoint: Option<&str>) -> Result<Host> {
+        let mut me = Host::new("localhost", Transport::Agent);
+        me.api_sock = Some(try!(Self::plain_sock(SocketType::REQ, api_endpoint)));
+        me.api_endpoint = Some(api_endpoint.into());
+        if let Some(e) = file_endpoint {
+            me.file_sock = Some(try!(Self::plain_sock(SocketType::DEALER, e)));
+        }
+        try!(me.handshake());
+        me.data = Rc::new(try!(telemetry::Telemetry::init(&mut me)));
+
+        Ok(me)
+    }
+
+    /// Create a new Host that is managed over SSH, for hosts that
+    /// don't run the Intecture Agent. Note that this function does
+    /// not load any user data.
+    ///
+    /// Authentication must not require a password, e.g. by using a
+    /// key loaded into `ssh-agent`, or by passing `identity`.
+    pub fn connect_ssh(hostname: &str, port: Option<u32>, user: Option<&str>, identity: Option<&str>) -> Result<Host> {
+        let session = SshSession::new(hostname, port, user, identity);
+        try!(session.check());
+
+        let mut me = Host::new(hostname, Transport::Ssh);
+        me.ssh = Some(session);
         me.data = Rc::new(try!(telemetry::Telemetry::init(&mut me)));
 
         Ok(me)
@@ -157,12 +259,48 @@ impl Host {
         let data_json = try!(api_sock.recv_str()).unwrap();
         let data = try!(serde_json::from_str(&data_json));
 
-        Ok(Host {
-            hostname: "payload".into(),
-            api_sock: Some(api_sock),
-            file_sock: Some(file_sock),
-            data: Rc::new(data),
-        })
+        let mut me = Host::new("payload", Transport::Agent);
+        me.api_sock = Some(api_sock);
+        me.file_sock = Some(file_sock);
+        me.data = Rc::new(data);
+        try!(me.handshake());
+
+        Ok(me)
+    }
+
+    #[cfg(feature = "remote-run")]
+    fn new(hostname: &str, transport: Transport) -> Host {
+        Host {
+            hostname: hostname.into(),
+            transport: transport,
+            api_sock: None,
+            api_endpoint: None,
+            file_sock: None,
+            stream_sock: None,
+            user_cert: None,
+            server_cert: None,
+            socks_proxy: None,
+            server_key_changed: false,
+            protocol_version: 0,
+            capabilities: Vec::new(),
+            options: AgentOptions::default(),
+            last_seen: Instant::now(),
+            stale_replies: 0,
+            ssh: None,
+            data: Rc::new(Value::Null),
+            data_path: None,
+        }
+    }
+
+    #[cfg(not(feature = "remote-run"))]
+    fn new(hostname: &str, transport: Transport) -> Host {
+        Host {
+            hostname: hostname.into(),
+            transport: transport,
+            ssh: None,
+            data: Rc::new(Value::Null),
+            data_path: None,
+        }
     }
 
     /// Get data for Host.
@@ -170,58 +308,165 @@ impl Host {
         &self.data
     }
 
+    /// Deserialize the value at a JSON pointer in the host's data.
+    ///
+    ///# Examples
+    ///
+    /// ```no_run
+    /// # use inapi::Host;
+    #[cfg_attr(not(feature = "remote-run"), doc = "# let host = Host::local(Some(\"hosts/myhost.json\")).unwrap();")]
+    #[cfg_attr(feature = "remote-run", doc = "# let host = Host::connect(\"hosts/myhost.json\").unwrap();")]
+    ///let port: u16 = host.data_as("/nginx/port").unwrap();
+    ///let sites: Vec<String> = host.data_as("/nginx/sites").unwrap();
+    /// ```
+    pub fn data_as<T: Deserialize>(&self, pointer: &str) -> Result<T> {
+        data::get(&self.data, pointer)
+    }
+
     /// Get a reference counted version of data for Host.
     pub fn data_owned(&self) -> Rc<Value> {
         self.data.clone()
     }
 
+    /// Get the method used to communicate with the host.
+    pub fn transport(&self) -> Transport {
+        self.transport
+    }
+
+    /// Check whether the host supports a message type, e.g.
+    /// "command::exec_stream". Only Agents can lack support for a
+    /// message type, as they may be older than this API.
+    pub fn supports(&self, capability: &str) -> bool {
+        match self.transport {
+            #[cfg(feature = "remote-run")]
+            Transport::Agent => self.capabilities.iter().any(|c| c == capability),
+            _ => true,
+        }
+    }
+
     #[cfg(feature = "remote-run")]
-    fn lookup_server_cert(hostname: &str, user_cert: &ZCert) -> Result<ZCert> {
-        let auth_cert = try!(ZCert::load("auth.crt"));
+    /// Get the protocol version negotiated with the Agent, or 0 if
+    /// the host is not managed by an Agent.
+    pub fn protocol_version(&self) -> u32 {
+        self.protocol_version
+    }
 
-        let mut auth_sock = ZSock::new(SocketType::REQ);
-        user_cert.apply(&mut auth_sock);
-        auth_sock.set_curve_serverkey(auth_cert.public_txt());
-        auth_sock.set_sndtimeo(Some(10000));
-        auth_sock.set_rcvtimeo(Some(10000));
-        try!(auth_sock.connect(&format!("tcp://{}:{}", ::PROJECT_CONFIG.auth_server, ::PROJECT_CONFIG.auth_api_port)));
+    #[cfg(feature = "remote-run")]
+    /// Get the message types supported by the Agent.
+    pub fn capabilities(&self) -> &[String] {
+        &self.capabilities
+    }
 
-        // Get server cert from Auth server
-        let msg = ZMsg::new();
-        try!(msg.addstr("cert::lookup"));
-        try!(msg.addstr(hostname));
-        try!(msg.send(&mut auth_sock));
+    #[cfg(feature = "remote-run")]
+    /// Check whether the host's public key differed from the one in
+    /// the known hosts file when it was connected. This can only
+    /// happen if `HostBuilder::strict_host_keys(false)` was set.
+    pub fn server_key_changed(&self) -> bool {
+        self.server_key_changed
+    }
 
-        let reply = try!(ZMsg::recv(&mut auth_sock));
+    #[cfg(feature = "remote-run")]
+    /// Set timeouts and heartbeating for the Agent connection.
+    pub fn set_agent_options(&mut self, options: AgentOptions) {
+        if let Some(ref mut sock) = self.api_sock {
+            sock.set_rcvtimeo(Some(timeout_ms(options.timeout)));
+        }
 
-        if reply.size() != 2 {
-            return Err(Error::HostResponse);
+        self.options = options;
+    }
+
+    #[cfg(feature = "remote-run")]
+    /// Get the timeouts and heartbeating for the Agent connection.
+    pub fn agent_options(&self) -> &AgentOptions {
+        &self.options
+    }
+
+    #[cfg(feature = "remote-run")]
+    fn handshake(&mut self) -> Result<()> {
+        let (version, capabilities) = try!(protocol::hello(self));
+        self.protocol_version = version;
+        self.capabilities = capabilities;
+        Ok(())
+    }
+
+    #[doc(hidden)]
+    pub fn ssh_session(&self) -> Option<&SshSession> {
+        self.ssh.as_ref()
+    }
+
+    #[cfg(feature = "remote-run")]
+    fn curve_sock(sock_type: SocketType, user_cert: &ZCert, server_cert: &ZCert, socks_proxy: Option<&String>, endpoint: &str) -> Result<ZSock> {
+        let mut sock = ZSock::new(sock_type);
+        user_cert.apply(&mut sock);
+        sock.set_curve_serverkey(server_cert.public_txt());
+        if let Some(proxy) = socks_proxy {
+            sock.set_socks_proxy(proxy);
         }
+        sock.set_sndtimeo(Some(SOCK_TIMEOUT));
+        sock.set_rcvtimeo(Some(SOCK_TIMEOUT));
+        try!(sock.connect(endpoint));
+        Ok(sock)
+    }
 
-        match try!(reply.popstr().unwrap().or(Err(Error::HostResponse))).as_ref() {
-            "Ok" => {
-                let pk = try!(reply.popstr().unwrap().or(Err(Error::HostResponse)));
-                Ok(try!(ZCert::from_txt(&pk, "0000000000000000000000000000000000000000")))
+    #[cfg(feature = "remote-run")]
+    /// Replace the API socket with a fresh connection to the Agent.
+    /// A REQ socket that has missed a reply cannot send another
+    /// request, so this is required to keep the Host usable after
+    /// a timeout.
+    ///
+    /// Hosts that were not created with `connect_endpoint()` or
+    /// `connect_insecure()` (e.g. payload hosts) have no endpoint to
+    /// reconnect to, so their socket is left untouched and this
+    /// returns false.
+    fn reset_api_sock(&mut self) -> Result<bool> {
+        match try!(self.agent_sock(SocketType::REQ)) {
+            Some(sock) => {
+                self.api_sock = Some(sock);
+                self.stale_replies = 0;
+                Ok(true)
             },
-            "Err" => Err(Error::Auth(try!(reply.popstr().unwrap().or(Err(Error::HostResponse))))),
-            _ => Err(Error::HostResponse),
+            None => Ok(false),
         }
     }
 
+    #[cfg(feature = "remote-run")]
+    /// Open a new socket to the Agent's API endpoint, using CURVE
+    /// if the Host was created with certificates.
+    fn agent_sock(&self, sock_type: SocketType) -> Result<Option<ZSock>> {
+        match (self.api_endpoint.as_ref(), self.user_cert.as_ref(), self.server_cert.as_ref()) {
+            (Some(e), Some(u), Some(s)) => Ok(Some(try!(Self::curve_sock(sock_type, u, s, self.socks_proxy.as_ref(), e)))),
+            (Some(e), None, None) => Ok(Some(try!(Self::plain_sock(sock_type, e)))),
+            _ => Ok(None),
+        }
+    }
+
+    #[cfg(feature = "remote-run")]
+    fn plain_sock(sock_type: SocketType, endpoint: &str) -> Result<ZSock> {
+        let mut sock = ZSock::new(sock_type);
+        sock.set_sndtimeo(Some(SOCK_TIMEOUT));
+        sock.set_rcvtimeo(Some(SOCK_TIMEOUT));
+        try!(sock.connect(endpoint));
+        Ok(sock)
+    }
+
     #[cfg(all(test, feature = "remote-run"))]
     pub fn test_new(hostname: Option<String>, api_sock: Option<ZSock>, file_sock: Option<ZSock>, data: Option<Value>) -> Host {
-        let host = Host {
-            hostname: hostname.unwrap_or(String::new()),
-            api_sock: api_sock,
-            file_sock: file_sock,
-            data: match data {
-                Some(d) => Rc::new(d),
-                None => Rc::new(Value::Null),
-            },
-        };
+        let mut host = Host::new(&hostname.unwrap_or(String::new()), Transport::Agent);
+        host.protocol_version = protocol::PROTOCOL_VERSION;
+        host.capabilities = protocol::CAPABILITIES.iter().map(|c| c.to_string()).collect();
+        host.api_sock = api_sock;
+        host.file_sock = file_sock;
+        if let Some(d) = data {
+            host.data = Rc::new(d);
+        }
 
         host
     }
+
+    #[cfg(all(test, feature = "remote-run"))]
+    pub fn test_set_stream_sock(&mut self, stream_sock: ZSock) {
+        self.stream_sock = Some(stream_sock);
+    }
 }
 
 #[cfg(feature = "remote-run")]
@@ -230,8 +475,12 @@ pub trait HostSendRecv {
     fn send_file(&mut self, msg: ZMsg) -> Result<()>;
     fn send_fs_file<P: AsRef<Path>>(&mut self, file: &mut zfilexfer::File, remote_path: P) -> Result<()>;
     fn recv(&mut self, min: usize, max: Option<usize>) -> Result<ZMsg>;
+    fn recv_timeout(&mut self, min: usize, max: Option<usize>, timeout: u64) -> Result<ZMsg>;
     fn recv_raw(&mut self) -> Result<ZMsg>;
     fn recv_file_raw(&mut self) -> Result<ZMsg>;
+    fn send_stream(&mut self, msg: ZMsg) -> Result<()>;
+    fn recv_stream(&mut self, timeout: Option<u64>) -> Result<ZMsg>;
+    fn close_stream(&mut self);
     fn extract_header(msg: &ZMsg) -> Result<()>;
 }
 
@@ -242,6 +491,7 @@ impl HostSendRecv for Host {
             return Err(Error::HostDisconnected);
         }
 
+        try!(keepalive::keepalive(self));
         try!(msg.send(self.api_sock.as_mut().unwrap()));
         Ok(())
     }
@@ -265,11 +515,55 @@ impl HostSendRecv for Host {
     }
 
     fn recv(&mut self, min: usize, max: Option<usize>) -> Result<ZMsg> {
+        let timeout = self.options.timeout;
+        self.recv_timeout(min, max, timeout)
+    }
+
+    fn recv_timeout(&mut self, min: usize, max: Option<usize>, timeout: u64) -> Result<ZMsg> {
         if self.api_sock.is_none() {
             return Err(Error::HostDisconnected);
         }
 
-        let msg = try!(ZMsg::recv(self.api_sock.as_mut().unwrap()));
+        let result = {
+            let default = timeout_ms(self.options.timeout);
+            let deadline = Instant::now() + Duration::from_millis(timeout_ms(timeout) as u64);
+            let sock = self.api_sock.as_mut().unwrap();
+
+            let result = loop {
+                let now = Instant::now();
+                let remaining = if now < deadline { deadline - now } else { Duration::from_millis(0) };
+                let ms = remaining.as_secs() * 1000 + (remaining.subsec_nanos() / 1_000_000) as u64;
+                sock.set_rcvtimeo(Some(if ms > i32::max_value() as u64 { i32::max_value() } else { ms as i32 }));
+
+                // Capture errno before anything else can overwrite it
+                match ZMsg::recv(sock).map_err(|e| (e, io::Error::last_os_error().raw_os_error())) {
+                    // Late replies to timed out requests arrive first,
+                    // so discard them rather than mistake them for the
+                    // reply to this request.
+                    Ok(_) if self.stale_replies > 0 => self.stale_replies -= 1,
+                    r => break r,
+                }
+            };
+
+            sock.set_rcvtimeo(Some(default));
+            result
+        };
+
+        let msg = match result {
+            Ok(msg) => msg,
+            // EAGAIN means that the Agent didn't reply in time, which
+            // leaves the REQ socket in an unusable state. If it can't
+            // be replaced, the late reply has to be skipped instead.
+            Err((_, Some(libc::EAGAIN))) => {
+                if !try!(self.reset_api_sock()) {
+                    self.stale_replies += 1;
+                }
+                return Err(Error::Timeout(format!("No reply from {} within {} seconds", self.hostname, timeout)));
+            },
+            Err((e, _)) => return Err(e.into()),
+        };
+
+        self.last_seen = Instant::now();
         try!(Self::extract_header(&msg));
 
         // Check msg size
@@ -285,7 +579,9 @@ impl HostSendRecv for Host {
             return Err(Error::HostDisconnected);
         }
 
-        Ok(try!(ZMsg::recv(self.api_sock.as_mut().unwrap())))
+        let msg = try!(ZMsg::recv(self.api_sock.as_mut().unwrap()));
+        self.last_seen = Instant::now();
+        Ok(msg)
     }
 
     fn recv_file_raw(&mut self) -> Result<ZMsg> {
@@ -296,6 +592,58 @@ impl HostSendRecv for Host {
         Ok(try!(ZMsg::recv(self.file_sock.as_mut().unwrap())))
     }
 
+    fn send_stream(&mut self, msg: ZMsg) -> Result<()> {
+        // Streamed replies can't be carried over the REQ socket, so
+        // open a DEALER socket to the same endpoint.
+        if self.stream_sock.is_none() {
+            self.stream_sock = Some(try!(try!(self.agent_sock(SocketType::DEALER)).ok_or(Error::HostDisconnected)));
+        }
+
+        // DEALER sockets have to add the empty delimiter frame that
+        // a REQ socket would send for us.
+        try!(msg.pushstr(""));
+        try!(msg.send(self.stream_sock.as_mut().unwrap()));
+        Ok(())
+    }
+
+    fn recv_stream(&mut self, timeout: Option<u64>) -> Result<ZMsg> {
+        if self.stream_sock.is_none() {
+            return Err(Error::HostDisconnected);
+        }
+
+        let result = {
+            let default = self.options.timeout;
+            let sock = self.stream_sock.as_mut().unwrap();
+            sock.set_rcvtimeo(Some(timeout_ms(timeout.unwrap_or(default))));
+            ZMsg::recv(sock)
+        };
+
+        match result {
+            Ok(msg) => {
+                if msg.size() == 0 || try!(msg.popstr().unwrap().or(Err(Error::HostResponse))) != "" {
+                    self.close_stream();
+                    Err(Error::HostResponse)
+                } else {
+                    Ok(msg)
+                }
+            },
+            Err(e) => {
+                self.close_stream();
+
+                match timeout {
+                    Some(t) => Err(Error::Timeout(format!("No reply from {} within {} seconds", self.hostname, t))),
+                    None => Err(e.into()),
+                }
+            },
+        }
+    }
+
+    fn close_stream(&mut self) {
+        // Dropping the socket discards any late replies, which would
+        // otherwise be received by the next stream.
+        self.stream_sock = None;
+    }
+
     fn extract_header(msg: &ZMsg) -> Result<()> {
         if msg.size() == 0 {
             return Err(Error::HostResponse);
@@ -315,19 +663,72 @@ impl HostSendRecv for Host {
     }
 }
 
+#[cfg(feature = "remote-run")]
+/// Convert a command timeout in seconds to a socket timeout in
+/// milliseconds, allowing the Agent time to reply.
+fn timeout_ms(timeout: u64) -> i32 {
+    let ms = timeout.saturating_mul(1000).saturating_add(TIMEOUT_GRACE as u64);
+    if ms > i32::max_value() as u64 { i32::max_value() } else { ms as i32 }
+}
+
 #[cfg(feature = "remote-run")]
 #[cfg(test)]
 mod tests {
     use czmq::{ZMsg, ZSock, SocketType, ZSys};
+    use error::Error;
     use std::fs;
+    use std::io::Write;
     use std::thread;
+    use std::time::Duration;
     use super::*;
     use tempdir::TempDir;
     use zfilexfer::File;
 
+    #[test]
+    fn test_connect_unknown_transport() {
+        let tempdir = TempDir::new("host_test_transport").unwrap();
+        let path = tempdir.path().join("host.json");
+        fs::File::create(&path).unwrap().write_all(br#"{"hostname": "localhost", "transport": "moo"}"#).unwrap();
+
+        match Host::connect(&path) {
+            Err(Error::Generic(e)) => assert_eq!(e, "Unknown transport `moo`"),
+            _ => panic!("Expected unknown transport error"),
+        }
+    }
+
+    #[test]
+    fn test_connect_local() {
+        let tempdir = TempDir::new("host_test_local").unwrap();
+        let path = tempdir.path().join("host.json");
+        fs::File::create(&path).unwrap().write_all(br#"{"transport": "local", "key": "value"}"#).unwrap();
+
+        let mut host = Host::connect(&path).unwrap();
+        assert_eq!(host.transport(), Transport::Local);
+        assert_eq!(host.data()["key"], json!("value"));
+
+        // Local hosts can be used alongside Agent hosts
+        let agent = Host::test_new(None, None, None, None);
+        assert_eq!(agent.transport(), Transport::Agent);
+
+        let result = ::Command::new("echo moo").exec(&mut host).unwrap();
+        assert_eq!(result.stdout, "moo");
+    }
+
+    #[test]
+    #[ignore]
+    fn test_connect_ssh() {
+        // Requires an sshd on localhost that accepts the current
+        // user's key without prompting.
+        let mut host = Host::connect_ssh("localhost", None, None, None).unwrap();
+        assert!(host.data().pointer("/_telemetry/hostname").is_some());
+
+        let result = ::Command::new("echo moo").exec(&mut host).unwrap();
+        assert_eq!(result.stdout, "moo");
+    }
+
     #[test]
     fn test_connect_payload() {
-        let api = ZSock::new(SocketType::DEALER);
+        let mut api = ZSock::new(SocketType::DEALER);
         let port = api.bind("tcp://127.0.0.1:*").unwrap();
         let api_endpoint = format!("tcp://127.0.0.1:{}", port);
 
@@ -336,11 +737,22 @@ mod tests {
                 "key": "value"
             }"#).unwrap();
 
+            let msg = ZMsg::recv(&mut api).unwrap();
+            assert_eq!(msg.popstr().unwrap().unwrap(), "hello");
+
+            let reply = ZMsg::new();
+            reply.addstr("Ok").unwrap();
+            reply.addstr(&protocol::PROTOCOL_VERSION.to_string()).unwrap();
+            reply.addstr("command::exec").unwrap();
+            reply.send(&mut api).unwrap();
+
             api.recv_str().unwrap().unwrap();
         });
 
         let mut host = Host::connect_payload(&api_endpoint, "inproc://file_endpoint").unwrap();
         assert_eq!(host.data()["key"], json!("value"));
+        assert!(host.supports("command::exec"));
+        assert!(!host.supports("command::exec_stream"));
 
         let msg = ZMsg::new();
         msg.addstr("test").unwrap();
@@ -349,6 +761,60 @@ mod tests {
         handle.join().unwrap();
     }
 
+    #[test]
+    fn test_payload_timeout() {
+        let mut api = ZSock::new(SocketType::DEALER);
+        let port = api.bind("tcp://127.0.0.1:*").unwrap();
+        let api_endpoint = format!("tcp://127.0.0.1:{}", port);
+
+        fn reply(sock: &mut ZSock, stdout: &str) {
+            let reply = ZMsg::new();
+            reply.addstr("Ok").unwrap();
+            reply.addstr("0").unwrap();
+            reply.addstr(stdout).unwrap();
+            reply.addstr("").unwrap();
+            reply.send(sock).unwrap();
+        }
+
+        let handle = thread::spawn(move || {
+            api.send_str("{}").unwrap();
+
+            ZMsg::recv(&mut api).unwrap();
+            let hello = ZMsg::new();
+            hello.addstr("Ok").unwrap();
+            hello.addstr(&protocol::PROTOCOL_VERSION.to_string()).unwrap();
+            hello.send(&mut api).unwrap();
+
+            // Reply to the first command after it has timed out
+            let msg = ZMsg::recv(&mut api).unwrap();
+            msg.popstr().unwrap().unwrap();
+            assert_eq!(msg.popstr().unwrap().unwrap(), "slow");
+            thread::sleep(Duration::from_millis(3500));
+            reply(&mut api, "slow");
+
+            let msg = ZMsg::recv(&mut api).unwrap();
+            msg.popstr().unwrap().unwrap();
+            assert_eq!(msg.popstr().unwrap().unwrap(), "fast");
+            reply(&mut api, "fast");
+        });
+
+        let mut host = Host::connect_payload(&api_endpoint, "inproc://file_endpoint").unwrap();
+
+        // Payload hosts can't reconnect, so the late reply has to be
+        // discarded rather than returned for the next command.
+        let mut cmd = ::Command::new("slow");
+        cmd.set_timeout(Some(1));
+        match cmd.exec(&mut host) {
+            Err(Error::Timeout(_)) => (),
+            _ => panic!("Expected command to time out"),
+        }
+
+        let result = ::Command::new("fast").exec(&mut host).unwrap();
+        assert_eq!(result.stdout, "fast");
+
+        handle.join().unwrap();
+    }
+
     #[test]
     fn test_send_recv() {
         let _ = ::_MOCK_ENV.init();
@@ -0,0 +1,178 @@
+// Copyright 2015-2017 Intecture Developers. See the COPYRIGHT file at the
+// top-level directory of this distribution and at
+// https://intecture.io/COPYRIGHT.
+//
+// Licensed under the Mozilla Public License 2.0 <LICENSE or
+// https://www.tldrlegal.com/l/mpl-2.0>. This file may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Versioning for the Agent protocol.
+//!
+//! When a Host connects to an Agent, it sends a "hello" message with
+//! the newest protocol version that it speaks:
+//!
+//! `["hello", "<version>"]`
+//!
+//! The Agent replies with the version it will speak, which must not
+//! be newer than the Host's, followed by the message types that it
+//! supports:
+//!
+//! `["Ok", "<version>", "<capability>", ...]`
+//!
+//! If the Agent can't speak a compatible version, it replies with an
+//! error instead. Agents that predate versioning may not reply at
+//! all, so the Host only waits for `AgentOptions::handshake_timeout`.
+
+use czmq::ZMsg;
+use error::{Error, Result};
+use super::{Host, HostSendRecv};
+
+/// Newest protocol version spoken by this API.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Oldest protocol version spoken by this API.
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// Message types that this API knows how to send.
+pub const CAPABILITIES: &'static [&'static str] = &[
+    "batch",
+    "command::exec",
+    "command::exec_stream",
+    "directory::is_directory",
+    "directory::exists",
+    "directory::create",
+    "directory::delete",
+    "directory::mv",
+    "directory::get_owner",
+    "directory::set_owner",
+    "directory::get_mode",
+    "directory::set_mode",
+    "file::is_file",
+    "file::exists",
+    "file::delete",
+    "file::mv",
+    "file::copy",
+    "file::get_owner",
+    "file::set_owner",
+    "file::get_mode",
+    "file::set_mode",
+    "package::default_provider",
+    "ping",
+    "service::action",
+    "telemetry",
+];
+
+/// Negotiate a protocol version with the Agent, returning the
+/// version and the Agent's capabilities.
+pub fn hello(host: &mut Host) -> Result<(u32, Vec<String>)> {
+    let msg = ZMsg::new();
+    try!(msg.addstr("hello"));
+    try!(msg.addstr(&PROTOCOL_VERSION.to_string()));
+    try!(host.send(msg));
+
+    // Agents that predate versioning reply with an error (or
+    // garbage), or may not reply at all, so don't wait for the usual
+    // request timeout.
+    let timeout = host.agent_options().handshake_timeout;
+    let reply = match host.recv_timeout(0, None, timeout) {
+        Ok(reply) => reply,
+        Err(Error::Timeout(_)) => return Err(Error::Protocol("Agent did not answer the version handshake".into())),
+        Err(Error::Agent(e)) => {
+            return Err(Error::Protocol(format!("Agent rejected protocol version {}: {}. The Agent may be too old for this API.", PROTOCOL_VERSION, e)));
+        },
+        Err(Error::HostResponse) => {
+            return Err(Error::Protocol("Invalid reply to protocol handshake. The Agent may be too old for this API.".into()));
+        },
+        Err(e) => return Err(e),
+    };
+
+    let version = match reply.popstr() {
+        Some(Ok(v)) => try!(v.parse::<u32>().or(Err(Error::Protocol(format!("Invalid protocol version `{}` from Agent", v))))),
+        _ => return Err(Error::Protocol("Agent did not send a protocol version".into())),
+    };
+
+    if version < MIN_PROTOCOL_VERSION || version > PROTOCOL_VERSION {
+        return Err(Error::Protocol(format!("Agent speaks protocol version {}, but this API requires a version from {} to {}",
+                                           version, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION)));
+    }
+
+    let mut capabilities = Vec::new();
+    while let Some(c) = reply.popstr() {
+        capabilities.push(try!(c.or(Err(Error::HostResponse))));
+    }
+
+    Ok((version, capabilities))
+}
+
+#[cfg(test)]
+mod tests {
+    use czmq::{ZMsg, ZSys};
+    use error::Error;
+    use host::{AgentOptions, Host};
+    use std::thread;
+    use super::*;
+
+    #[test]
+    fn test_hello() {
+        ZSys::init();
+
+        let (client, mut server) = ZSys::create_pipe().unwrap();
+
+        let agent_mock = thread::spawn(move || {
+            let msg = ZMsg::recv(&mut server).unwrap();
+            assert_eq!("hello", msg.popstr().unwrap().unwrap());
+            assert_eq!(PROTOCOL_VERSION.to_string(), msg.popstr().unwrap().unwrap());
+
+            let reply = ZMsg::new();
+            reply.addstr("Ok").unwrap();
+            reply.addstr(&PROTOCOL_VERSION.to_string()).unwrap();
+            reply.addstr("command::exec").unwrap();
+            reply.addstr("telemetry").unwrap();
+            reply.send(&mut server).unwrap();
+
+            server.recv_str().unwrap().unwrap();
+
+            let reply = ZMsg::new();
+            reply.addstr("Ok").unwrap();
+            reply.addstr("999").unwrap();
+            reply.send(&mut server).unwrap();
+
+            server.recv_str().unwrap().unwrap();
+
+            let reply = ZMsg::new();
+            reply.addstr("Err").unwrap();
+            reply.addstr("Unknown endpoint").unwrap();
+            reply.send(&mut server).unwrap();
+
+            // Never answer the last handshake
+            server.recv_str().unwrap().unwrap();
+        });
+
+        let mut host = Host::test_new(None, Some(client), None, None);
+
+        let (version, capabilities) = hello(&mut host).unwrap();
+        assert_eq!(version, PROTOCOL_VERSION);
+        assert_eq!(capabilities, vec!["command::exec", "telemetry"]);
+
+        match hello(&mut host) {
+            Err(Error::Protocol(e)) => assert!(e.contains("999")),
+            _ => panic!("Expected protocol error"),
+        }
+
+        match hello(&mut host) {
+            Err(Error::Protocol(e)) => assert!(e.contains("Unknown endpoint")),
+            _ => panic!("Expected protocol error"),
+        }
+
+        host.set_agent_options(AgentOptions {
+            handshake_timeout: 0,
+            ..AgentOptions::default()
+        });
+        match hello(&mut host) {
+            Err(Error::Protocol(e)) => assert_eq!(e, "Agent did not answer the version handshake"),
+            _ => panic!("Expected protocol error"),
+        }
+
+        agent_mock.join().unwrap();
+    }
+}
@@ -0,0 +1,325 @@
+// Copyright 2015-2017 Intecture Developers. See the COPYRIGHT file at the
+// top-level directory of this distribution and at
+// https://intecture.io/COPYRIGHT.
+//
+// Licensed under the Mozilla Public License 2.0 <LICENSE or
+// https://www.tldrlegal.com/l/mpl-2.0>. This file may not be copied,
+// modified, or distributed except according to those terms.
+
+//! SSH session for hosts that don't run the Intecture Agent.
+//!
+//! Rather than linking against an SSH library, the session drives
+//! the system's OpenSSH client, so it honours the user's
+//! `~/.ssh/config`, agent and known hosts. Connections are shared
+//! between commands using OpenSSH's `ControlMaster` multiplexing.
+
+use command::{CommandResult, CommandStream};
+use error::{Error, Result};
+use std::env;
+use std::io::{self, Read};
+use std::process;
+use target::process as process_exec;
+
+/// Seconds that a shared connection stays open after its last use.
+const CONTROL_PERSIST: u32 = 60;
+/// Seconds to wait beyond a command's timeout before killing the SSH
+/// client, for hosts that can't enforce the timeout themselves.
+const TIMEOUT_GRACE: u64 = 10;
+/// Exit status of the remote wrapper when the command timed out.
+const TIMEOUT_STATUS: i32 = 124;
+/// Line that the remote wrapper writes to stderr when the command
+/// timed out, so that a command that exits with `TIMEOUT_STATUS`
+/// itself isn't mistaken for a timeout.
+const TIMEOUT_MARKER: &'static str = "inapi: command timed out";
+/// Exit status of the SSH client when it couldn't run the command.
+const SSH_ERROR_STATUS: i32 = 255;
+/// Prefixes of the errors that the SSH client reports when it fails
+/// to connect or authenticate.
+const SSH_ERRORS: &'static [&'static str] = &[
+    "ssh: ",
+    "ssh_exchange_identification: ",
+    "kex_exchange_identification: ",
+    "Permission denied",
+    "Host key verification failed",
+    "Connection closed by",
+    "Connection reset by",
+    "Connection timed out",
+    "Control socket connect",
+];
+/// Bytes of stderr to keep from a streamed command, to check for an
+/// SSH error.
+const STDERR_TAIL: usize = 4096;
+
+/// Connection details for a host that is managed over SSH.
+pub struct SshSession {
+    /// Hostname or IP of managed host
+    hostname: String,
+    /// SSH port, if not the default
+    port: Option<u32>,
+    /// Remote user, if not the current user
+    user: Option<String>,
+    /// Path to a private key, if not the default
+    identity: Option<String>,
+}
+
+impl SshSession {
+    pub fn new(hostname: &str, port: Option<u32>, user: Option<&str>, identity: Option<&str>) -> SshSession {
+        SshSession {
+            hostname: hostname.into(),
+            port: port,
+            user: user.map(|u| u.into()),
+            identity: identity.map(|i| i.into()),
+        }
+    }
+
+    /// Check that the host is reachable and that we can
+    /// authenticate without prompting.
+    pub fn check(&self) -> Result<()> {
+        let output = try!(self.command().arg("true").output());
+
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(Error::Generic(format!("Could not connect to {} over SSH: {}",
+                                       self.hostname,
+                                       String::from_utf8_lossy(&output.stderr).trim())))
+        }
+    }
+
+    /// Run a shell command on the host.
+    ///
+    /// If the command times out, its process group is killed on the
+    /// host. This requires `setsid` on the host, otherwise only the
+    /// SSH client is killed, which may leave the remote process
+    /// running until it next writes to its output.
+    pub fn exec(&self, cmd: &str, timeout: Option<u64>) -> Result<CommandResult> {
+        let result = try!(process_exec::exec(self.shell(cmd, timeout), cmd, timeout.map(|t| t.saturating_add(TIMEOUT_GRACE))));
+        try!(self.check_result(&result, &result.stderr, cmd, timeout));
+        Ok(result)
+    }
+
+    /// Run a shell command on the host, streaming its output.
+    pub fn exec_stream(&self, cmd: &str, timeout: Option<u64>, handler: &mut FnMut(CommandStream, &[u8])) -> Result<CommandResult> {
+        let mut stderr = Vec::new();
+
+        let result = try!(process_exec::exec_stream(self.shell(cmd, timeout), cmd, timeout.map(|t| t.saturating_add(TIMEOUT_GRACE)), &mut |stream, chunk| {
+            if stream == CommandStream::Stderr {
+                stderr.extend_from_slice(chunk);
+                if stderr.len() > STDERR_TAIL {
+                    let excess = stderr.len() - STDERR_TAIL;
+                    stderr.drain(..excess);
+                }
+            }
+
+            handler(stream, chunk)
+        }));
+
+        try!(self.check_result(&result, String::from_utf8_lossy(&stderr).trim(), cmd, timeout));
+        Ok(result)
+    }
+
+    /// Turn the remote wrapper's timeout and the SSH client's own
+    /// failures into errors, rather than results of the command.
+    fn check_result(&self, result: &CommandResult, stderr: &str, cmd: &str, timeout: Option<u64>) -> Result<()> {
+        let last_line = stderr.lines().last().unwrap_or("");
+
+        match (result.exit_code, timeout) {
+            (TIMEOUT_STATUS, Some(t)) if last_line == TIMEOUT_MARKER => {
+                Err(Error::Timeout(format!("Command `{}` did not complete within {} seconds", cmd, t)))
+            },
+            (SSH_ERROR_STATUS, _) if SSH_ERRORS.iter().any(|e| last_line.starts_with(e)) => {
+                Err(Error::Generic(format!("Could not run command on {} over SSH: {}", self.hostname, last_line)))
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// Write the contents of `reader` to `remote_path` on the host.
+    ///
+    /// The file is written to a temporary path and moved into place,
+    /// so a failed upload never leaves a partial file behind. If
+    /// `backup_suffix` is set, any existing file is first copied to
+    /// `remote_path` + `backup_suffix`.
+    pub fn upload<R: Read>(&self, reader: &mut R, remote_path: &str, backup_suffix: Option<&str>) -> Result<()> {
+        let path = quote(remote_path);
+        let tmp = quote(&format!("{}.inapi-upload", remote_path));
+        let mut script = String::from("set -e; ");
+        if let Some(suffix) = backup_suffix {
+            let backup = quote(&format!("{}{}", remote_path, suffix));
+            script.push_str(&format!("if [ -e {0} ]; then cp -p {0} {1}; fi; ", path, backup));
+        }
+        script.push_str(&format!("cat > {0}; mv {0} {1}", tmp, path));
+
+        let mut child = try!(self.shell(&script, None)
+                                 .stdin(process::Stdio::piped())
+                                 .stdout(process::Stdio::null())
+                                 .stderr(process::Stdio::piped())
+                                 .spawn());
+
+        {
+            // Drop stdin once we're done so that `cat` sees EOF
+            let mut stdin = child.stdin.take().unwrap();
+            try!(io::copy(reader, &mut stdin));
+        }
+
+        let output = try!(child.wait_with_output());
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(Error::Generic(format!("Could not upload file to {}: {}",
+                                       remote_path,
+                                       String::from_utf8_lossy(&output.stderr).trim())))
+        }
+    }
+
+    fn shell(&self, cmd: &str, timeout: Option<u64>) -> process::Command {
+        let script = match timeout {
+            Some(t) => timeout_script(cmd, t),
+            None => format!("sh -c {}", quote(cmd)),
+        };
+
+        let mut command = self.command();
+        // The remote command is interpreted by the user's login
+        // shell, so wrap it to make sure it is run by `sh` as it
+        // would be locally.
+        command.arg(format!("sh -c {}", quote(&script)));
+        command
+    }
+
+    fn command(&self) -> process::Command {
+        let mut control_path = env::temp_dir();
+        control_path.push("inapi-ssh-%r@%h:%p");
+
+        let mut command = process::Command::new("ssh");
+        command.arg("-o").arg("BatchMode=yes")
+               .arg("-o").arg("ControlMaster=auto")
+               .arg("-o").arg(format!("ControlPath={}", control_path.display()))
+               .arg("-o").arg(format!("ControlPersist={}", CONTROL_PERSIST));
+
+        if let Some(port) = self.port {
+            command.arg("-p").arg(port.to_string());
+        }
+
+        if let Some(ref user) = self.user {
+            command.arg("-l").arg(user);
+        }
+
+        if let Some(ref identity) = self.identity {
+            command.arg("-i").arg(identity);
+        }
+
+        command.arg(&self.hostname).arg("--");
+        command
+    }
+}
+
+/// Build a `sh` script that runs `cmd` in its own session, so that a
+/// watchdog can kill its whole process group once `timeout` seconds
+/// have passed. The watchdog leaves a file behind when it fires, so
+/// that the script can tell a timeout apart from the command's own
+/// exit status.
+fn timeout_script(cmd: &str, timeout: u64) -> String {
+    format!("if ! command -v setsid >/dev/null 2>&1; then exec sh -c {cmd}; fi
+f=\"${{TMPDIR:-/tmp}}/inapi-timeout.$$\"
+rm -f \"$f\"
+setsid sh -c {cmd} & c=$!
+setsid sh -c \"sleep {timeout}; touch '$f'; kill -KILL -$c\" >/dev/null 2>&1 & w=$!
+wait $c; s=$?
+kill -KILL -$w 2>/dev/null
+if [ -e \"$f\" ]; then rm -f \"$f\"; echo {marker} >&2; exit {status}; fi
+exit $s",
+            cmd = quote(cmd),
+            timeout = timeout,
+            marker = quote(TIMEOUT_MARKER),
+            status = TIMEOUT_STATUS)
+}
+
+/// Quote a string for safe interpolation into a `sh` command line.
+pub fn quote(s: &str) -> String {
+    format!("'{}'", s.replace("'", "'\\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use command::CommandResult;
+    use error::Error;
+    use std::env;
+    use std::process;
+    use super::*;
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("moo"), "'moo'");
+        assert_eq!(quote("it's"), "'it'\\''s'");
+    }
+
+    #[test]
+    fn test_timeout_script() {
+        // The script is run by the host's `sh`, so it can be tested
+        // locally without an sshd.
+        let output = process::Command::new("sh").arg("-c").arg(timeout_script("echo 'moo'; exit 3", 5)).output().unwrap();
+        assert_eq!(output.status.code(), Some(3));
+        assert_eq!(output.stdout, b"moo\n");
+
+        let output = process::Command::new("sh").arg("-c").arg(timeout_script("sleep 30 & sleep 5; echo moo", 1)).output().unwrap();
+        assert_eq!(output.status.code(), Some(TIMEOUT_STATUS));
+        assert!(output.stdout.is_empty());
+        assert!(String::from_utf8_lossy(&output.stderr).trim().ends_with(TIMEOUT_MARKER));
+    }
+
+    #[test]
+    fn test_check_result() {
+        let session = SshSession::new("localhost", None, None, None);
+        let result = |code, stderr: &str| CommandResult::from_raw(Some(code), None, Vec::new(), stderr.as_bytes().to_vec());
+
+        let timed_out = result(TIMEOUT_STATUS, TIMEOUT_MARKER);
+        match session.check_result(&timed_out, &timed_out.stderr, "sleep 5", Some(1)) {
+            Err(Error::Timeout(_)) => (),
+            _ => panic!("Expected a timeout"),
+        }
+
+        let exited = result(TIMEOUT_STATUS, "moo");
+        assert!(session.check_result(&exited, &exited.stderr, "exit 124", Some(1)).is_ok());
+
+        let refused = result(SSH_ERROR_STATUS, "ssh: connect to host localhost port 22: Connection refused");
+        match session.check_result(&refused, &refused.stderr, "true", None) {
+            Err(Error::Generic(e)) => assert!(e.contains("Connection refused")),
+            _ => panic!("Expected an SSH error"),
+        }
+
+        let exited = result(SSH_ERROR_STATUS, "moo");
+        assert!(session.check_result(&exited, &exited.stderr, "exit 255", None).is_ok());
+    }
+
+    // These tests need an sshd running on localhost that accepts
+    // the current user's key without prompting. Run them with
+    // `cargo test -- --ignored`.
+
+    #[test]
+    #[ignore]
+    fn test_exec() {
+        let session = SshSession::new("localhost", None, None, None);
+        session.check().unwrap();
+
+        let result = session.exec("echo 'moo'; echo cow >&2; exit 3", None).unwrap();
+        assert_eq!(result.exit_code, 3);
+        assert_eq!(result.stdout, "moo");
+        assert_eq!(result.stderr, "cow");
+    }
+
+    #[test]
+    #[ignore]
+    fn test_upload() {
+        let session = SshSession::new("localhost", None, None, None);
+        let mut path = env::temp_dir();
+        path.push("inapi_ssh_test_upload");
+        let path = path.to_str().unwrap().to_string();
+
+        session.upload(&mut "moo".as_bytes(), &path, None).unwrap();
+        session.upload(&mut "cow".as_bytes(), &path, Some("_bk")).unwrap();
+
+        assert_eq!(session.exec(&format!("cat {}", quote(&path)), None).unwrap().stdout, "cow");
+        assert_eq!(session.exec(&format!("cat {}_bk", quote(&path)), None).unwrap().stdout, "moo");
+        session.exec(&format!("rm -f {0} {0}_bk", quote(&path)), None).unwrap();
+    }
+}
@@ -7,14 +7,11 @@
 // modified, or distributed except according to those terms.
 
 use error::Result;
-use host::Host;
-#[cfg(feature = "local-run")]
+use host::{Host, Transport};
 use serde_json::Map;
 use serde_json::Value;
 use target::Target;
-#[cfg(feature = "local-run")]
 
-#[cfg(feature = "local-run")]
 #[derive(Debug, Serialize)]
 pub struct Telemetry {
     pub cpu: Cpu,
@@ -25,11 +22,7 @@ pub struct Telemetry {
     pub os: Os,
 }
 
-#[cfg(feature = "remote-run")]
-pub struct Telemetry;
-
 impl Telemetry {
-    #[cfg(feature = "local-run")]
     pub fn new(cpu: Cpu, fs: Vec<FsMount>, hostname: &str, memory: u64, net: Vec<Netif>, os: Os) -> Telemetry {
         Telemetry {
             cpu: cpu,
@@ -41,27 +34,26 @@ impl Telemetry {
         }
     }
 
-    #[cfg(feature = "local-run")]
     pub fn init(host: &mut Host) -> Result<Value> {
         let t = try!(Target::telemetry_init(host));
 
+        // Remote transports return telemetry that is already
+        // namespaced.
+        if host.transport() != Transport::Local {
+            return Ok(t);
+        }
+
         // Make sure telemetry is namespaced
         let mut t_map: Map<String, Value> = Map::new();
         t_map.insert("_telemetry".into(), t);
         Ok(json!(t_map))
     }
-
-    #[cfg(feature = "remote-run")]
-    pub fn init(host: &mut Host) -> Result<Value> {
-        Ok(try!(Target::telemetry_init(host)))
-    }
 }
 
 pub trait TelemetryTarget {
     fn telemetry_init(host: &mut Host) -> Result<Value>;
 }
 
-#[cfg(feature = "local-run")]
 #[derive(Debug, Serialize)]
 pub struct Cpu {
     pub vendor: String,
@@ -69,7 +61,6 @@ pub struct Cpu {
     pub cores: u32,
 }
 
-#[cfg(feature = "local-run")]
 impl Cpu {
     pub fn new(vendor: &str, brand_string: &str, cores: u32) -> Cpu {
         Cpu {
@@ -80,7 +71,6 @@ impl Cpu {
     }
 }
 
-#[cfg(feature = "local-run")]
 #[derive(Debug, Serialize)]
 pub struct FsMount {
     pub filesystem: String,
@@ -91,7 +81,6 @@ pub struct FsMount {
     pub capacity: f32,
 }
 
-#[cfg(feature = "local-run")]
 #[derive(Debug, Serialize)]
 pub struct Netif {
     pub name: String,
@@ -101,7 +90,6 @@ pub struct Netif {
     pub flags: u32,
 }
 
-#[cfg(feature = "local-run")]
 #[derive(Debug, Serialize)]
 pub struct Os {
     pub arch: String,
@@ -113,7 +101,6 @@ pub struct Os {
     pub version_patch: u32,
 }
 
-#[cfg(feature = "local-run")]
 impl Os {
     pub fn new(arch: &str, family: &str, platform: &str, version_str: &str, version_maj: u32, version_min: u32, version_patch: u32) -> Os {
         Os {
@@ -130,10 +117,8 @@ impl Os {
 
 #[cfg(test)]
 mod tests {
-    #[cfg(feature = "local-run")]
     use Host;
 
-    #[cfg(feature = "local-run")]
     #[test]
     fn test_telemetry_init() {
         let path: Option<String> = None;
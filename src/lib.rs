@@ -18,30 +18,52 @@
 //! via several ZeroMQ sockets. If the service is not running on your
 //! host when you run you code, the program will hang while it attempts
 //! to connect to a non-existent socket.
+//!
+//! Alternatively, hosts that don't run the Agent can be managed over
+//! SSH by setting `"transport": "ssh"` in the host's data file. This
+//! uses the system's `ssh` client, so authentication must not require
+//! a password.
+//!
+//! The machine running your code can be managed directly with
+//! `Host::local`, or by setting `"transport": "local"`. Transports are
+//! chosen at runtime, so a single program can manage local and remote
+//! hosts side by side. The Agent transport requires the `remote-run`
+//! feature, which is enabled by default.
+//!
+//! For testing code that targets the Agent, `LocalAgent` runs an
+//! in-process Agent that manages the local machine.
 
 #[cfg(feature = "remote-run")]
 extern crate czmq;
-#[cfg(not(feature = "local-run"))]
 #[macro_use]
 extern crate lazy_static;
 extern crate libc;
 extern crate mustache;
 extern crate regex;
 extern crate rustc_serialize;
+#[macro_use]
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 #[macro_use]
 extern crate serde_json;
+extern crate serde_yaml;
+extern crate sodiumoxide;
 #[cfg(test)]
 extern crate tempdir;
 extern crate tempfile;
+extern crate toml;
+#[cfg(feature = "remote-run")]
+extern crate zdaemon;
 extern crate zfilexfer;
 extern crate hostname;
 extern crate pnet;
 
 #[macro_use]
 mod ffi_helpers;
+#[cfg(feature = "remote-run")]
+mod agent;
+mod batch;
 mod command;
 mod project;
 mod directory;
@@ -58,12 +80,20 @@ mod service;
 mod target;
 mod template;
 
-pub use command::{Command, CommandResult, ffi as command_ffi};
+#[cfg(feature = "remote-run")]
+pub use agent::LocalAgent;
+pub use batch::{Batch, BatchOutput};
+pub use command::{Command, CommandResult, CommandStream, ffi as command_ffi};
 pub use directory::{Directory, DirectoryOpts, ffi as directory_ffi};
-pub use error::{Error, geterr};
+pub use error::{DataError, Error, geterr};
 pub use file::{File, FileOwner, ffi as file_ffi};
-pub use host::{Host, ffi as host_ffi};
-pub use host::data::open as data_open;
+pub use host::{Host, HostGroup, Transport, ffi as host_ffi};
+#[cfg(feature = "remote-run")]
+pub use host::{AgentOptions, HostBuilder, KnownHosts, Proxy};
+pub use host::data::{explain as data_explain, get as data_get, open as data_open, Origin as DataOrigin, SecretKey};
+pub use host::data::{trace as data_trace, Trace as DataTrace};
+pub use host::data::{redact as data_redact, redact_value as data_redact_value};
+pub use host::data::{validate as data_validate, Violation as DataViolation};
 pub use mustache::{MapBuilder, VecBuilder};
 pub use package::{Package, ffi as package_ffi};
 pub use package::providers::{Providers, ProviderFactory};
@@ -79,14 +109,11 @@ pub use service::{Service, ServiceRunnable, ffi as service_ffi};
 pub use template::{Template, ffi as template_ffi};
 pub use zfilexfer::FileOptions;
 
-#[cfg(feature = "remote-run")]
 use std::fs;
-#[cfg(feature = "remote-run")]
 use std::io::Read;
 #[cfg(feature = "remote-run")]
 #[cfg(test)]
 use std::io::Write;
-#[cfg(feature = "remote-run")]
 use std::path::Path;
 
 #[cfg(all(test, feature = "remote-run"))]
@@ -94,12 +121,6 @@ lazy_static! {
     static ref _MOCK_ENV: mock_env::MockEnv = mock_env::MockEnv::new();
 }
 
-#[cfg(feature = "remote-run")]
-lazy_static! {
-    static ref PROJECT_CONFIG: project::ProjectConfig = read_conf("project.json").expect("Could not load project.json");
-}
-
-#[cfg(feature = "remote-run")]
 fn read_conf<T: serde::Deserialize, P: AsRef<Path>>(path: P) -> error::Result<T> {
     let mut fh = fs::File::open(&path)?;
     let mut json = String::new();
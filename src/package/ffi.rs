@@ -113,9 +113,8 @@ mod tests {
     use std::thread;
     use super::*;
 
-    #[cfg(feature = "local-run")]
     #[test]
-    fn test_package_new_default() {
+    fn test_package_new_default_local() {
         let path: Option<String> = None;
         let host = Host::local(path).unwrap();
         let name = CString::new("nginx").unwrap().into_raw();
@@ -206,7 +205,6 @@ mod tests {
         agent_mock.join().unwrap();
     }
 
-    #[cfg(feature = "local-run")]
     #[test]
     fn test_package_is_installed() {
         let path: Option<String> = None;
@@ -24,8 +24,8 @@ use self::providers::*;
 ///
 /// ```no_run
 /// # use inapi::Host;
-#[cfg_attr(feature = "local-run", doc = "let path: Option<String> = None;")]
-#[cfg_attr(feature = "local-run", doc = "let mut host = Host::local(path).unwrap();")]
+#[cfg_attr(not(feature = "remote-run"), doc = "let path: Option<String> = None;")]
+#[cfg_attr(not(feature = "remote-run"), doc = "let mut host = Host::local(path).unwrap();")]
 #[cfg_attr(feature = "remote-run", doc = "let mut host = Host::connect(\"hosts/myhost.json\").unwrap();")]
 /// ```
 ///
@@ -33,8 +33,8 @@ use self::providers::*;
 ///
 /// ```no_run
 /// # use inapi::{Host, Package};
-#[cfg_attr(feature = "local-run", doc = "# let path: Option<String> = None;")]
-#[cfg_attr(feature = "local-run", doc = "# let mut host = Host::local(path).unwrap();")]
+#[cfg_attr(not(feature = "remote-run"), doc = "# let path: Option<String> = None;")]
+#[cfg_attr(not(feature = "remote-run"), doc = "# let mut host = Host::local(path).unwrap();")]
 #[cfg_attr(feature = "remote-run", doc = "# let mut host = Host::connect(\"hosts/myhost.json\").unwrap();")]
 ///let mut package = Package::new(&mut host, "nginx", None).unwrap();
 ///package.install(&mut host);
@@ -46,8 +46,8 @@ use self::providers::*;
 ///
 /// ```no_run
 /// # use inapi::{Host, Package, Providers};
-#[cfg_attr(feature = "local-run", doc = "# let path: Option<String> = None;")]
-#[cfg_attr(feature = "local-run", doc = "# let mut host = Host::local(path).unwrap();")]
+#[cfg_attr(not(feature = "remote-run"), doc = "# let path: Option<String> = None;")]
+#[cfg_attr(not(feature = "remote-run"), doc = "# let mut host = Host::local(path).unwrap();")]
 #[cfg_attr(feature = "remote-run", doc = "# let mut host = Host::connect(\"hosts/myhost.json\").unwrap();")]
 ///let mut package = Package::new(&mut host, "nginx", Some(Providers::Homebrew)).unwrap();
 ///package.install(&mut host);
@@ -167,9 +167,8 @@ mod tests {
         agent_mock.join().unwrap();
     }
 
-    #[cfg(feature = "local-run")]
     #[test]
-    fn test_new_default() {
+    fn test_new_default_local() {
         let path: Option<String> = None;
         let mut host = Host::local(path).unwrap();
         let pkg = Package::new(&mut host, "nginx", None);
@@ -21,6 +21,7 @@ use host::Host;
 pub use self::homebrew::Homebrew;
 use std::convert;
 use std::panic;
+use std::str::FromStr;
 use std::string::ToString;
 use super::PackageTarget;
 use target::Target;
@@ -54,16 +55,24 @@ impl ToString for Providers {
 
 impl convert::From<String> for Providers {
     fn from(provider: String) -> Providers {
-        match provider.as_ref() {
-            "Apt" => Providers::Apt,
-            "Dnf" => Providers::Dnf,
-            "Homebrew" => Providers::Homebrew,
-            "Macports" => Providers::Macports,
-            "Pkg" => Providers::Pkg,
-            "Ports" => Providers::Ports,
-            "Yum" => Providers::Yum,
-            "Nix" => Providers::Nix,
-            _ => panic!("Invalid provider"),
+        provider.parse().expect("Invalid provider")
+    }
+}
+
+impl FromStr for Providers {
+    type Err = Error;
+
+    fn from_str(provider: &str) -> Result<Providers> {
+        match provider {
+            "Apt" => Ok(Providers::Apt),
+            "Dnf" => Ok(Providers::Dnf),
+            "Homebrew" => Ok(Providers::Homebrew),
+            "Macports" => Ok(Providers::Macports),
+            "Pkg" => Ok(Providers::Pkg),
+            "Ports" => Ok(Providers::Ports),
+            "Yum" => Ok(Providers::Yum),
+            "Nix" => Ok(Providers::Nix),
+            _ => Err(Error::Generic(format!("Invalid provider `{}`", provider))),
         }
     }
 }
@@ -7,6 +7,7 @@
 // modified, or distributed except according to those terms.
 
 use project::Language;
+use serde_json::Value;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Config {
@@ -14,4 +15,7 @@ pub struct Config {
     pub repository: String,
     pub language: Language,
     pub dependencies: Option<Vec<String>>,
+    /// JSON Schema for host data, or the path of a schema file
+    /// relative to the payload
+    pub schema: Option<Value>,
 }
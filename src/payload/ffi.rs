@@ -93,6 +93,7 @@ mod tests {
             repository: "https://github.com/dhibbz/hehehe.git".into(),
             language: Language::Php,
             dependencies: None,
+            schema: None,
         };
 
         buf.push("payload.json");
@@ -116,6 +117,7 @@ mod tests {
             repository: "https://github.com/dhibbz/hehehe.git".into(),
             language: Language::Php,
             dependencies: None,
+            schema: None,
         };
 
         let mut buf = tempdir.path().to_owned();
@@ -156,6 +158,7 @@ mod tests {
             repository: "https://github.com/dhibbz/hehehe.git".into(),
             language: Language::Rust,
             dependencies: None,
+            schema: None,
         };
 
         buf.push("payload.json");
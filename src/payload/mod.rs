@@ -14,10 +14,11 @@ pub mod ffi;
 use czmq::{ZMsg, ZPoller, ZSock, SocketType, ZSys};
 use error::{Error, Result};
 use host::{Host,HostSendRecv};
+use host::data;
 use project::Language;
 use read_conf;
 use self::config::Config;
-use serde_json;
+use serde_json::{self, Value};
 use std::env::{current_dir, set_current_dir};
 use std::process;
 use std::path::PathBuf;
@@ -39,7 +40,7 @@ use std::thread;
 ///
 /// ```no_run
 /// # use inapi::{Host, Payload};
-#[cfg_attr(feature = "local-run", doc = "# let mut host = Host::local(Some(\"hosts/myhost.json\")).unwrap();")]
+#[cfg_attr(not(feature = "remote-run"), doc = "# let mut host = Host::local(Some(\"hosts/myhost.json\")).unwrap();")]
 #[cfg_attr(feature = "remote-run", doc = "# let mut host = Host::connect(\"hosts/myhost.json\").unwrap();")]
 ///let payload = Payload::new("nginx::install").unwrap(); // format is "payload::executable"
 ///payload.run(&mut host, None).unwrap();
@@ -51,6 +52,8 @@ pub struct Payload {
     artifact: Option<String>,
     /// Language the payload is written in.
     language: Language,
+    /// JSON Schema for host data.
+    schema: Option<Value>,
 }
 
 impl Payload {
@@ -92,6 +95,11 @@ impl Payload {
             try!(Self::check_deps(&deps));
         }
 
+        let schema = match config.schema {
+            Some(Value::String(path)) => Some(try!(read_conf(buf.join(path)))),
+            s => s,
+        };
+
         Ok(Payload {
             path: buf,
             artifact: if parts.len() > 0 {
@@ -100,6 +108,7 @@ impl Payload {
                 None
             },
             language: config.language,
+            schema: schema,
         })
     }
 
@@ -145,6 +154,40 @@ impl Payload {
         Ok(())
     }
 
+    /// Check the host's data against the payload's schema, if it has
+    /// one. This function is also called by `run()`.
+    ///
+    /// The schema is declared by the "schema" key in `payload.json`,
+    /// either inline or as the path of a schema file relative to the
+    /// payload. The error lists every value that doesn't match,
+    /// along with the data file it came from.
+    pub fn validate(&self, host: &Host) -> Result<()> {
+        let schema = match self.schema {
+            Some(ref s) => s,
+            None => return Ok(()),
+        };
+
+        let violations = try!(data::validate(schema, host.data()));
+        if violations.is_empty() {
+            return Ok(());
+        }
+
+        // Origins are only a hint, so the violations are still
+        // reported if the data files can't be traced.
+        let trace = data::trace(host).unwrap_or(None);
+
+        let mut msg = format!("Host data does not match the schema for payload {}", self.path.display());
+        for violation in violations {
+            msg.push_str(&format!("\n  {}", violation));
+
+            if let Some(origin) = trace.as_ref().and_then(|t| t.get(&violation.pointer)) {
+                msg.push_str(&format!(" (in {})", origin.file));
+            }
+        }
+
+        Err(Error::Generic(msg))
+    }
+
     /// Build and execute the payload's artifact.
     ///
     /// For compiled languages, the artifact will be executed
@@ -153,7 +196,7 @@ impl Payload {
     ///
     /// ```no_run
     /// # use inapi::{Host, Payload};
-    #[cfg_attr(feature = "local-run", doc = "# let mut host = Host::local(Some(\"hosts/myhost.json\")).unwrap();")]
+    #[cfg_attr(not(feature = "remote-run"), doc = "# let mut host = Host::local(Some(\"hosts/myhost.json\")).unwrap();")]
     #[cfg_attr(feature = "remote-run", doc = "# let mut host = Host::connect(\"hosts/myhost.json\").unwrap();")]
     /// let payload = Payload::new("iptables::configure").unwrap();
     /// payload.run(&mut host, Some(vec![
@@ -168,6 +211,8 @@ impl Payload {
         // clone half the world. When these are implemented, this
         // code should be changed.
 
+        try!(self.validate(host));
+
         // Build payload to make sure it's up to date
         try!(self.build());
 
@@ -366,6 +411,7 @@ mod tests {
             repository: "https://github.com/dhibbz/hehehe.git".into(),
             language: Language::Rust,
             dependencies: Some(vec!["missing_payload".into()]),
+            schema: None,
         };
 
         buf.push("payload.json");
@@ -375,6 +421,38 @@ mod tests {
         assert!(Payload::new(buf.to_str().unwrap()).is_err());
     }
 
+    #[test]
+    fn test_validate() {
+        let _ = ::_MOCK_ENV.init();
+
+        let tempdir = TempDir::new("test_payload_validate").unwrap();
+        let mut buf = tempdir.path().to_owned();
+
+        buf.push("schema.json");
+        write_conf(json!({ "required": [ "port" ] }), &buf).unwrap();
+        buf.pop();
+
+        let conf = Config {
+            author: "Dr. Hibbert".into(),
+            repository: "https://github.com/dhibbz/hehehe.git".into(),
+            language: Language::Php,
+            dependencies: None,
+            schema: Some(json!("schema.json")),
+        };
+
+        buf.push("payload.json");
+        write_conf(&conf, &buf).unwrap();
+        buf.pop();
+
+        let payload = Payload::new(buf.to_str().unwrap()).unwrap();
+
+        let host = Host::test_new(None, None, None, Some(json!({ "port": 80 })));
+        assert!(payload.validate(&host).is_ok());
+
+        let host = Host::test_new(None, None, None, Some(json!({ "prot": 80 })));
+        assert!(payload.validate(&host).is_err());
+    }
+
     pub fn test_build_c() {
         let tempdir = TempDir::new("test_payload_build_c").unwrap();
         let mut buf = tempdir.path().to_owned();
@@ -390,6 +468,7 @@ mod tests {
             repository: "https://github.com/dhibbz/hehehe.git".into(),
             language: Language::C,
             dependencies: None,
+            schema: None,
         };
 
         buf.push("payload.json");
@@ -414,6 +493,7 @@ mod tests {
             repository: "https://github.com/dhibbz/hehehe.git".into(),
             language: Language::Rust,
             dependencies: None,
+            schema: None,
         };
 
         buf.push("payload.json");
@@ -21,4 +21,8 @@ pub struct ProjectConfig {
     pub auth_server: String,
     pub auth_api_port: u32,
     pub auth_update_port: u32,
+    /// Data files that every host inherits, most important first,
+    /// e.g. "env/${/environment}.json"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hierarchy: Option<Vec<String>>,
 }
@@ -48,8 +48,8 @@ impl<'a> From<ServiceRunnable<'a>> for ServiceRunnableOwned {
 ///
 /// ```no_run
 /// # use inapi::Host;
-#[cfg_attr(feature = "local-run", doc = "let path: Option<String> = None;")]
-#[cfg_attr(feature = "local-run", doc = "let mut host = Host::local(path).unwrap();")]
+#[cfg_attr(not(feature = "remote-run"), doc = "let path: Option<String> = None;")]
+#[cfg_attr(not(feature = "remote-run"), doc = "let mut host = Host::local(path).unwrap();")]
 #[cfg_attr(feature = "remote-run", doc = "let mut host = Host::connect(\"hosts/myhost.json\").unwrap();")]
 /// ```
 ///
@@ -57,8 +57,8 @@ impl<'a> From<ServiceRunnable<'a>> for ServiceRunnableOwned {
 ///
 /// ```no_run
 /// # use inapi::{Host, Service, ServiceRunnable};
-#[cfg_attr(feature = "local-run", doc = "# let path: Option<String> = None;")]
-#[cfg_attr(feature = "local-run", doc = "# let mut host = Host::local(path).unwrap();")]
+#[cfg_attr(not(feature = "remote-run"), doc = "# let path: Option<String> = None;")]
+#[cfg_attr(not(feature = "remote-run"), doc = "# let mut host = Host::local(path).unwrap();")]
 #[cfg_attr(feature = "remote-run", doc = "# let mut host = Host::connect(\"hosts/myhost.json\").unwrap();")]
 ///let service = Service::new_service(ServiceRunnable::Service("nginx"), None);
 /// ```
@@ -68,8 +68,8 @@ impl<'a> From<ServiceRunnable<'a>> for ServiceRunnableOwned {
 ///
 /// ```no_run
 /// # use inapi::{Host, Service, ServiceRunnable};
-#[cfg_attr(feature = "local-run", doc = "# let path: Option<String> = None;")]
-#[cfg_attr(feature = "local-run", doc = "# let mut host = Host::local(path).unwrap();")]
+#[cfg_attr(not(feature = "remote-run"), doc = "# let path: Option<String> = None;")]
+#[cfg_attr(not(feature = "remote-run"), doc = "# let mut host = Host::local(path).unwrap();")]
 #[cfg_attr(feature = "remote-run", doc = "# let mut host = Host::connect(\"hosts/myhost.json\").unwrap();")]
 ///let service = Service::new_service(ServiceRunnable::Command("/usr/bin/apachectl"), None);
 /// ```
@@ -80,8 +80,8 @@ impl<'a> From<ServiceRunnable<'a>> for ServiceRunnableOwned {
 ///
 /// ```no_run
 /// # use inapi::{Host, Service, ServiceRunnable};
-#[cfg_attr(feature = "local-run", doc = "# let path: Option<String> = None;")]
-#[cfg_attr(feature = "local-run", doc = "# let mut host = Host::local(path).unwrap();")]
+#[cfg_attr(not(feature = "remote-run"), doc = "# let path: Option<String> = None;")]
+#[cfg_attr(not(feature = "remote-run"), doc = "# let mut host = Host::local(path).unwrap();")]
 #[cfg_attr(feature = "remote-run", doc = "# let mut host = Host::connect(\"hosts/myhost.json\").unwrap();")]
 /// # let service = Service::new_service(ServiceRunnable::Service(""), None);
 ///let result = service.action(&mut host, "start").unwrap();
@@ -116,8 +116,8 @@ impl<'a> From<ServiceRunnable<'a>> for ServiceRunnableOwned {
 /// ```no_run
 /// # use inapi::{Host, Service, ServiceRunnable};
 /// # use std::collections::HashMap;
-#[cfg_attr(feature = "local-run", doc = "# let path: Option<String> = None;")]
-#[cfg_attr(feature = "local-run", doc = "# let mut host = Host::local(path).unwrap();")]
+#[cfg_attr(not(feature = "remote-run"), doc = "# let path: Option<String> = None;")]
+#[cfg_attr(not(feature = "remote-run"), doc = "# let mut host = Host::local(path).unwrap();")]
 #[cfg_attr(feature = "remote-run", doc = "# let mut host = Host::connect(\"hosts/myhost.json\").unwrap();")]
 /// let mut map = HashMap::new();
 /// map.insert("start", ServiceRunnable::Command("/usr/local/bin/svc_start"));
@@ -133,8 +133,8 @@ impl<'a> From<ServiceRunnable<'a>> for ServiceRunnableOwned {
 /// ```no_run
 /// # use inapi::{Host, Service, ServiceRunnable};
 /// # use std::collections::HashMap;
-#[cfg_attr(feature = "local-run", doc = "# let path: Option<String> = None;")]
-#[cfg_attr(feature = "local-run", doc = "# let mut host = Host::local(path).unwrap();")]
+#[cfg_attr(not(feature = "remote-run"), doc = "# let path: Option<String> = None;")]
+#[cfg_attr(not(feature = "remote-run"), doc = "# let mut host = Host::local(path).unwrap();")]
 #[cfg_attr(feature = "remote-run", doc = "# let mut host = Host::connect(\"hosts/myhost.json\").unwrap();")]
 ///let mut map = HashMap::new();
 ///map.insert("_", ServiceRunnable::Service("nginx")); // <-- "_" is the default key
@@ -149,8 +149,8 @@ impl<'a> From<ServiceRunnable<'a>> for ServiceRunnableOwned {
 /// ```no_run
 /// # use inapi::{Host, Service, ServiceRunnable};
 /// # use std::collections::HashMap;
-#[cfg_attr(feature = "local-run", doc = "# let path: Option<String> = None;")]
-#[cfg_attr(feature = "local-run", doc = "# let mut host = Host::local(path).unwrap();")]
+#[cfg_attr(not(feature = "remote-run"), doc = "# let path: Option<String> = None;")]
+#[cfg_attr(not(feature = "remote-run"), doc = "# let mut host = Host::local(path).unwrap();")]
 #[cfg_attr(feature = "remote-run", doc = "# let mut host = Host::connect(\"hosts/myhost.json\").unwrap();")]
 ///let mut map = HashMap::new();
 ///map.insert("start", ServiceRunnable::Command("/usr/bin/start_svc"));
@@ -171,8 +171,8 @@ impl<'a> From<ServiceRunnable<'a>> for ServiceRunnableOwned {
 /// ```no_run
 /// # use inapi::{Host, Service, ServiceRunnable};
 /// # use std::collections::HashMap;
-#[cfg_attr(feature = "local-run", doc = "# let path: Option<String> = None;")]
-#[cfg_attr(feature = "local-run", doc = "# let mut host = Host::local(path).unwrap();")]
+#[cfg_attr(not(feature = "remote-run"), doc = "# let path: Option<String> = None;")]
+#[cfg_attr(not(feature = "remote-run"), doc = "# let mut host = Host::local(path).unwrap();")]
 #[cfg_attr(feature = "remote-run", doc = "# let mut host = Host::connect(\"hosts/myhost.json\").unwrap();")]
 ///let mut map = HashMap::new();
 ///map.insert("start", "-c /etc/my_svc.conf");
@@ -188,8 +188,8 @@ impl<'a> From<ServiceRunnable<'a>> for ServiceRunnableOwned {
 /// # fn main() {
 /// # use inapi::{Host, Service, ServiceRunnable};
 /// # use std::collections::HashMap;
-#[cfg_attr(feature = "local-run", doc = "# let path: Option<String> = None;")]
-#[cfg_attr(feature = "local-run", doc = "# let mut host = Host::local(path).unwrap();")]
+#[cfg_attr(not(feature = "remote-run"), doc = "# let path: Option<String> = None;")]
+#[cfg_attr(not(feature = "remote-run"), doc = "# let mut host = Host::local(path).unwrap();")]
 #[cfg_attr(feature = "remote-run", doc = "# let mut host = Host::connect(\"hosts/myhost.json\").unwrap();")]
 ///let mut map = HashMap::new();
 ///if needstr!(host.data_owned() => "/_telemetry/os/platform").unwrap() == "centos" {
@@ -261,8 +261,8 @@ impl Service {
     ///
     /// ```no_run
     /// # use inapi::{Host, Service, ServiceRunnable};
-    #[cfg_attr(feature = "local-run", doc = "# let path: Option<String> = None;")]
-    #[cfg_attr(feature = "local-run", doc = "# let mut host = Host::local(path).unwrap();")]
+    #[cfg_attr(not(feature = "remote-run"), doc = "# let path: Option<String> = None;")]
+    #[cfg_attr(not(feature = "remote-run"), doc = "# let mut host = Host::local(path).unwrap();")]
     #[cfg_attr(feature = "remote-run", doc = "# let mut host = Host::connect(\"hosts/myhost.json\").unwrap();")]
     /// let service = Service::new_service(ServiceRunnable::Command("/usr/bin/nginx"), None);
     /// service.action(&mut host, "start").unwrap();
@@ -290,9 +290,9 @@ impl Service {
         match *runnable {
             ServiceRunnableOwned::Service(ref name) => Target::service_action(host, name, action),
             ServiceRunnableOwned::Command(ref cmd) => if default {
-                Ok(Some(try!(Target::exec(host, &format!("{} {}", cmd, action)))))
+                Ok(Some(try!(Target::exec(host, &format!("{} {}", cmd, action), None))))
             } else {
-                Ok(Some(try!(Target::exec(host, cmd))))
+                Ok(Some(try!(Target::exec(host, cmd, None))))
             },
         }
     }
@@ -316,7 +316,6 @@ mod tests {
     use std::thread;
 
     // XXX This requires mocking the shell or Command struct
-    // #[cfg(feature = "local-run")]
     // #[test]
     // fn test_action() {
     // }
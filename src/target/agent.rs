@@ -0,0 +1,339 @@
+// Copyright 2015-2017 Intecture Developers. See the COPYRIGHT file at the
+// top-level directory of this distribution and at
+// https://intecture.io/COPYRIGHT.
+//
+// Licensed under the Mozilla Public License 2.0 <LICENSE or
+// https://www.tldrlegal.com/l/mpl-2.0>. This file may not be copied,
+// modified, or distributed except according to those terms.
+
+use command::{CommandResult, CommandStream, CommandTarget};
+use czmq::ZMsg;
+use directory::DirectoryTarget;
+use error::{Error, Result};
+use file::{FileTarget, FileOwner};
+use host::{Host, HostSendRecv, TelemetryTarget};
+use package::PackageTarget;
+use package::providers::Providers;
+use serde_json::Value;
+use service::ServiceTarget;
+use std::path::Path;
+use super::reply::{self, StreamReply};
+
+/// Target for hosts that run the Intecture Agent.
+pub struct AgentTarget;
+
+//
+// Command
+//
+
+impl CommandTarget for AgentTarget {
+    fn exec(host: &mut Host, cmd: &str, timeout: Option<u64>) -> Result<CommandResult> {
+        let msg = ZMsg::new();
+        try!(msg.addstr("command::exec"));
+        try!(msg.addstr(cmd));
+        // The Agent is responsible for killing the command's process
+        // group once the timeout expires.
+        if let Some(t) = timeout {
+            try!(msg.addstr(&t.to_string()));
+        }
+        try!(host.send(msg));
+
+        // Agents that report termination signals send a fourth frame
+        let msg = match timeout {
+            Some(t) => try!(host.recv_timeout(3, Some(4), t)),
+            None => try!(host.recv(3, Some(4))),
+        };
+
+        reply::decode(&msg, "command::exec")
+    }
+
+    fn exec_stream(host: &mut Host, cmd: &str, timeout: Option<u64>, handler: &mut FnMut(CommandStream, &[u8])) -> Result<CommandResult> {
+        try!(require(host, "command::exec_stream"));
+
+        let msg = ZMsg::new();
+        try!(msg.addstr("command::exec_stream"));
+        try!(msg.addstr(cmd));
+        if let Some(t) = timeout {
+            try!(msg.addstr(&t.to_string()));
+        }
+        try!(host.send_stream(msg));
+
+        // The Agent replies with any number of "Stream" messages
+        // containing output, followed by a single "Ok" or "Err"
+        // message when the process exits.
+        loop {
+            let reply = try!(host.recv_stream(timeout));
+
+            match reply::decode(&reply, "command::exec_stream") {
+                Ok(StreamReply::Output(stream, chunk)) => handler(stream, &chunk),
+                Ok(StreamReply::Exit(exit_code, signal)) => {
+                    return Ok(CommandResult::from_raw(Some(exit_code), signal, Vec::new(), Vec::new()));
+                },
+                Ok(StreamReply::Err(e)) => return Err(Error::Agent(e)),
+                Err(e) => {
+                    host.close_stream();
+                    return Err(e);
+                },
+            }
+        }
+    }
+}
+
+/// Check that the Agent supports a message type before sending it.
+fn require(host: &Host, capability: &str) -> Result<()> {
+    if host.supports(capability) {
+        Ok(())
+    } else {
+        Err(Error::Protocol(format!("The Agent on {} does not support `{}`. Upgrade the Agent to use this feature.", host.hostname, capability)))
+    }
+}
+
+//
+// Directory
+//
+
+impl <P: AsRef<Path>> DirectoryTarget<P> for AgentTarget {
+    fn directory_is_directory(host: &mut Host, path: P) -> Result<bool> {
+        let msg = ZMsg::new();
+        try!(msg.addstr("directory::is_directory"));
+        try!(msg.addstr(path.as_ref().to_str().unwrap()));
+        try!(host.send(msg));
+
+        let reply = try!(host.recv(1, Some(1)));
+        reply::decode(&reply, "directory::is_directory")
+    }
+
+    fn directory_exists(host: &mut Host, path: P) -> Result<bool> {
+        let msg = ZMsg::new();
+        try!(msg.addstr("directory::exists"));
+        try!(msg.addstr(path.as_ref().to_str().unwrap()));
+        try!(host.send(msg));
+
+        let reply = try!(host.recv(1, Some(1)));
+        reply::decode(&reply, "directory::exists")
+    }
+
+    fn directory_create(host: &mut Host, path: P, recursive: bool) -> Result<()> {
+        let msg = ZMsg::new();
+        try!(msg.addstr("directory::create"));
+        try!(msg.addstr(path.as_ref().to_str().unwrap()));
+        try!(msg.addstr(if recursive { "1" } else { "0" }));
+        try!(host.send(msg));
+
+        let reply = try!(host.recv(0, Some(0)));
+        reply::decode(&reply, "directory::create")
+    }
+
+    fn directory_delete(host: &mut Host, path: P, recursive: bool) -> Result<()> {
+        let msg = ZMsg::new();
+        try!(msg.addstr("directory::delete"));
+        try!(msg.addstr(path.as_ref().to_str().unwrap()));
+        try!(msg.addstr(if recursive { "1" } else { "0" }));
+        try!(host.send(msg));
+
+        let reply = try!(host.recv(0, Some(0)));
+        reply::decode(&reply, "directory::delete")
+    }
+
+    fn directory_mv(host: &mut Host, path: P, new_path: P) -> Result<()> {
+        let msg = ZMsg::new();
+        try!(msg.addstr("directory::mv"));
+        try!(msg.addstr(path.as_ref().to_str().unwrap()));
+        try!(msg.addstr(new_path.as_ref().to_str().unwrap()));
+        try!(host.send(msg));
+
+        let reply = try!(host.recv(0, Some(0)));
+        reply::decode(&reply, "directory::mv")
+    }
+
+    fn directory_get_owner(host: &mut Host, path: P) -> Result<FileOwner> {
+        let msg = ZMsg::new();
+        try!(msg.addstr("directory::get_owner"));
+        try!(msg.addstr(path.as_ref().to_str().unwrap()));
+        try!(host.send(msg));
+
+        let reply = try!(host.recv(4, Some(4)));
+        reply::decode(&reply, "directory::get_owner")
+    }
+
+    fn directory_set_owner(host: &mut Host, path: P, user: &str, group: &str) -> Result<()> {
+        let msg = ZMsg::new();
+        try!(msg.addstr("directory::set_owner"));
+        try!(msg.addstr(path.as_ref().to_str().unwrap()));
+        try!(msg.addstr(user));
+        try!(msg.addstr(group));
+        try!(host.send(msg));
+
+        let reply = try!(host.recv(0, Some(0)));
+        reply::decode(&reply, "directory::set_owner")
+    }
+
+    fn directory_get_mode(host: &mut Host, path: P) -> Result<u16> {
+        let msg = ZMsg::new();
+        try!(msg.addstr("directory::get_mode"));
+        try!(msg.addstr(path.as_ref().to_str().unwrap()));
+        try!(host.send(msg));
+
+        let reply = try!(host.recv(1, Some(1)));
+        reply::decode(&reply, "directory::get_mode")
+    }
+
+    fn directory_set_mode(host: &mut Host, path: P, mode: u16) -> Result<()> {
+        let msg = ZMsg::new();
+        try!(msg.addstr("directory::set_mode"));
+        try!(msg.addstr(path.as_ref().to_str().unwrap()));
+        try!(msg.addstr(&mode.to_string()));
+        try!(host.send(msg));
+
+        let reply = try!(host.recv(0, Some(0)));
+        reply::decode(&reply, "directory::set_mode")
+    }
+}
+
+//
+// File
+//
+
+impl <P: AsRef<Path>> FileTarget<P> for AgentTarget {
+    fn file_is_file(host: &mut Host, path: P) -> Result<bool> {
+        let msg = ZMsg::new();
+        try!(msg.addstr("file::is_file"));
+        try!(msg.addstr(path.as_ref().to_str().unwrap()));
+        try!(host.send(msg));
+
+        let reply = try!(host.recv(1, Some(1)));
+        reply::decode(&reply, "file::is_file")
+    }
+
+    fn file_exists(host: &mut Host, path: P) -> Result<bool> {
+        let msg = ZMsg::new();
+        try!(msg.addstr("file::exists"));
+        try!(msg.addstr(path.as_ref().to_str().unwrap()));
+        try!(host.send(msg));
+
+        let reply = try!(host.recv(1, Some(1)));
+        reply::decode(&reply, "file::exists")
+    }
+
+    fn file_delete(host: &mut Host, path: P) -> Result<()> {
+        let msg = ZMsg::new();
+        try!(msg.addstr("file::delete"));
+        try!(msg.addstr(path.as_ref().to_str().unwrap()));
+        try!(host.send(msg));
+
+        let reply = try!(host.recv(0, Some(0)));
+        reply::decode(&reply, "file::delete")
+    }
+
+    fn file_mv(host: &mut Host, path: P, new_path: P) -> Result<()> {
+        let msg = ZMsg::new();
+        try!(msg.addstr("file::mv"));
+        try!(msg.addstr(path.as_ref().to_str().unwrap()));
+        try!(msg.addstr(new_path.as_ref().to_str().unwrap()));
+        try!(host.send(msg));
+
+        let reply = try!(host.recv(0, Some(0)));
+        reply::decode(&reply, "file::mv")
+    }
+
+    fn file_copy(host: &mut Host, path: P, new_path: P) -> Result<()> {
+        let msg = ZMsg::new();
+        try!(msg.addstr("file::copy"));
+        try!(msg.addstr(path.as_ref().to_str().unwrap()));
+        try!(msg.addstr(new_path.as_ref().to_str().unwrap()));
+        try!(host.send(msg));
+
+        let reply = try!(host.recv(0, Some(0)));
+        reply::decode(&reply, "file::copy")
+    }
+
+    fn file_get_owner(host: &mut Host, path: P) -> Result<FileOwner> {
+        let msg = ZMsg::new();
+        try!(msg.addstr("file::get_owner"));
+        try!(msg.addstr(path.as_ref().to_str().unwrap()));
+        try!(host.send(msg));
+
+        let reply = try!(host.recv(4, Some(4)));
+        reply::decode(&reply, "file::get_owner")
+    }
+
+    fn file_set_owner(host: &mut Host, path: P, user: &str, group: &str) -> Result<()> {
+        let msg = ZMsg::new();
+        try!(msg.addstr("file::set_owner"));
+        try!(msg.addstr(path.as_ref().to_str().unwrap()));
+        try!(msg.addstr(user));
+        try!(msg.addstr(group));
+        try!(host.send(msg));
+
+        let reply = try!(host.recv(0, Some(0)));
+        reply::decode(&reply, "file::set_owner")
+    }
+
+    fn file_get_mode(host: &mut Host, path: P) -> Result<u16> {
+        let msg = ZMsg::new();
+        try!(msg.addstr("file::get_mode"));
+        try!(msg.addstr(path.as_ref().to_str().unwrap()));
+        try!(host.send(msg));
+
+        let reply = try!(host.recv(1, Some(1)));
+        reply::decode(&reply, "file::get_mode")
+    }
+
+    fn file_set_mode(host: &mut Host, path: P, mode: u16) -> Result<()> {
+        let msg = ZMsg::new();
+        try!(msg.addstr("file::set_mode"));
+        try!(msg.addstr(path.as_ref().to_str().unwrap()));
+        try!(msg.addstr(&mode.to_string()));
+        try!(host.send(msg));
+
+        let reply = try!(host.recv(0, Some(0)));
+        reply::decode(&reply, "file::set_mode")
+    }
+}
+
+//
+// Package
+//
+
+impl PackageTarget for AgentTarget {
+    fn default_provider(host: &mut Host) -> Result<Providers> {
+        let msg = ZMsg::new();
+        try!(msg.addstr("package::default_provider"));
+        try!(host.send(msg));
+
+        let reply = try!(host.recv(1, Some(1)));
+        reply::decode(&reply, "package::default_provider")
+    }
+}
+
+//
+// Service
+//
+
+impl ServiceTarget for AgentTarget {
+    fn service_action(host: &mut Host, name: &str, action: &str) -> Result<Option<CommandResult>> {
+        let msg = ZMsg::new();
+        try!(msg.addstr("service::action"));
+        try!(msg.addstr(name));
+        try!(msg.addstr(action));
+        try!(host.send(msg));
+
+        let reply = try!(host.recv(0, Some(4)));
+        reply::decode(&reply, "service::action")
+    }
+}
+
+//
+// Telemetry
+//
+
+impl TelemetryTarget for AgentTarget {
+    fn telemetry_init(host: &mut Host) -> Result<Value> {
+        let msg = ZMsg::new();
+        try!(msg.addstr("telemetry"));
+        try!(host.send(msg));
+
+        let reply = try!(host.recv(1, Some(1)));
+        reply::decode(&reply, "telemetry")
+    }
+}
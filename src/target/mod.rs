@@ -0,0 +1,76 @@
+// Copyright 2015-2017 Intecture Developers. See the COPYRIGHT file at the
+// top-level directory of this distribution and at
+// https://intecture.io/COPYRIGHT.
+//
+// Licensed under the Mozilla Public License 2.0 <LICENSE or
+// https://www.tldrlegal.com/l/mpl-2.0>. This file may not be copied,
+// modified, or distributed except according to those terms.
+
+#[cfg(target_os = "linux")]
+#[allow(dead_code)]
+pub mod debian_base;
+
+#[allow(dead_code)]
+pub mod default_base;
+
+#[cfg(target_os = "linux")]
+#[allow(dead_code)]
+pub mod linux_base;
+
+#[cfg(target_os = "linux")]
+#[allow(dead_code)]
+pub mod redhat_base;
+
+#[cfg(any(target_os = "freebsd", target_os = "macos"))]
+#[allow(dead_code)]
+pub mod unix_base;
+
+#[cfg(target_os = "linux")]
+pub mod debian;
+
+#[cfg(target_os = "linux")]
+pub mod centos;
+
+#[cfg(target_os = "linux")]
+pub mod fedora;
+
+#[cfg(target_os = "freebsd")]
+pub mod freebsd;
+
+#[cfg(target_os = "linux")]
+#[allow(dead_code)]
+pub mod linux;
+
+#[cfg(target_os = "macos")]
+pub mod macos;
+
+#[cfg(target_os = "linux")]
+pub mod redhat;
+
+#[cfg(target_os = "linux")]
+pub mod ubuntu;
+
+#[cfg(target_os = "linux")]
+pub mod nixos;
+
+#[allow(dead_code)]
+pub mod process;
+
+#[cfg(feature = "remote-run")]
+pub mod agent;
+
+#[cfg(feature = "remote-run")]
+pub mod reply;
+
+pub mod ssh;
+
+pub mod transport;
+
+/// Target for the host's current transport. Each operation is
+/// dispatched to the target that implements it for that transport.
+pub struct Target;
+
+/// Target for the local machine. Each operation is dispatched to the
+/// implementation for the local OS.
+#[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "macos"))]
+pub struct LocalTarget;
@@ -0,0 +1,157 @@
+// Copyright 2015-2017 Intecture Developers. See the COPYRIGHT file at the
+// top-level directory of this distribution and at
+// https://intecture.io/COPYRIGHT.
+//
+// Licensed under the Mozilla Public License 2.0 <LICENSE or
+// https://www.tldrlegal.com/l/mpl-2.0>. This file may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Helpers for running processes on the local machine, shared by
+//! the local targets and transports that shell out to a client
+//! (e.g. SSH).
+
+use command::{CommandResult, CommandStream};
+use error::{Error, Result};
+use libc;
+use std::{process, thread};
+use std::io::Read;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+/// Run a process to completion, buffering its output.
+pub fn exec(command: process::Command, cmd: &str, timeout: Option<u64>) -> Result<CommandResult> {
+    let mut stdout = Vec::new();
+    let mut stderr = Vec::new();
+
+    let result = try!(exec_stream(command, cmd, timeout, &mut |stream, chunk| match stream {
+        CommandStream::Stdout => stdout.extend_from_slice(chunk),
+        CommandStream::Stderr => stderr.extend_from_slice(chunk),
+    }));
+
+    Ok(CommandResult::from_raw(Some(result.exit_code), result.signal, stdout, stderr))
+}
+
+/// Run a process to completion, passing its output to `handler` as
+/// it is produced. `cmd` is only used to describe the process in
+/// errors.
+pub fn exec_stream(mut command: process::Command, cmd: &str, timeout: Option<u64>, handler: &mut FnMut(CommandStream, &[u8])) -> Result<CommandResult> {
+    let mut child = try!(command.stdout(process::Stdio::piped())
+                                .stderr(process::Stdio::piped())
+                                // Run the process in its own process group so
+                                // that a timeout can kill any children it has
+                                // forked.
+                                .before_exec(|| {
+                                    unsafe { libc::setpgid(0, 0); }
+                                    Ok(())
+                                })
+                                .spawn());
+
+    // Drain the pipes on separate threads, otherwise a chatty
+    // process can block on a full pipe buffer and never exit.
+    let (tx, rx) = mpsc::channel();
+    let stdout = read_pipe(child.stdout.take(), CommandStream::Stdout, tx.clone());
+    let stderr = read_pipe(child.stderr.take(), CommandStream::Stderr, tx);
+
+    let deadline = timeout.map(|t| Instant::now() + Duration::from_secs(t));
+
+    loop {
+        let chunk = match deadline {
+            Some(d) => {
+                let now = Instant::now();
+                if now >= d {
+                    return Err(kill_timeout(&mut child, cmd, timeout.unwrap()));
+                }
+
+                match rx.recv_timeout(d - now) {
+                    Ok(chunk) => chunk,
+                    Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                    Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                }
+            },
+            None => match rx.recv() {
+                Ok(chunk) => chunk,
+                Err(_) => break,
+            },
+        };
+
+        handler(chunk.0, &chunk.1);
+    }
+
+    try!(try!(stdout.join()));
+    try!(try!(stderr.join()));
+
+    // The process may outlive its pipes (e.g. if it has closed
+    // them), so keep enforcing the deadline until it exits.
+    let status = match deadline {
+        Some(d) => loop {
+            if let Some(status) = try!(child.try_wait()) {
+                break status;
+            }
+
+            if Instant::now() >= d {
+                return Err(kill_timeout(&mut child, cmd, timeout.unwrap()));
+            }
+
+            thread::sleep(Duration::from_millis(50));
+        },
+        None => try!(child.wait()),
+    };
+
+    Ok(CommandResult::from_raw(status.code(), status.signal(), Vec::new(), Vec::new()))
+}
+
+fn read_pipe<R: Read + Send + 'static>(pipe: Option<R>, stream: CommandStream, tx: mpsc::Sender<(CommandStream, Vec<u8>)>) -> thread::JoinHandle<Result<()>> {
+    thread::spawn(move || {
+        if let Some(mut p) = pipe {
+            let mut buf = [0; 4096];
+
+            loop {
+                let len = try!(p.read(&mut buf));
+                // Stop reading if the pipe has closed, or if the
+                // receiver has hung up after a timeout.
+                if len == 0 || tx.send((stream, buf[..len].to_vec())).is_err() {
+                    break;
+                }
+            }
+        }
+
+        Ok(())
+    })
+}
+
+fn kill_timeout(child: &mut process::Child, cmd: &str, timeout: u64) -> Error {
+    unsafe { libc::killpg(child.id() as libc::pid_t, libc::SIGKILL); }
+
+    match child.wait() {
+        Ok(_) => Error::Timeout(format!("Command `{}` did not complete within {} seconds", cmd, timeout)),
+        Err(e) => e.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use command::CommandStream;
+    use error::Error;
+    use std::process;
+    use super::*;
+
+    #[test]
+    fn test_exec() {
+        let mut command = process::Command::new("sh");
+        command.arg("-c").arg("printf 'moo\\n'; exit 2");
+        let result = exec(command, "moo", None).unwrap();
+        assert_eq!(result.exit_code, 2);
+        assert_eq!(result.stdout_raw, b"moo\n");
+    }
+
+    #[test]
+    fn test_exec_stream_timeout() {
+        let mut command = process::Command::new("sleep");
+        command.arg("5");
+        match exec_stream(command, "sleep 5", Some(1), &mut |_: CommandStream, _: &[u8]| ()) {
+            Err(Error::Timeout(_)) => (),
+            _ => panic!("Expected process to time out"),
+        }
+    }
+}
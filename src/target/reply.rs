@@ -0,0 +1,276 @@
+// Copyright 2015-2017 Intecture Developers. See the COPYRIGHT file at the
+// top-level directory of this distribution and at
+// https://intecture.io/COPYRIGHT.
+//
+// Licensed under the Mozilla Public License 2.0 <LICENSE or
+// https://www.tldrlegal.com/l/mpl-2.0>. This file may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Typed decoding of Agent replies.
+//!
+//! Every reply that the Agent sends is decoded into a `Reply` type,
+//! which validates each frame as it is read. A reply that is missing
+//! a frame is an `Error::Frame`, and a reply with a frame that can't
+//! be parsed, or with unexpected extra frames, is an
+//! `Error::HostResponse`.
+
+use command::{CommandResult, CommandStream};
+use czmq::ZMsg;
+use error::{Error, MissingFrame, Result};
+use file::FileOwner;
+use package::providers::Providers;
+use serde_json::{self, Value};
+use std::str::FromStr;
+
+/// A message that can be decoded from the frames of an Agent reply.
+pub trait Reply: Sized {
+    fn decode(frames: &mut Frames) -> Result<Self>;
+}
+
+/// Decode a reply to `endpoint`, once its header has been removed.
+pub fn decode<R: Reply>(msg: &ZMsg, endpoint: &str) -> Result<R> {
+    let mut frames = Frames::new(msg, endpoint);
+    let reply = try!(R::decode(&mut frames));
+    try!(frames.finish());
+    Ok(reply)
+}
+
+/// Reader for the frames of a reply.
+pub struct Frames<'a> {
+    msg: &'a ZMsg,
+    endpoint: &'a str,
+    order: u8,
+}
+
+impl <'a> Frames<'a> {
+    pub fn new(msg: &'a ZMsg, endpoint: &'a str) -> Frames<'a> {
+        Frames {
+            msg: msg,
+            endpoint: endpoint,
+            order: 0,
+        }
+    }
+
+    /// Number of frames that haven't been read yet.
+    pub fn remaining(&self) -> usize {
+        self.msg.size()
+    }
+
+    /// Read a frame without requiring it to be UTF-8.
+    pub fn bytes(&mut self) -> Result<Vec<u8>> {
+        let frame = match self.msg.popstr() {
+            Some(Ok(s)) => s.into_bytes(),
+            Some(Err(b)) => b,
+            None => return Err(MissingFrame::new(self.endpoint, self.order).into()),
+        };
+
+        self.order = self.order.saturating_add(1);
+        Ok(frame)
+    }
+
+    pub fn string(&mut self) -> Result<String> {
+        String::from_utf8(try!(self.bytes())).or(Err(Error::HostResponse))
+    }
+
+    /// Read a frame and parse it, e.g. as a number.
+    pub fn parse<T: FromStr>(&mut self) -> Result<T> {
+        try!(self.string()).parse().or(Err(Error::HostResponse))
+    }
+
+    /// Read a "1" or "0" frame.
+    pub fn flag(&mut self) -> Result<bool> {
+        match try!(self.string()).as_ref() {
+            "1" => Ok(true),
+            "0" => Ok(false),
+            _ => Err(Error::HostResponse),
+        }
+    }
+
+    /// Check that every frame has been read.
+    pub fn finish(self) -> Result<()> {
+        if self.msg.size() == 0 {
+            Ok(())
+        } else {
+            Err(Error::HostResponse)
+        }
+    }
+}
+
+impl Reply for () {
+    fn decode(_: &mut Frames) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl Reply for bool {
+    fn decode(frames: &mut Frames) -> Result<bool> {
+        frames.flag()
+    }
+}
+
+/// File or directory mode
+impl Reply for u16 {
+    fn decode(frames: &mut Frames) -> Result<u16> {
+        frames.parse()
+    }
+}
+
+impl Reply for FileOwner {
+    fn decode(frames: &mut Frames) -> Result<FileOwner> {
+        Ok(FileOwner {
+            user_name: try!(frames.string()),
+            user_uid: try!(frames.parse()),
+            group_name: try!(frames.string()),
+            group_gid: try!(frames.parse()),
+        })
+    }
+}
+
+/// `["<exit code>", "<stdout>", "<stderr>", ("<signal>")]`
+impl Reply for CommandResult {
+    fn decode(frames: &mut Frames) -> Result<CommandResult> {
+        let exit_code = try!(frames.parse());
+        let stdout = try!(frames.bytes());
+        let stderr = try!(frames.bytes());
+        let signal = try!(signal(frames));
+
+        // A signal-killed process has no exit code
+        let exit_code = if signal.is_some() { None } else { Some(exit_code) };
+
+        Ok(CommandResult::from_raw(exit_code, signal, stdout, stderr))
+    }
+}
+
+/// Services that are started or stopped without running a command
+/// have an empty reply.
+impl Reply for Option<CommandResult> {
+    fn decode(frames: &mut Frames) -> Result<Option<CommandResult>> {
+        if frames.remaining() == 0 {
+            Ok(None)
+        } else {
+            Ok(Some(try!(CommandResult::decode(frames))))
+        }
+    }
+}
+
+impl Reply for Providers {
+    fn decode(frames: &mut Frames) -> Result<Providers> {
+        frames.parse()
+    }
+}
+
+/// Telemetry, serialized as JSON
+impl Reply for Value {
+    fn decode(frames: &mut Frames) -> Result<Value> {
+        serde_json::from_str(&try!(frames.string())).or(Err(Error::HostResponse))
+    }
+}
+
+/// A single message from a command's output stream.
+pub enum StreamReply {
+    /// `["Stream", "stdout"|"stderr", "<chunk>"]`
+    Output(CommandStream, Vec<u8>),
+    /// `["Ok", "<exit code>", ("<signal>")]`
+    Exit(i32, Option<i32>),
+    /// `["Err", "<message>"]`
+    Err(String),
+}
+
+impl Reply for StreamReply {
+    fn decode(frames: &mut Frames) -> Result<StreamReply> {
+        match try!(frames.string()).as_ref() {
+            "Stream" => {
+                let stream = match try!(frames.string()).as_ref() {
+                    "stdout" => CommandStream::Stdout,
+                    "stderr" => CommandStream::Stderr,
+                    _ => return Err(Error::HostResponse),
+                };
+                Ok(StreamReply::Output(stream, try!(frames.bytes())))
+            },
+            "Ok" => {
+                let exit_code = try!(frames.parse());
+                Ok(StreamReply::Exit(exit_code, try!(signal(frames))))
+            },
+            "Err" => Ok(StreamReply::Err(try!(frames.string()))),
+            _ => Err(Error::HostResponse),
+        }
+    }
+}
+
+/// Read the optional signal frame. Agents that predate signal
+/// reporting don't send it, and "0" means no signal.
+fn signal(frames: &mut Frames) -> Result<Option<i32>> {
+    if frames.remaining() == 0 {
+        return Ok(None);
+    }
+
+    match try!(frames.parse()) {
+        0 => Ok(None),
+        s => Ok(Some(s)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use command::CommandResult;
+    use czmq::ZMsg;
+    use error::Error;
+    use file::FileOwner;
+    use super::*;
+
+    fn msg(frames: &[&str]) -> ZMsg {
+        let msg = ZMsg::new();
+        for frame in frames {
+            msg.addstr(frame).unwrap();
+        }
+        msg
+    }
+
+    #[test]
+    fn test_decode() {
+        let owner: FileOwner = decode(&msg(&["root", "0", "wheel", "0"]), "file::get_owner").unwrap();
+        assert_eq!(owner.user_name, "root");
+        assert_eq!(owner.group_gid, 0);
+
+        let result: CommandResult = decode(&msg(&["0", "moo", ""]), "command::exec").unwrap();
+        assert_eq!(result.exit_code, 0);
+        assert_eq!(result.stdout, "moo");
+
+        let result: Option<CommandResult> = decode(&msg(&[]), "service::action").unwrap();
+        assert!(result.is_none());
+
+        assert!(decode::<bool>(&msg(&["1"]), "file::exists").unwrap());
+        match decode::<Providers>(&msg(&["Apt"]), "package::default_provider") {
+            Ok(Providers::Apt) => (),
+            _ => panic!("Expected Apt provider"),
+        }
+    }
+
+    #[test]
+    fn test_decode_malformed() {
+        match decode::<FileOwner>(&msg(&["root", "0"]), "file::get_owner") {
+            Err(Error::Frame(_)) => (),
+            _ => panic!("Expected missing frame error"),
+        }
+
+        match decode::<u16>(&msg(&["rwx"]), "file::get_mode") {
+            Err(Error::HostResponse) => (),
+            _ => panic!("Expected invalid frame error"),
+        }
+
+        match decode::<bool>(&msg(&["1", "1"]), "file::exists") {
+            Err(Error::HostResponse) => (),
+            _ => panic!("Expected extra frame error"),
+        }
+
+        match decode::<CommandResult>(&msg(&["-", "", ""]), "command::exec") {
+            Err(Error::HostResponse) => (),
+            _ => panic!("Expected invalid exit code error"),
+        }
+
+        match decode::<Providers>(&msg(&["Chocolatey"]), "package::default_provider") {
+            Err(Error::HostResponse) => (),
+            _ => panic!("Expected invalid provider error"),
+        }
+    }
+}
@@ -0,0 +1,328 @@
+// Copyright 2015-2017 Intecture Developers. See the COPYRIGHT file at the
+// top-level directory of this distribution and at
+// https://intecture.io/COPYRIGHT.
+//
+// Licensed under the Mozilla Public License 2.0 <LICENSE or
+// https://www.tldrlegal.com/l/mpl-2.0>. This file may not be copied,
+// modified, or distributed except according to those terms.
+
+use command::{CommandResult, CommandStream, CommandTarget};
+use directory::DirectoryTarget;
+use error::{Error, Result};
+use file::{FileTarget, FileOwner};
+use host::{Host, TelemetryTarget};
+use host::ssh::{quote, SshSession};
+use package::PackageTarget;
+use package::providers::{ProviderFactory, Providers};
+use serde_json::Value;
+use service::ServiceTarget;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Target for hosts that are managed over SSH. Each operation is
+/// performed by running a POSIX shell command on the host.
+pub struct SshTarget;
+
+//
+// Command
+//
+
+impl CommandTarget for SshTarget {
+    fn exec(host: &mut Host, cmd: &str, timeout: Option<u64>) -> Result<CommandResult> {
+        try!(session(host)).exec(cmd, timeout)
+    }
+
+    fn exec_stream(host: &mut Host, cmd: &str, timeout: Option<u64>, handler: &mut FnMut(CommandStream, &[u8])) -> Result<CommandResult> {
+        try!(session(host)).exec_stream(cmd, timeout, handler)
+    }
+}
+
+//
+// Directory
+//
+
+impl<P: AsRef<Path>> DirectoryTarget<P> for SshTarget {
+    fn directory_is_directory(host: &mut Host, path: P) -> Result<bool> {
+        let path = try!(quote_path(path));
+        test(host, &format!("[ ! -e {0} ] || [ -d {0} ]", path))
+    }
+
+    fn directory_exists(host: &mut Host, path: P) -> Result<bool> {
+        test(host, &format!("[ -e {} ]", try!(quote_path(path))))
+    }
+
+    fn directory_create(host: &mut Host, path: P, recursive: bool) -> Result<()> {
+        run(host, &format!("mkdir {}{}", if recursive { "-p " } else { "" }, try!(quote_path(path))))
+    }
+
+    fn directory_delete(host: &mut Host, path: P, recursive: bool) -> Result<()> {
+        if recursive {
+            run(host, &format!("rm -r {}", try!(quote_path(path))))
+        } else {
+            run(host, &format!("rmdir {}", try!(quote_path(path))))
+        }
+    }
+
+    fn directory_mv(host: &mut Host, path: P, new_path: P) -> Result<()> {
+        run(host, &format!("mv {} {}", try!(quote_path(path)), try!(quote_path(new_path))))
+    }
+
+    fn directory_get_owner(host: &mut Host, path: P) -> Result<FileOwner> {
+        get_owner(host, path)
+    }
+
+    fn directory_set_owner(host: &mut Host, path: P, user: &str, group: &str) -> Result<()> {
+        set_owner(host, path, user, group)
+    }
+
+    fn directory_get_mode(host: &mut Host, path: P) -> Result<u16> {
+        get_mode(host, path)
+    }
+
+    fn directory_set_mode(host: &mut Host, path: P, mode: u16) -> Result<()> {
+        set_mode(host, path, mode)
+    }
+}
+
+//
+// File
+//
+
+impl<P: AsRef<Path>> FileTarget<P> for SshTarget {
+    fn file_is_file(host: &mut Host, path: P) -> Result<bool> {
+        let path = try!(quote_path(path));
+        test(host, &format!("[ ! -e {0} ] || [ -f {0} ]", path))
+    }
+
+    fn file_exists(host: &mut Host, path: P) -> Result<bool> {
+        test(host, &format!("[ -e {} ]", try!(quote_path(path))))
+    }
+
+    fn file_delete(host: &mut Host, path: P) -> Result<()> {
+        run(host, &format!("rm {}", try!(quote_path(path))))
+    }
+
+    fn file_mv(host: &mut Host, path: P, new_path: P) -> Result<()> {
+        run(host, &format!("mv {} {}", try!(quote_path(path)), try!(quote_path(new_path))))
+    }
+
+    fn file_copy(host: &mut Host, path: P, new_path: P) -> Result<()> {
+        run(host, &format!("cp {} {}", try!(quote_path(path)), try!(quote_path(new_path))))
+    }
+
+    fn file_get_owner(host: &mut Host, path: P) -> Result<FileOwner> {
+        get_owner(host, path)
+    }
+
+    fn file_set_owner(host: &mut Host, path: P, user: &str, group: &str) -> Result<()> {
+        set_owner(host, path, user, group)
+    }
+
+    fn file_get_mode(host: &mut Host, path: P) -> Result<u16> {
+        get_mode(host, path)
+    }
+
+    fn file_set_mode(host: &mut Host, path: P, mode: u16) -> Result<()> {
+        set_mode(host, path, mode)
+    }
+}
+
+//
+// Package
+//
+
+impl PackageTarget for SshTarget {
+    fn default_provider(host: &mut Host) -> Result<Providers> {
+        let providers = vec![
+            Providers::Apt,
+            Providers::Dnf,
+            Providers::Yum,
+            Providers::Pkg,
+            Providers::Ports,
+            Providers::Homebrew,
+            Providers::Macports,
+            Providers::Nix,
+        ];
+
+        for p in providers {
+            if let Ok(provider) = ProviderFactory::create(host, Some(p)) {
+                return Ok(provider.get_providers());
+            }
+        }
+
+        Err(Error::Generic("No package providers are available".to_string()))
+    }
+}
+
+//
+// Service
+//
+
+impl ServiceTarget for SshTarget {
+    fn service_action(host: &mut Host, name: &str, action: &str) -> Result<Option<CommandResult>> {
+        let name = quote(name);
+        let systemd = try!(test(host, "[ -d /run/systemd/system ]"));
+
+        // Skip actions that wouldn't change the service's state
+        let check = match (action, systemd) {
+            ("start", true) | ("stop", true) => Some(format!("systemctl is-active --quiet {}", name)),
+            ("enable", true) | ("disable", true) => Some(format!("systemctl is-enabled --quiet {}", name)),
+            ("start", false) | ("stop", false) => Some(format!("service {} status", name)),
+            _ => None,
+        };
+
+        if let Some(check) = check {
+            let on = try!(test(host, &check));
+            if (on && (action == "start" || action == "enable")) || (!on && (action == "stop" || action == "disable")) {
+                return Ok(None);
+            }
+        }
+
+        let cmd = if systemd {
+            format!("systemctl {} {}", quote(action), name)
+        } else {
+            format!("service {} {}", name, quote(action))
+        };
+
+        Ok(Some(try!(try!(session(host)).exec(&cmd, None))))
+    }
+}
+
+//
+// Telemetry
+//
+
+impl TelemetryTarget for SshTarget {
+    fn telemetry_init(host: &mut Host) -> Result<Value> {
+        // Only gather what we can portably get from a POSIX shell.
+        // Filesystem and network details require the Agent.
+        let script = "echo \"hostname=$(uname -n)\"; \
+                      echo \"arch=$(uname -m)\"; \
+                      echo \"cores=$(getconf _NPROCESSORS_ONLN 2>/dev/null || sysctl -n hw.ncpu 2>/dev/null)\"; \
+                      echo \"memory=$(awk '/^MemTotal:/ { print $2 * 1024 }' /proc/meminfo 2>/dev/null || sysctl -n hw.physmem 2>/dev/null)\"; \
+                      if [ -f /etc/os-release ]; then . /etc/os-release; echo \"platform=$ID\"; echo \"version=$VERSION_ID\"; \
+                      elif command -v sw_vers >/dev/null; then echo platform=macos; echo \"version=$(sw_vers -productVersion)\"; \
+                      else echo \"platform=$(uname -s | tr A-Z a-z)\"; echo \"version=$(uname -r | cut -d- -f1)\"; fi";
+
+        let result = try!(try!(session(host)).exec(script, None));
+        if result.exit_code != 0 {
+            return Err(Error::Generic(format!("Could not gather telemetry: {}", result.stderr)));
+        }
+
+        let facts: HashMap<&str, &str> = result.stdout.lines()
+                                                      .filter_map(|l| {
+                                                          let mut parts = l.splitn(2, '=');
+                                                          match (parts.next(), parts.next()) {
+                                                              (Some(k), Some(v)) => Some((k, v.trim())),
+                                                              _ => None,
+                                                          }
+                                                      })
+                                                      .collect();
+
+        let platform = match facts.get("platform").cloned().unwrap_or("") {
+            "rhel" => "redhat",
+            p => p,
+        };
+        let family = match platform {
+            "debian" | "ubuntu" => "debian",
+            "centos" | "fedora" | "redhat" => "redhat",
+            "freebsd" | "macos" => "unix",
+            _ => "linux",
+        };
+
+        let version_str = facts.get("version").cloned().unwrap_or("");
+        let mut version = version_str.split('.').map(|v| v.parse::<u32>().unwrap_or(0));
+
+        Ok(json!({
+            "_telemetry": {
+                "cpu": {
+                    "vendor": "",
+                    "brand_string": "",
+                    "cores": facts.get("cores").and_then(|c| c.parse::<u32>().ok()).unwrap_or(0),
+                },
+                "fs": [],
+                "hostname": facts.get("hostname").cloned().unwrap_or(""),
+                "memory": facts.get("memory").and_then(|m| m.parse::<u64>().ok()).unwrap_or(0),
+                "net": [],
+                "os": {
+                    "arch": facts.get("arch").cloned().unwrap_or(""),
+                    "family": family,
+                    "platform": platform,
+                    "version_str": version_str,
+                    "version_maj": version.next().unwrap_or(0),
+                    "version_min": version.next().unwrap_or(0),
+                    "version_patch": version.next().unwrap_or(0),
+                },
+            }
+        }))
+    }
+}
+
+fn session(host: &Host) -> Result<&SshSession> {
+    host.ssh_session().ok_or(Error::HostDisconnected)
+}
+
+fn quote_path<P: AsRef<Path>>(path: P) -> Result<String> {
+    match path.as_ref().to_str() {
+        Some(p) => Ok(quote(p)),
+        None => Err(Error::Generic(format!("Path is not valid UTF-8: {}", path.as_ref().display()))),
+    }
+}
+
+/// Run a command, returning an error if it fails.
+fn run(host: &mut Host, cmd: &str) -> Result<()> {
+    let result = try!(try!(session(host)).exec(cmd, None));
+
+    if result.exit_code == 0 {
+        Ok(())
+    } else {
+        Err(Error::Generic(format!("Command `{}` failed with error: {}", cmd, result.stderr)))
+    }
+}
+
+/// Run a command, returning whether it succeeded.
+fn test(host: &mut Host, cmd: &str) -> Result<bool> {
+    Ok(try!(try!(session(host)).exec(cmd, None)).exit_code == 0)
+}
+
+/// Run a command and return its output, or an error if it fails.
+fn output(host: &mut Host, cmd: &str) -> Result<String> {
+    let result = try!(try!(session(host)).exec(cmd, None));
+
+    if result.exit_code == 0 {
+        Ok(result.stdout)
+    } else {
+        Err(Error::Generic(format!("Command `{}` failed with error: {}", cmd, result.stderr)))
+    }
+}
+
+fn get_owner<P: AsRef<Path>>(host: &mut Host, path: P) -> Result<FileOwner> {
+    // GNU stat uses `-c`, whereas BSD stat uses `-f`
+    let path = try!(quote_path(path));
+    let out = try!(output(host, &format!("stat -c '%U %u %G %g' {0} 2>/dev/null || stat -f '%Su %u %Sg %g' {0}", path)));
+    let parts: Vec<&str> = out.split_whitespace().collect();
+
+    if parts.len() != 4 {
+        return Err(Error::Generic(format!("Unexpected output from stat: {}", out)));
+    }
+
+    Ok(FileOwner {
+        user_name: parts[0].into(),
+        user_uid: try!(parts[1].parse()),
+        group_name: parts[2].into(),
+        group_gid: try!(parts[3].parse()),
+    })
+}
+
+fn set_owner<P: AsRef<Path>>(host: &mut Host, path: P, user: &str, group: &str) -> Result<()> {
+    run(host, &format!("chown {} {}", quote(&format!("{}:{}", user, group)), try!(quote_path(path))))
+}
+
+fn get_mode<P: AsRef<Path>>(host: &mut Host, path: P) -> Result<u16> {
+    let path = try!(quote_path(path));
+    Ok(try!(try!(output(host, &format!("stat -c %a {0} 2>/dev/null || stat -f %Lp {0}", path))).parse()))
+}
+
+fn set_mode<P: AsRef<Path>>(host: &mut Host, path: P, mode: u16) -> Result<()> {
+    run(host, &format!("chmod {} {}", mode, try!(quote_path(path))))
+}
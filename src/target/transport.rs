@@ -0,0 +1,265 @@
+// Copyright 2015-2017 Intecture Developers. See the COPYRIGHT file at the
+// top-level directory of this distribution and at
+// https://intecture.io/COPYRIGHT.
+//
+// Licensed under the Mozilla Public License 2.0 <LICENSE or
+// https://www.tldrlegal.com/l/mpl-2.0>. This file may not be copied,
+// modified, or distributed except according to those terms.
+
+use command::{CommandResult, CommandStream, CommandTarget};
+use directory::DirectoryTarget;
+use error::Result;
+use file::{FileTarget, FileOwner};
+use host::{Host, TelemetryTarget, Transport};
+use package::PackageTarget;
+use package::providers::Providers;
+use serde_json::Value;
+use service::ServiceTarget;
+use std::path::Path;
+use super::{LocalTarget, Target};
+#[cfg(feature = "remote-run")]
+use super::agent::AgentTarget;
+use super::ssh::SshTarget;
+
+//
+// Command
+//
+
+impl CommandTarget for Target {
+    fn exec(host: &mut Host, cmd: &str, timeout: Option<u64>) -> Result<CommandResult> {
+        match host.transport() {
+            Transport::Local => LocalTarget::exec(host, cmd, timeout),
+            #[cfg(feature = "remote-run")]
+            Transport::Agent => AgentTarget::exec(host, cmd, timeout),
+            Transport::Ssh => SshTarget::exec(host, cmd, timeout),
+        }
+    }
+
+    fn exec_stream(host: &mut Host, cmd: &str, timeout: Option<u64>, handler: &mut FnMut(CommandStream, &[u8])) -> Result<CommandResult> {
+        match host.transport() {
+            Transport::Local => LocalTarget::exec_stream(host, cmd, timeout, handler),
+            #[cfg(feature = "remote-run")]
+            Transport::Agent => AgentTarget::exec_stream(host, cmd, timeout, handler),
+            Transport::Ssh => SshTarget::exec_stream(host, cmd, timeout, handler),
+        }
+    }
+}
+
+//
+// Directory
+//
+
+impl<P: AsRef<Path>> DirectoryTarget<P> for Target {
+    fn directory_is_directory(host: &mut Host, path: P) -> Result<bool> {
+        match host.transport() {
+            Transport::Local => LocalTarget::directory_is_directory(host, path),
+            #[cfg(feature = "remote-run")]
+            Transport::Agent => AgentTarget::directory_is_directory(host, path),
+            Transport::Ssh => SshTarget::directory_is_directory(host, path),
+        }
+    }
+
+    fn directory_exists(host: &mut Host, path: P) -> Result<bool> {
+        match host.transport() {
+            Transport::Local => LocalTarget::directory_exists(host, path),
+            #[cfg(feature = "remote-run")]
+            Transport::Agent => AgentTarget::directory_exists(host, path),
+            Transport::Ssh => SshTarget::directory_exists(host, path),
+        }
+    }
+
+    fn directory_create(host: &mut Host, path: P, recursive: bool) -> Result<()> {
+        match host.transport() {
+            Transport::Local => LocalTarget::directory_create(host, path, recursive),
+            #[cfg(feature = "remote-run")]
+            Transport::Agent => AgentTarget::directory_create(host, path, recursive),
+            Transport::Ssh => SshTarget::directory_create(host, path, recursive),
+        }
+    }
+
+    fn directory_delete(host: &mut Host, path: P, recursive: bool) -> Result<()> {
+        match host.transport() {
+            Transport::Local => LocalTarget::directory_delete(host, path, recursive),
+            #[cfg(feature = "remote-run")]
+            Transport::Agent => AgentTarget::directory_delete(host, path, recursive),
+            Transport::Ssh => SshTarget::directory_delete(host, path, recursive),
+        }
+    }
+
+    fn directory_mv(host: &mut Host, path: P, new_path: P) -> Result<()> {
+        match host.transport() {
+            Transport::Local => LocalTarget::directory_mv(host, path, new_path),
+            #[cfg(feature = "remote-run")]
+            Transport::Agent => AgentTarget::directory_mv(host, path, new_path),
+            Transport::Ssh => SshTarget::directory_mv(host, path, new_path),
+        }
+    }
+
+    fn directory_get_owner(host: &mut Host, path: P) -> Result<FileOwner> {
+        match host.transport() {
+            Transport::Local => LocalTarget::directory_get_owner(host, path),
+            #[cfg(feature = "remote-run")]
+            Transport::Agent => AgentTarget::directory_get_owner(host, path),
+            Transport::Ssh => SshTarget::directory_get_owner(host, path),
+        }
+    }
+
+    fn directory_set_owner(host: &mut Host, path: P, user: &str, group: &str) -> Result<()> {
+        match host.transport() {
+            Transport::Local => LocalTarget::directory_set_owner(host, path, user, group),
+            #[cfg(feature = "remote-run")]
+            Transport::Agent => AgentTarget::directory_set_owner(host, path, user, group),
+            Transport::Ssh => SshTarget::directory_set_owner(host, path, user, group),
+        }
+    }
+
+    fn directory_get_mode(host: &mut Host, path: P) -> Result<u16> {
+        match host.transport() {
+            Transport::Local => LocalTarget::directory_get_mode(host, path),
+            #[cfg(feature = "remote-run")]
+            Transport::Agent => AgentTarget::directory_get_mode(host, path),
+            Transport::Ssh => SshTarget::directory_get_mode(host, path),
+        }
+    }
+
+    fn directory_set_mode(host: &mut Host, path: P, mode: u16) -> Result<()> {
+        match host.transport() {
+            Transport::Local => LocalTarget::directory_set_mode(host, path, mode),
+            #[cfg(feature = "remote-run")]
+            Transport::Agent => AgentTarget::directory_set_mode(host, path, mode),
+            Transport::Ssh => SshTarget::directory_set_mode(host, path, mode),
+        }
+    }
+}
+
+//
+// File
+//
+
+impl<P: AsRef<Path>> FileTarget<P> for Target {
+    fn file_is_file(host: &mut Host, path: P) -> Result<bool> {
+        match host.transport() {
+            Transport::Local => LocalTarget::file_is_file(host, path),
+            #[cfg(feature = "remote-run")]
+            Transport::Agent => AgentTarget::file_is_file(host, path),
+            Transport::Ssh => SshTarget::file_is_file(host, path),
+        }
+    }
+
+    fn file_exists(host: &mut Host, path: P) -> Result<bool> {
+        match host.transport() {
+            Transport::Local => LocalTarget::file_exists(host, path),
+            #[cfg(feature = "remote-run")]
+            Transport::Agent => AgentTarget::file_exists(host, path),
+            Transport::Ssh => SshTarget::file_exists(host, path),
+        }
+    }
+
+    fn file_delete(host: &mut Host, path: P) -> Result<()> {
+        match host.transport() {
+            Transport::Local => LocalTarget::file_delete(host, path),
+            #[cfg(feature = "remote-run")]
+            Transport::Agent => AgentTarget::file_delete(host, path),
+            Transport::Ssh => SshTarget::file_delete(host, path),
+        }
+    }
+
+    fn file_mv(host: &mut Host, path: P, new_path: P) -> Result<()> {
+        match host.transport() {
+            Transport::Local => LocalTarget::file_mv(host, path, new_path),
+            #[cfg(feature = "remote-run")]
+            Transport::Agent => AgentTarget::file_mv(host, path, new_path),
+            Transport::Ssh => SshTarget::file_mv(host, path, new_path),
+        }
+    }
+
+    fn file_copy(host: &mut Host, path: P, new_path: P) -> Result<()> {
+        match host.transport() {
+            Transport::Local => LocalTarget::file_copy(host, path, new_path),
+            #[cfg(feature = "remote-run")]
+            Transport::Agent => AgentTarget::file_copy(host, path, new_path),
+            Transport::Ssh => SshTarget::file_copy(host, path, new_path),
+        }
+    }
+
+    fn file_get_owner(host: &mut Host, path: P) -> Result<FileOwner> {
+        match host.transport() {
+            Transport::Local => LocalTarget::file_get_owner(host, path),
+            #[cfg(feature = "remote-run")]
+            Transport::Agent => AgentTarget::file_get_owner(host, path),
+            Transport::Ssh => SshTarget::file_get_owner(host, path),
+        }
+    }
+
+    fn file_set_owner(host: &mut Host, path: P, user: &str, group: &str) -> Result<()> {
+        match host.transport() {
+            Transport::Local => LocalTarget::file_set_owner(host, path, user, group),
+            #[cfg(feature = "remote-run")]
+            Transport::Agent => AgentTarget::file_set_owner(host, path, user, group),
+            Transport::Ssh => SshTarget::file_set_owner(host, path, user, group),
+        }
+    }
+
+    fn file_get_mode(host: &mut Host, path: P) -> Result<u16> {
+        match host.transport() {
+            Transport::Local => LocalTarget::file_get_mode(host, path),
+            #[cfg(feature = "remote-run")]
+            Transport::Agent => AgentTarget::file_get_mode(host, path),
+            Transport::Ssh => SshTarget::file_get_mode(host, path),
+        }
+    }
+
+    fn file_set_mode(host: &mut Host, path: P, mode: u16) -> Result<()> {
+        match host.transport() {
+            Transport::Local => LocalTarget::file_set_mode(host, path, mode),
+            #[cfg(feature = "remote-run")]
+            Transport::Agent => AgentTarget::file_set_mode(host, path, mode),
+            Transport::Ssh => SshTarget::file_set_mode(host, path, mode),
+        }
+    }
+}
+
+//
+// Package
+//
+
+impl PackageTarget for Target {
+    fn default_provider(host: &mut Host) -> Result<Providers> {
+        match host.transport() {
+            Transport::Local => LocalTarget::default_provider(host),
+            #[cfg(feature = "remote-run")]
+            Transport::Agent => AgentTarget::default_provider(host),
+            Transport::Ssh => SshTarget::default_provider(host),
+        }
+    }
+}
+
+//
+// Service
+//
+
+impl ServiceTarget for Target {
+    fn service_action(host: &mut Host, name: &str, action: &str) -> Result<Option<CommandResult>> {
+        match host.transport() {
+            Transport::Local => LocalTarget::service_action(host, name, action),
+            #[cfg(feature = "remote-run")]
+            Transport::Agent => AgentTarget::service_action(host, name, action),
+            Transport::Ssh => SshTarget::service_action(host, name, action),
+        }
+    }
+}
+
+//
+// Telemetry
+//
+
+impl TelemetryTarget for Target {
+    fn telemetry_init(host: &mut Host) -> Result<Value> {
+        match host.transport() {
+            Transport::Local => LocalTarget::telemetry_init(host),
+            #[cfg(feature = "remote-run")]
+            Transport::Agent => AgentTarget::telemetry_init(host),
+            Transport::Ssh => SshTarget::telemetry_init(host),
+        }
+    }
+}